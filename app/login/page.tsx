@@ -1,11 +1,11 @@
 'use client'
 
-import { useState, useEffect } from 'react'
+import { useState, useEffect, type FormEvent } from 'react'
 import { useRouter } from 'next/navigation'
 import { useForm } from 'react-hook-form'
 import { zodResolver } from '@hookform/resolvers/zod'
 import * as z from 'zod'
-import { Eye, EyeOff, User, Lock, Loader2 } from 'lucide-react'
+import { Eye, EyeOff, User, Lock, Loader2, ShieldCheck } from 'lucide-react'
 
 import { Button } from '@/components/ui/button'
 import { Card, CardContent, CardDescription, CardFooter, CardHeader, CardTitle } from '@/components/ui/card'
@@ -48,8 +48,9 @@ const getSavedBoolean = (key: string): boolean => {
 
 export default function LoginPage() {
   const router = useRouter()
-  const { state, login, clearError } = useAuth()
+  const { state, login, verifySecondFactor, cancelSecondFactor, clearError } = useAuth()
   const [showPassword, setShowPassword] = useState(false)
+  const [totpCode, setTotpCode] = useState('')
 
   // 确保样式正确加载
   useEffect(() => {
@@ -129,8 +130,26 @@ export default function LoginPage() {
     }
   }
 
+  // 提交动态验证码或恢复码
+  const onSubmitCode = async (event: FormEvent) => {
+    event.preventDefault()
+    if (!totpCode.trim()) {
+      return
+    }
+    const success = await verifySecondFactor(totpCode.trim())
+    if (!success) {
+      setTotpCode('')
+    }
+  }
+
+  // 返回密码登录
+  const onCancelCode = () => {
+    setTotpCode('')
+    cancelSecondFactor()
+  }
+
   // 如果正在检查会话，显示加载状态
-  if (state.loading && !state.error) {
+  if (state.loading && !state.error && !state.secondFactor) {
     return (
       <div className="min-h-screen flex items-center justify-center bg-gradient-to-br from-blue-50 via-indigo-50 to-purple-50">
         <Card className="w-[380px]">
@@ -212,6 +231,67 @@ export default function LoginPage() {
               </CardDescription>
             </CardHeader>
 
+            {state.secondFactor ? (
+              <form onSubmit={onSubmitCode}>
+                <CardContent className="space-y-4">
+                  {/* 错误提示 */}
+                  {state.error && (
+                    <Alert variant="destructive">
+                      <AlertDescription>{state.error}</AlertDescription>
+                    </Alert>
+                  )}
+
+                  {/* 动态验证码输入框 */}
+                  <div className="space-y-2">
+                    <label htmlFor="totp-code" className="text-sm font-medium text-gray-700 dark:text-gray-300">
+                      动态验证码
+                    </label>
+                    <div className="relative">
+                      <ShieldCheck className="absolute left-3 top-3 h-4 w-4 text-gray-400" />
+                      <Input
+                        id="totp-code"
+                        placeholder="请输入6位验证码或恢复码"
+                        autoComplete="one-time-code"
+                        autoFocus
+                        className="pl-10 h-12 border-gray-200 dark:border-gray-700 focus:border-blue-500 dark:focus:border-blue-400"
+                        disabled={state.loading}
+                        value={totpCode}
+                        onChange={(event) => setTotpCode(event.target.value)}
+                      />
+                    </div>
+                    <p className="text-xs text-gray-500 dark:text-gray-400">
+                      该账户已开启两步验证，请输入身份验证器中的验证码
+                    </p>
+                  </div>
+                </CardContent>
+
+                <CardFooter className="pt-4 flex flex-col space-y-2">
+                  <Button
+                    type="submit"
+                    className="w-full h-12 bg-gradient-to-r from-blue-600 to-purple-600 hover:from-blue-700 hover:to-purple-700 text-white font-medium rounded-lg transition-all duration-200"
+                    disabled={state.loading || !totpCode.trim()}
+                  >
+                    {state.loading ? (
+                      <>
+                        <Loader2 className="mr-2 h-4 w-4 animate-spin" />
+                        验证中...
+                      </>
+                    ) : (
+                      '验证'
+                    )}
+                  </Button>
+                  <Button
+                    type="button"
+                    variant="ghost"
+                    className="w-full"
+                    onClick={onCancelCode}
+                    disabled={state.loading}
+                  >
+                    返回密码登录
+                  </Button>
+                </CardFooter>
+              </form>
+            ) : (
             <Form {...form}>
               <form onSubmit={form.handleSubmit(onSubmit)}>
                 <CardContent className="space-y-4">
@@ -353,6 +433,7 @@ export default function LoginPage() {
                 </CardFooter>
               </form>
             </Form>
+            )}
           </Card>
         </div>
       </div>
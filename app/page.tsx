@@ -5,6 +5,7 @@ import { Sidebar } from "@/components/sidebar"
 import { ToolGrid } from "@/components/tool-grid"
 import { StatsCards } from "@/components/stats-cards"
 import { AuthGuard } from "@/components/auth/auth-guard"
+import { ToolCatalogProvider } from "@/lib/tool-catalog"
 import { useState } from "react"
 
 export default function HomePage() {
@@ -22,6 +23,7 @@ export default function HomePage() {
 
   return (
     <AuthGuard>
+      <ToolCatalogProvider>
       <div className="h-screen flex flex-col bg-gradient-to-br from-slate-50 via-blue-50/30 to-indigo-50/50 dark:from-gray-900 dark:via-gray-800/30 dark:to-gray-900/50">
       <Header searchQuery={searchQuery} onSearchChange={handleSearchChange} />
       <div className="flex flex-1 overflow-hidden">
@@ -76,6 +78,7 @@ export default function HomePage() {
         </main>
       </div>
     </div>
+      </ToolCatalogProvider>
     </AuthGuard>
   )
 }
@@ -69,6 +69,13 @@ interface UserAnalytics {
   toolUsageDetails: ToolUsageDetail[]
 }
 
+interface UserAnalyticsPage {
+  items: UserAnalytics[]
+  total: number
+  page: number
+  pageSize: number
+}
+
 interface DailyGrowth {
   date: string
   newUsers: number
@@ -98,17 +105,17 @@ export function MongoDBDashboard() {
       setLoading(true)
       setError(null)
       
-      const [systemData, userData] = await Promise.all([
+      const [systemData, userData]: [SystemAnalytics, UserAnalyticsPage] = await Promise.all([
         apiCall('get_system_analytics'),
-        apiCall('get_user_analytics', { limit: 50, includeInactive: true }) // 增加限制到50个用户，包括非活跃用户
+        apiCall('get_user_analytics', { query: { pageSize: 50, status: 'all' } }) // 第一页50个用户，包括非活跃用户
       ])
 
       setAnalytics(systemData)
-      setUserAnalytics(userData)
+      setUserAnalytics(userData.items)
       
       // 调试信息：显示实际获取的用户数量
-      console.log(`🔍 [MongoDB Dashboard] 成功获取 ${userData.length} 个用户分析数据`)
-      console.log('用户列表:', userData.map(u => u.username).join(', '))
+      console.log(`🔍 [MongoDB Dashboard] 成功获取 ${userData.items.length} / ${userData.total} 个用户分析数据`)
+      console.log('用户列表:', userData.items.map(u => u.username).join(', '))
     } catch (error: any) {
       console.error('加载MongoDB仪表板数据失败:', error)
       setError(error.message || '数据加载失败')
@@ -6,10 +6,12 @@ import { Layers, TrendingUp, Palette, ShoppingCart, Users, MessageCircle, Star,
 import { Badge } from "@/components/ui/badge"
 import { WeatherWidget } from "@/components/weather-widget"
 import { getCategoryStats, getToolsByCategory } from "@/lib/tool-data"
+import { useToolCatalog } from "@/lib/tool-catalog"
+import { Tool } from "@/types/tools"
 
 // 动态获取分类数据
-const getCategoriesWithCounts = () => {
-  const categoryStats = getCategoryStats()
+const getCategoriesWithCounts = (tools: Tool[]) => {
+  const categoryStats = getCategoryStats(tools)
 
   return [
     {
@@ -23,7 +25,7 @@ const getCategoriesWithCounts = () => {
       id: "operations",
       name: "运营工具",
       icon: TrendingUp,
-      count: getToolsByCategory("运营工具").length,
+      count: getToolsByCategory(tools, "运营工具").length,
       color: "from-blue-300 to-blue-400",
       hot: true
     },
@@ -31,28 +33,28 @@ const getCategoriesWithCounts = () => {
       id: "design",
       name: "美工工具",
       icon: Palette,
-      count: getToolsByCategory("美工工具").length,
+      count: getToolsByCategory(tools, "美工工具").length,
       color: "from-purple-300 to-purple-400"
     },
     {
       id: "sales",
       name: "销售工具",
       icon: ShoppingCart,
-      count: getToolsByCategory("销售工具").length,
+      count: getToolsByCategory(tools, "销售工具").length,
       color: "from-emerald-300 to-emerald-400"
     },
     {
       id: "hr",
       name: "人事工具",
       icon: Users,
-      count: getToolsByCategory("人事工具").length,
+      count: getToolsByCategory(tools, "人事工具").length,
       color: "from-amber-300 to-amber-400"
     },
     {
       id: "service",
       name: "客服工具",
       icon: MessageCircle,
-      count: getToolsByCategory("客服工具").length,
+      count: getToolsByCategory(tools, "客服工具").length,
       color: "from-rose-300 to-rose-400",
       new: true
     },
@@ -60,7 +62,7 @@ const getCategoriesWithCounts = () => {
       id: "website",
       name: "公司官网",
       icon: Globe,
-      count: getToolsByCategory("公司官网").length,
+      count: getToolsByCategory(tools, "公司官网").length,
       color: "from-indigo-300 to-indigo-400",
       featured: true
     },
@@ -74,7 +76,8 @@ interface SidebarProps {
 
 export function Sidebar({ activeCategory, onCategoryChange }: SidebarProps) {
   // 获取动态分类数据
-  const categories = getCategoriesWithCounts()
+  const { tools } = useToolCatalog()
+  const categories = getCategoriesWithCounts(tools)
 
   return (
     <aside className="w-72 bg-white/80 dark:bg-gray-800/80 backdrop-blur-md border-r border-gray-200/50 dark:border-gray-700/50 shadow-lg">
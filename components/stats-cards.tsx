@@ -1,6 +1,7 @@
 import { Card, CardContent } from "@/components/ui/card"
 import { TrendingUp, Users, Zap, Plus } from "lucide-react"
-import { toolsData, getCategoryStats } from "@/lib/tool-data"
+import { getCategoryStats } from "@/lib/tool-data"
+import { useToolCatalog } from "@/lib/tool-catalog"
 import { useState, useEffect } from "react"
 import { apiCall } from "@/lib/tauri-api"
 
@@ -19,7 +20,8 @@ interface SystemStats {
 }
 
 export function StatsCards() {
-  const categoryStats = getCategoryStats()
+  const { tools } = useToolCatalog()
+  const categoryStats = getCategoryStats(tools)
   const [systemStats, setSystemStats] = useState<SystemStats | null>(null)
   const [loading, setLoading] = useState(true)
 
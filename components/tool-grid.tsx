@@ -8,7 +8,8 @@ import {
   Zap,
   AlertCircle,
 } from "lucide-react"
-import { toolsData } from "@/lib/tool-data"
+import { useToolCatalog } from "@/lib/tool-catalog"
+import { Tool } from "@/types/tools"
 import { ToolLauncher } from "@/utils/toolLauncher"
 import { WebViewModal } from "@/components/web-view-modal"
 import { useAuth } from "@/lib/auth/auth-context"
@@ -22,17 +23,18 @@ interface ToolGridProps {
 
 export function ToolGrid({ category = "全部工具", searchQuery = "" }: ToolGridProps) {
   const { state } = useAuth()
+  const { tools, loading, error } = useToolCatalog()
   const [launchingTool, setLaunchingTool] = useState<number | null>(null)
   const [webViewModal, setWebViewModal] = useState<{
     isOpen: boolean
-    tool: typeof toolsData[0] | null
+    tool: Tool | null
   }>({
     isOpen: false,
     tool: null
   })
 
   // 筛选工具
-  const filteredTools = toolsData.filter(tool => {
+  const filteredTools = tools.filter(tool => {
     // 如果有搜索查询，优先按搜索结果筛选
     if (searchQuery.trim()) {
       const query = searchQuery.toLowerCase().trim()
@@ -48,7 +50,7 @@ export function ToolGrid({ category = "全部工具", searchQuery = "" }: ToolGr
     return category === "全部工具" || tool.category === category
   })
 
-  const handleLaunchTool = async (tool: typeof toolsData[0]) => {
+  const handleLaunchTool = async (tool: Tool) => {
     console.log(`🎯 [前端] 用户点击工具: ${tool.name} (ID: ${tool.id})`)
     console.log(`🎯 [前端] 工具类型: ${tool.toolType}`)
     console.log(`🎯 [前端] 当前用户状态:`, state.user)
@@ -56,21 +58,8 @@ export function ToolGrid({ category = "全部工具", searchQuery = "" }: ToolGr
     // 记录工具点击统计
     if (state.user) {
       try {
-        console.log(`🎯 [前端] 准备调用 track_user_activity API`)
-        console.log(`🎯 [前端] 调用参数:`, {
-          userId: state.user.id,
-          activityType: 'tool_click',
-          toolId: tool.id,
-          toolName: tool.name,
-          duration: null
-        })
-
-        const result = await apiCall('track_user_activity', {
-          userId: state.user.id,
-          activityType: 'tool_click',
-          toolId: tool.id,
-          toolName: tool.name,
-          duration: null
+        const result = await apiCall('record_activity', {
+          event: { type: 'tool_opened', toolId: tool.id }
         })
         console.log(`✅ [前端] 工具点击记录成功: ${tool.name} (ID: ${tool.id})`, result)
       } catch (error) {
@@ -128,6 +117,25 @@ export function ToolGrid({ category = "全部工具", searchQuery = "" }: ToolGr
 
   // handleAddToFavorites 函数已移除
 
+  if (loading && tools.length === 0) {
+    return (
+      <div className="flex items-center justify-center py-12 text-gray-500 dark:text-gray-400">
+        <div className="w-5 h-5 mr-3 animate-spin rounded-full border-2 border-gray-300 border-t-blue-500" />
+        正在加载工具目录...
+      </div>
+    )
+  }
+
+  if (error && tools.length === 0) {
+    return (
+      <div className="flex flex-col items-center justify-center py-12 text-center">
+        <AlertCircle className="w-12 h-12 text-gray-400 dark:text-gray-500 mb-4" />
+        <h3 className="text-lg font-semibold text-gray-900 dark:text-gray-100 mb-2">工具目录加载失败</h3>
+        <p className="text-gray-500 dark:text-gray-400">{error}</p>
+      </div>
+    )
+  }
+
   if (filteredTools.length === 0) {
     return (
       <div className="flex flex-col items-center justify-center py-12 text-center">
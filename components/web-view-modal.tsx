@@ -102,12 +102,8 @@ export function WebViewModal({ isOpen, onClose, tool }: WebViewModalProps) {
       const duration = Math.floor((Date.now() - startTime) / 1000) // 计算使用时长（秒）
       if (duration > 0) { // 只记录有效的使用时长
         try {
-          await apiCall('track_user_activity', {
-            userId: state.user.id,
-            activityType: 'tool_usage',
-            toolId: tool.id,
-            toolName: tool.name,
-            duration: duration
+          await apiCall('record_activity', {
+            event: { type: 'tool_closed', toolId: tool.id, durationSecs: duration }
           })
           console.log(`记录工具使用时长: ${tool.name} - ${duration}秒`)
         } catch (error) {
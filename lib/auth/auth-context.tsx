@@ -56,12 +56,20 @@ export interface User {
   totalUsageTime: number
 }
 
+// 密码验证通过后等待输入动态验证码的登录
+export interface SecondFactorChallenge {
+  challengeId: string
+  rememberMe: boolean
+  autoLogin: boolean
+}
+
 // 认证状态类型
 interface AuthState {
   user: User | null
   isAuthenticated: boolean
   loading: boolean
   error: string | null
+  secondFactor: SecondFactorChallenge | null
 }
 
 // 认证动作类型
@@ -69,6 +77,8 @@ type AuthAction =
   | { type: 'LOGIN_START' }
   | { type: 'LOGIN_SUCCESS'; payload: User }
   | { type: 'LOGIN_FAILURE'; payload: string }
+  | { type: 'SECOND_FACTOR_REQUIRED'; payload: SecondFactorChallenge }
+  | { type: 'SECOND_FACTOR_CANCEL' }
   | { type: 'LOGOUT' }
   | { type: 'SET_LOADING'; payload: boolean }
   | { type: 'CLEAR_ERROR' }
@@ -79,6 +89,8 @@ type AuthAction =
 interface AuthContextType {
   state: AuthState
   login: (username: string, password: string, rememberMe?: boolean, autoLogin?: boolean) => Promise<boolean>
+  verifySecondFactor: (code: string) => Promise<boolean>
+  cancelSecondFactor: () => void
   logout: () => Promise<void>
   checkSession: () => Promise<void>
   clearError: () => void
@@ -90,6 +102,7 @@ const initialState: AuthState = {
   isAuthenticated: false,
   loading: true, // 初始加载状态为true，用于检查会话
   error: null,
+  secondFactor: null,
 }
 
 // Reducer函数
@@ -108,6 +121,7 @@ function authReducer(state: AuthState, action: AuthAction): AuthState {
         isAuthenticated: true,
         loading: false,
         error: null,
+        secondFactor: null,
       }
     case 'LOGIN_FAILURE':
       return {
@@ -117,6 +131,19 @@ function authReducer(state: AuthState, action: AuthAction): AuthState {
         loading: false,
         error: action.payload,
       }
+    case 'SECOND_FACTOR_REQUIRED':
+      return {
+        ...state,
+        loading: false,
+        error: null,
+        secondFactor: action.payload,
+      }
+    case 'SECOND_FACTOR_CANCEL':
+      return {
+        ...state,
+        error: null,
+        secondFactor: null,
+      }
     case 'LOGOUT':
       return {
         ...state,
@@ -124,6 +151,7 @@ function authReducer(state: AuthState, action: AuthAction): AuthState {
         isAuthenticated: false,
         loading: false,
         error: null,
+        secondFactor: null,
       }
     case 'SET_LOADING':
       return {
@@ -163,6 +191,27 @@ const AuthContext = createContext<AuthContextType | undefined>(undefined)
 export function AuthProvider({ children }: { children: ReactNode }) {
   const [state, dispatch] = useReducer(authReducer, initialState)
 
+  // 登录完成：更新状态并保存Token
+  const completeLogin = (loginResponse: any, rememberMe: boolean, autoLogin: boolean) => {
+    // 提取用户信息
+    const user = loginResponse.user || loginResponse
+
+    dispatch({ type: 'LOGIN_SUCCESS', payload: user })
+
+    // 保存Token到本地存储
+    if (rememberMe && loginResponse.rememberMeToken) {
+      saveToken(REMEMBER_ME_TOKEN_KEY, loginResponse.rememberMeToken)
+      console.log('✅ 记住我Token已保存')
+    }
+
+    if (autoLogin && loginResponse.autoLoginToken) {
+      saveToken(AUTO_LOGIN_TOKEN_KEY, loginResponse.autoLoginToken)
+      console.log('✅ 自动登录Token已保存')
+    }
+
+    toast.success(`欢迎回来，${user.username}！`)
+  }
+
   // 登录函数
   const login = async (
     username: string,
@@ -180,36 +229,17 @@ export function AuthProvider({ children }: { children: ReactNode }) {
         autoLogin,
       })
 
-      // 提取用户信息
-      const user = loginResponse.user || loginResponse
-
-      dispatch({ type: 'LOGIN_SUCCESS', payload: user })
-
-      // 保存Token到本地存储
-      if (rememberMe && loginResponse.rememberMeToken) {
-        saveToken(REMEMBER_ME_TOKEN_KEY, loginResponse.rememberMeToken)
-        console.log('✅ 记住我Token已保存')
-      }
-
-      if (autoLogin && loginResponse.autoLoginToken) {
-        saveToken(AUTO_LOGIN_TOKEN_KEY, loginResponse.autoLoginToken)
-        console.log('✅ 自动登录Token已保存')
-      }
-
-      // 记录登录成功日志
-      try {
-        await apiCall('track_user_activity', {
-          userId: user.id,
-          activityType: 'login',
-          toolId: null,
-          toolName: null,
-          duration: null,
+      // 已开启两步验证：等待输入动态验证码
+      if (loginResponse.secondFactorRequired) {
+        dispatch({
+          type: 'SECOND_FACTOR_REQUIRED',
+          payload: { challengeId: loginResponse.challengeId, rememberMe, autoLogin },
         })
-      } catch (trackError) {
-        console.log('记录登录日志失败:', trackError)
+        toast.info('请输入身份验证器中的动态验证码')
+        return false
       }
 
-      toast.success(`欢迎回来，${user.username}！`)
+      completeLogin(loginResponse, rememberMe, autoLogin)
       return true
     } catch (error) {
       const errorMessage = error instanceof Error ? error.message : String(error)
@@ -219,21 +249,40 @@ export function AuthProvider({ children }: { children: ReactNode }) {
     }
   }
 
+  // 登录第二步：提交动态验证码或恢复码
+  const verifySecondFactor = async (code: string): Promise<boolean> => {
+    const challenge = state.secondFactor
+    if (!challenge) {
+      return false
+    }
+    dispatch({ type: 'LOGIN_START' })
+
+    try {
+      const loginResponse = await apiCall('verify_totp', {
+        challengeId: challenge.challengeId,
+        code,
+      })
+      completeLogin(loginResponse, challenge.rememberMe, challenge.autoLogin)
+      return true
+    } catch (error) {
+      const errorMessage = error instanceof Error ? error.message : String(error)
+      dispatch({ type: 'LOGIN_FAILURE', payload: errorMessage })
+      toast.error(errorMessage || '验证码错误')
+      return false
+    }
+  }
+
+  // 放弃两步验证，回到密码登录
+  const cancelSecondFactor = (): void => {
+    dispatch({ type: 'SECOND_FACTOR_CANCEL' })
+  }
+
   // 登出函数
   const logout = async (): Promise<void> => {
     try {
       if (state.user) {
         try {
-          await apiCall('logout', { userId: state.user.id })
-
-          // 记录登出日志
-          await apiCall('track_user_activity', {
-            userId: state.user.id,
-            activityType: 'logout',
-            toolId: null,
-            toolName: null,
-            duration: null,
-          })
+          await apiCall('logout')
         } catch (apiError) {
           console.log('后端登出失败:', apiError)
         }
@@ -263,6 +312,25 @@ export function AuthProvider({ children }: { children: ReactNode }) {
     }
   }
 
+  // 使用本地Token恢复会话：刷新Token每次使用后轮换，旧版JWT在到期前继续兼容
+  const resumeWithToken = async (key: string, tokenType: string): Promise<any> => {
+    const token = getToken(key)
+    if (!token) {
+      throw new Error('本地Token不存在')
+    }
+    if (token.split('.').length === 3) {
+      const response = await apiCall('verify_token_and_login', { token, tokenType })
+      return response.user
+    }
+
+    const response = await apiCall('refresh_session', { refreshToken: token, tokenType })
+    const rotatedToken = tokenType === 'auto_login' ? response.autoLoginToken : response.rememberMeToken
+    if (rotatedToken) {
+      saveToken(key, rotatedToken)
+    }
+    return response.user
+  }
+
   // 检查会话函数
   const checkSession = async (): Promise<void> => {
     try {
@@ -279,10 +347,7 @@ export function AuthProvider({ children }: { children: ReactNode }) {
     if (autoLoginToken) {
       try {
         console.log('🔄 尝试自动登录Token验证...')
-        const user = await apiCall('verify_token_and_login', {
-          token: autoLoginToken,
-          tokenType: 'auto_login'
-        })
+        const user = await resumeWithToken(AUTO_LOGIN_TOKEN_KEY, 'auto_login')
         dispatch({ type: 'CHECK_SESSION_SUCCESS', payload: user })
         console.log('✅ 自动登录成功')
         return
@@ -297,10 +362,7 @@ export function AuthProvider({ children }: { children: ReactNode }) {
     if (rememberMeToken) {
       try {
         console.log('🔄 尝试记住我Token验证...')
-        const user = await apiCall('verify_token_and_login', {
-          token: rememberMeToken,
-          tokenType: 'remember_me'
-        })
+        const user = await resumeWithToken(REMEMBER_ME_TOKEN_KEY, 'remember_me')
         dispatch({ type: 'CHECK_SESSION_SUCCESS', payload: user })
         console.log('✅ 记住我登录成功')
         return
@@ -328,6 +390,8 @@ export function AuthProvider({ children }: { children: ReactNode }) {
   const contextValue: AuthContextType = {
     state,
     login,
+    verifySecondFactor,
+    cancelSecondFactor,
     logout,
     checkSession,
     clearError,
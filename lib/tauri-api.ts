@@ -412,8 +412,11 @@ export const apiCall = async (command: string, args?: any): Promise<any> => {
     case 'get_system_analytics':
       return JSON.parse(JSON.stringify(mockData.systemAnalytics))
 
-    case 'get_user_analytics':
-      return JSON.parse(JSON.stringify(mockData.users))
+    case 'get_user_analytics': {
+      const pageSize = args?.query?.pageSize ?? 50
+      const items = JSON.parse(JSON.stringify(mockData.users)).slice(0, pageSize)
+      return { items, total: mockData.users.length, page: 1, pageSize }
+    }
 
     case 'toggle_user_status':
       // 模拟切换用户状态
@@ -478,9 +481,14 @@ export const apiCall = async (command: string, args?: any): Promise<any> => {
       })
       return { success: true }
 
+    case 'record_activity':
+      // 模拟记录活动事件
+      console.log(`📊 Mock activity event:`, args.event)
+      return null
+
     case 'logout':
       // 模拟用户登出
-      console.log(`👋 Mock user logout:`, args.userId)
+      console.log(`👋 Mock user logout`)
       return { success: true }
 
     case 'check_session':
@@ -0,0 +1,54 @@
+"use client"
+
+import React, { createContext, useContext, useEffect, useState, useCallback, ReactNode } from 'react'
+import { Tool } from '@/types/tools'
+import { apiCall } from '@/lib/tauri-api'
+import { ToolResponse, toTool } from '@/lib/tool-data'
+
+interface ToolCatalogContextType {
+  tools: Tool[]
+  loading: boolean
+  error: string | null
+  reload: () => Promise<void>
+}
+
+const ToolCatalogContext = createContext<ToolCatalogContextType | undefined>(undefined)
+
+// 启动时从后端加载当前用户可用的工具目录
+export function ToolCatalogProvider({ children }: { children: ReactNode }) {
+  const [tools, setTools] = useState<Tool[]>([])
+  const [loading, setLoading] = useState(true)
+  const [error, setError] = useState<string | null>(null)
+
+  const reload = useCallback(async () => {
+    setLoading(true)
+    try {
+      const catalog: ToolResponse[] = await apiCall('list_tools')
+      setTools(catalog.map(toTool))
+      setError(null)
+    } catch (err) {
+      console.error('加载工具目录失败:', err)
+      setError(err instanceof Error ? err.message : String(err))
+    } finally {
+      setLoading(false)
+    }
+  }, [])
+
+  useEffect(() => {
+    reload()
+  }, [reload])
+
+  return (
+    <ToolCatalogContext.Provider value={{ tools, loading, error, reload }}>
+      {children}
+    </ToolCatalogContext.Provider>
+  )
+}
+
+export function useToolCatalog(): ToolCatalogContextType {
+  const context = useContext(ToolCatalogContext)
+  if (context === undefined) {
+    throw new Error('useToolCatalog must be used within a ToolCatalogProvider')
+  }
+  return context
+}
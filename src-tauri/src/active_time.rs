@@ -0,0 +1,573 @@
+use mongodb::{
+    bson::{doc, oid::ObjectId, DateTime},
+    error::{ErrorKind, WriteFailure},
+    options::IndexOptions,
+    IndexModel,
+};
+use chrono_tz::Tz;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use crate::auth::{AppState, MongoManager};
+use crate::daily_stats;
+
+// 主窗口标签，主窗口在前台时只计入用户使用时长
+const MAIN_WINDOW_LABEL: &str = "main";
+
+// MongoDB 唯一索引冲突错误码
+const DUPLICATE_KEY_CODE: i32 = 11000;
+
+// 待写入数据库的使用时长（毫秒）
+#[derive(Debug, Default)]
+struct Pending {
+    user_id: Option<ObjectId>,
+    total_ms: u64,
+    tools_ms: HashMap<i32, u64>,
+}
+
+// 一次写入的使用时长（秒），不足一秒的部分留到下次。
+// flush_id 在取出时生成，写入失败后用同一个 ID 重试，已完成的累加不会重复
+#[derive(Debug)]
+pub struct UsageFlush {
+    pub flush_id: String,
+    pub user_id: ObjectId,
+    pub total_secs: i64,
+    pub tools_secs: Vec<(i32, i64)>,
+}
+
+// 每次写入的使用时长明细（集合 usage_time），用于按时间段统计
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UsageTimeRecord {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    pub id: Option<ObjectId>,
+    // 对应 UsageFlush 的 flush_id，重试时按此去重；旧记录没有该字段
+    #[serde(rename = "flushId", default, skip_serializing_if = "Option::is_none")]
+    pub flush_id: Option<String>,
+    #[serde(rename = "userId")]
+    pub user_id: ObjectId,
+    #[serde(rename = "recordedAt")]
+    pub recorded_at: DateTime,
+    #[serde(rename = "totalSecs")]
+    pub total_secs: i64,
+    #[serde(default)]
+    pub tools: Vec<ToolSecs>,
+    // 派生统计是否已全部更新
+    #[serde(rename = "countersApplied", default = "applied")]
+    pub counters_applied: bool,
+    // 已完成的派生统计更新，中途失败重试时跳过这些项
+    #[serde(rename = "countersDone", default, skip_serializing_if = "Vec::is_empty")]
+    pub counters_done: Vec<String>,
+}
+
+// 旧记录写入时已同时更新了统计
+fn applied() -> bool {
+    true
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ToolSecs {
+    #[serde(rename = "toolId")]
+    pub tool_id: i32,
+    pub secs: i64,
+}
+
+#[derive(Debug, Default)]
+struct TrackerState {
+    // 当前获得焦点的本应用窗口
+    focused: Option<String>,
+    // 工具窗口标签 -> 工具ID
+    tool_windows: HashMap<String, i32>,
+    last_sample: Option<Instant>,
+    pending: Pending,
+    // 写入失败等待重试的使用时长
+    failed: Vec<UsageFlush>,
+}
+
+// 前台使用时长统计：根据窗口焦点和系统空闲时间累计用户和各工具的实际使用时间
+#[derive(Debug, Default)]
+pub struct ActiveTimeTracker {
+    state: Mutex<TrackerState>,
+}
+
+impl ActiveTimeTracker {
+    fn lock(&self) -> std::sync::MutexGuard<'_, TrackerState> {
+        self.state.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    // 记录工具窗口对应的工具
+    pub fn register_tool_window(&self, label: &str, tool_id: i32) {
+        self.lock().tool_windows.insert(label.to_string(), tool_id);
+    }
+
+    pub fn set_focus(&self, label: &str, focused: bool) {
+        let mut state = self.lock();
+        if focused {
+            state.focused = Some(label.to_string());
+        } else if state.focused.as_deref() == Some(label) {
+            state.focused = None;
+        }
+    }
+
+    pub fn window_closed(&self, label: &str) {
+        let mut state = self.lock();
+        state.tool_windows.remove(label);
+        if state.focused.as_deref() == Some(label) {
+            state.focused = None;
+        }
+    }
+
+    // 采样一次：距上次采样的时间在窗口获得焦点且用户未空闲时计入当前用户；
+    // 用户变化时返回上一个用户尚未写入的时长
+    pub fn sample(
+        &self,
+        now: Instant,
+        user_id: Option<ObjectId>,
+        idle: Option<Duration>,
+        idle_after: Duration,
+        max_step: Duration,
+    ) -> Option<UsageFlush> {
+        let mut state = self.lock();
+
+        let previous = if state.pending.user_id != user_id {
+            let flushed = take_flush(&mut state.pending, true);
+            state.pending = Pending { user_id, ..Pending::default() };
+            flushed
+        } else {
+            None
+        };
+
+        // 休眠或任务延迟导致的长间隔最多按一个采样周期计算
+        let elapsed = state
+            .last_sample
+            .map(|last| now.saturating_duration_since(last).min(max_step))
+            .unwrap_or_default();
+        state.last_sample = Some(now);
+
+        let is_idle = idle.is_some_and(|idle| idle >= idle_after);
+        if user_id.is_none() || is_idle || elapsed.is_zero() {
+            return previous;
+        }
+
+        let tool_id = match state.focused.as_deref() {
+            Some(MAIN_WINDOW_LABEL) => None,
+            Some(label) => state.tool_windows.get(label).copied(),
+            None => return previous,
+        };
+
+        let elapsed_ms = elapsed.as_millis() as u64;
+        state.pending.total_ms += elapsed_ms;
+        if let Some(tool_id) = tool_id {
+            *state.pending.tools_ms.entry(tool_id).or_default() += elapsed_ms;
+        }
+        previous
+    }
+
+    // 取出当前用户已累计的整秒数
+    pub fn take(&self) -> Option<UsageFlush> {
+        take_flush(&mut self.lock().pending, false)
+    }
+
+    // 写入失败时保留原样（包括 flush_id），下次用同一个 ID 重试
+    pub fn retry_later(&self, usage: UsageFlush) {
+        self.lock().failed.push(usage);
+    }
+
+    // 取出等待重试的使用时长
+    pub fn take_failed(&self) -> Vec<UsageFlush> {
+        std::mem::take(&mut self.lock().failed)
+    }
+}
+
+// round_up 为 true 时连同不足一秒的部分一起取出（用户切换时）
+fn take_flush(pending: &mut Pending, round_up: bool) -> Option<UsageFlush> {
+    let user_id = pending.user_id?;
+    let to_secs = |ms: u64| if round_up { ms.div_ceil(1000) } else { ms / 1000 };
+
+    let total_secs = to_secs(pending.total_ms);
+    if total_secs == 0 {
+        return None;
+    }
+    pending.total_ms -= (total_secs * 1000).min(pending.total_ms);
+
+    let mut tools_secs = Vec::new();
+    for (tool_id, ms) in pending.tools_ms.iter_mut() {
+        let secs = to_secs(*ms);
+        if secs > 0 {
+            *ms -= (secs * 1000).min(*ms);
+            tools_secs.push((*tool_id, secs as i64));
+        }
+    }
+    pending.tools_ms.retain(|_, ms| *ms > 0);
+
+    Some(UsageFlush {
+        flush_id: uuid::Uuid::new_v4().to_string(),
+        user_id,
+        total_secs: total_secs as i64,
+        tools_secs,
+    })
+}
+
+pub async fn ensure_indexes(mongo: &MongoManager) -> Result<(), String> {
+    let indexes = [
+        IndexModel::builder()
+            .keys(doc! {"recordedAt": -1, "userId": 1})
+            .build(),
+        IndexModel::builder()
+            .keys(doc! {"flushId": 1})
+            .options(IndexOptions::builder().unique(true).sparse(true).build())
+            .build(),
+    ];
+    mongo.usage_time()
+        .create_indexes(indexes)
+        .await
+        .map_err(|e| format!("创建使用时长索引失败: {}", e))?;
+    Ok(())
+}
+
+// 使用时长派生的统计，每项单独累加
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum FlushStep {
+    UserTotal,
+    UserDay,
+    ToolUsage(i32),
+    ToolTotal(i32),
+    ToolDay(i32),
+}
+
+impl FlushStep {
+    fn key(self) -> String {
+        match self {
+            FlushStep::UserTotal => "user_total".to_string(),
+            FlushStep::UserDay => "user_day".to_string(),
+            FlushStep::ToolUsage(tool_id) => format!("tool_usage:{}", tool_id),
+            FlushStep::ToolTotal(tool_id) => format!("tool_total:{}", tool_id),
+            FlushStep::ToolDay(tool_id) => format!("tool_day:{}", tool_id),
+        }
+    }
+}
+
+// 保存使用时长明细，并累加到用户、工具目录、工具使用记录和每日统计。
+// 明细按 flushId 去重，每项累加成功后记入 countersDone，重试时跳过已完成的项
+pub async fn flush(mongo: &MongoManager, tz: Tz, usage: &UsageFlush) -> Result<(), String> {
+    let record = UsageTimeRecord {
+        id: None,
+        flush_id: Some(usage.flush_id.clone()),
+        user_id: usage.user_id,
+        recorded_at: DateTime::now(),
+        total_secs: usage.total_secs,
+        tools: usage.tools_secs
+            .iter()
+            .map(|(tool_id, secs)| ToolSecs { tool_id: *tool_id, secs: *secs })
+            .collect(),
+        counters_applied: false,
+        counters_done: Vec::new(),
+    };
+    let record = match mongo.usage_time().insert_one(&record).await {
+        Ok(_) => record,
+        // 上次写入明细后中途失败，按已保存的记录继续
+        Err(e) if matches!(*e.kind, ErrorKind::Write(WriteFailure::WriteError(ref err)) if err.code == DUPLICATE_KEY_CODE) => {
+            mongo.usage_time()
+                .find_one(doc! {"flushId": &usage.flush_id})
+                .await
+                .map_err(|e| format!("查询使用时长明细失败: {}", e))?
+                .ok_or("使用时长明细不存在")?
+        }
+        Err(e) => return Err(format!("保存使用时长明细失败: {}", e)),
+    };
+    if record.counters_applied {
+        return Ok(());
+    }
+    let now = record.recorded_at;
+
+    let mut steps = vec![FlushStep::UserTotal, FlushStep::UserDay];
+    for (tool_id, _) in &usage.tools_secs {
+        steps.extend([FlushStep::ToolUsage(*tool_id), FlushStep::ToolTotal(*tool_id), FlushStep::ToolDay(*tool_id)]);
+    }
+    let tool_secs = |tool_id: i32| usage.tools_secs.iter().find(|(id, _)| *id == tool_id).map_or(0, |(_, secs)| *secs);
+
+    for step in steps {
+        let key = step.key();
+        if record.counters_done.contains(&key) {
+            continue;
+        }
+        match step {
+            FlushStep::UserTotal => {
+                mongo.users()
+                    .update_one(
+                        doc! {"_id": usage.user_id},
+                        doc! {"$inc": {"totalUsageTime": usage.total_secs}},
+                    )
+                    .await
+                    .map_err(|e| format!("更新用户使用时长失败: {}", e))?;
+            }
+            FlushStep::UserDay => {
+                daily_stats::add(mongo, tz, usage.user_id, None, now, doc! {"usageSecs": usage.total_secs}).await?;
+            }
+            FlushStep::ToolUsage(tool_id) => {
+                // 停用或已删除的工具也要记录时长，不使用 canonical_tool_name 的校验
+                let tool_name = mongo.tools()
+                    .find_one(doc! {"toolId": tool_id})
+                    .await
+                    .map_err(|e| format!("查询工具失败: {}", e))?
+                    .map(|tool| tool.name)
+                    .unwrap_or_else(|| format!("工具{}", tool_id));
+
+                mongo.tool_usage()
+                    .update_one(
+                        doc! {"userId": usage.user_id, "toolId": tool_id},
+                        doc! {
+                            "$inc": {"totalUsageTime": tool_secs(tool_id)},
+                            "$set": {"lastUsedAt": DateTime::now()},
+                            "$setOnInsert": {"toolName": tool_name, "clickCount": 0}
+                        },
+                    )
+                    .upsert(true)
+                    .await
+                    .map_err(|e| format!("更新工具使用时长失败: {}", e))?;
+            }
+            FlushStep::ToolTotal(tool_id) => {
+                mongo.tools()
+                    .update_one(doc! {"toolId": tool_id}, doc! {"$inc": {"totalUsageTime": tool_secs(tool_id)}})
+                    .await
+                    .map_err(|e| format!("更新工具累计使用时长失败: {}", e))?;
+            }
+            FlushStep::ToolDay(tool_id) => {
+                daily_stats::add(mongo, tz, usage.user_id, Some(tool_id), now, doc! {"usageSecs": tool_secs(tool_id)}).await?;
+            }
+        }
+
+        mongo.usage_time()
+            .update_one(
+                doc! {"flushId": &usage.flush_id},
+                doc! {"$addToSet": {"countersDone": key}},
+            )
+            .await
+            .map_err(|e| format!("更新使用时长明细失败: {}", e))?;
+    }
+
+    mongo.usage_time()
+        .update_one(
+            doc! {"flushId": &usage.flush_id},
+            doc! {"$set": {"countersApplied": true}, "$unset": {"countersDone": ""}},
+        )
+        .await
+        .map_err(|e| format!("更新使用时长明细失败: {}", e))?;
+    Ok(())
+}
+
+// 系统级空闲时间（距最后一次键盘鼠标输入），无法获取时返回 None
+#[cfg(windows)]
+fn system_idle() -> Option<Duration> {
+    use windows_sys::Win32::System::SystemInformation::GetTickCount;
+    use windows_sys::Win32::UI::Input::KeyboardAndMouse::{GetLastInputInfo, LASTINPUTINFO};
+
+    let mut info = LASTINPUTINFO {
+        cbSize: std::mem::size_of::<LASTINPUTINFO>() as u32,
+        dwTime: 0,
+    };
+    // SAFETY: info 是按要求设置了 cbSize 的有效结构体
+    if unsafe { GetLastInputInfo(&mut info) } == 0 {
+        return None;
+    }
+    let now = unsafe { GetTickCount() };
+    Some(Duration::from_millis(now.wrapping_sub(info.dwTime) as u64))
+}
+
+#[cfg(target_os = "macos")]
+fn system_idle() -> Option<Duration> {
+    // kCGEventSourceStateCombinedSessionState
+    const COMBINED_SESSION_STATE: i32 = 0;
+    // kCGAnyInputEventType
+    const ANY_INPUT_EVENT: u32 = u32::MAX;
+
+    #[link(name = "CoreGraphics", kind = "framework")]
+    extern "C" {
+        fn CGEventSourceSecondsSinceLastEventType(state: i32, event_type: u32) -> f64;
+    }
+
+    // SAFETY: 只读取系统事件源的统计信息
+    let secs = unsafe { CGEventSourceSecondsSinceLastEventType(COMBINED_SESSION_STATE, ANY_INPUT_EVENT) };
+    (secs.is_finite() && secs >= 0.0).then(|| Duration::from_secs_f64(secs))
+}
+
+// Linux 通过 X11 屏幕保护扩展查询，运行时加载 libX11 和 libXss；
+// 没有 X11 显示（如纯 Wayland 会话）或缺少这两个库时返回 None
+#[cfg(target_os = "linux")]
+fn system_idle() -> Option<Duration> {
+    use std::sync::OnceLock;
+    use x11_dl::{xlib, xss};
+
+    struct X11Idle {
+        xlib: xlib::Xlib,
+        xss: xss::Xss,
+        display: *mut xlib::Display,
+    }
+    // SAFETY: 显示连接只在持有互斥锁时使用
+    unsafe impl Send for X11Idle {}
+
+    // 显示连接在第一次查询时打开，之后一直复用
+    static X11: OnceLock<Option<Mutex<X11Idle>>> = OnceLock::new();
+    let x11 = X11
+        .get_or_init(|| {
+            let xlib = xlib::Xlib::open().ok()?;
+            let xss = xss::Xss::open().ok()?;
+            // SAFETY: 传入空指针时使用 DISPLAY 环境变量指定的显示
+            let display = unsafe { (xlib.XOpenDisplay)(std::ptr::null()) };
+            if display.is_null() {
+                log::warn!("⚠️ 无法连接 X11 显示，不检测系统空闲时间");
+                return None;
+            }
+            Some(Mutex::new(X11Idle { xlib, xss, display }))
+        })
+        .as_ref()?;
+    let x11 = x11.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+
+    // SAFETY: display 是已打开的显示连接，info 由 Xss 分配并在使用后释放
+    unsafe {
+        let info = (x11.xss.XScreenSaverAllocInfo)();
+        if info.is_null() {
+            return None;
+        }
+        let root = (x11.xlib.XDefaultRootWindow)(x11.display);
+        let status = (x11.xss.XScreenSaverQueryInfo)(x11.display, root, info);
+        // c_ulong 在32位系统上是 u32
+        #[allow(clippy::unnecessary_cast)]
+        let idle_ms = (*info).idle as u64;
+        (x11.xlib.XFree)(info.cast());
+        (status != 0).then(|| Duration::from_millis(idle_ms))
+    }
+}
+
+#[cfg(not(any(windows, target_os = "macos", target_os = "linux")))]
+fn system_idle() -> Option<Duration> {
+    None
+}
+
+// 后台任务：定期采样前台时间并批量写入数据库
+pub fn spawn_sampler(state: &AppState) {
+    let config = state.config.clone();
+    let mongo = state.mongo.clone();
+    let current_user = state.current_user.clone();
+    let tracker = state.active_time.clone();
+
+    tauri::async_runtime::spawn(async move {
+        let activity = &config.activity;
+        let sample_every = Duration::from_secs(activity.sample_secs);
+        let idle_after = Duration::from_secs(activity.idle_after_secs);
+        let flush_every = Duration::from_secs(activity.flush_secs);
+        let tz = config.analytics.tz();
+
+        let mut interval = tokio::time::interval(sample_every);
+        let mut last_flush = Instant::now();
+        loop {
+            interval.tick().await;
+
+            let user_id = current_user
+                .read()
+                .await
+                .as_ref()
+                .and_then(|user| ObjectId::parse_str(&user.id).ok());
+
+            let now = Instant::now();
+            let previous = tracker.sample(now, user_id, system_idle(), idle_after, sample_every * 2);
+            if let Some(previous) = previous {
+                // 上一个用户的时长立即写入，失败时按原 flush_id 稍后重试
+                if let Err(e) = flush(&*mongo.read().await, tz, &previous).await {
+                    log::warn!("⚠️ {}", e);
+                    tracker.retry_later(previous);
+                }
+            }
+
+            if now.duration_since(last_flush) >= flush_every {
+                last_flush = now;
+                let mut batch = tracker.take_failed();
+                batch.extend(tracker.take());
+                for usage in batch {
+                    if let Err(e) = flush(&*mongo.read().await, tz, &usage).await {
+                        log::warn!("⚠️ {}", e);
+                        tracker.retry_later(usage);
+                    }
+                }
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::TestDb;
+    use futures::TryStreamExt;
+
+    const TZ: Tz = chrono_tz::Asia::Shanghai;
+
+    #[test]
+    fn failed_flush_is_retried_with_the_same_id() {
+        let tracker = ActiveTimeTracker::default();
+        let user_id = ObjectId::new();
+        let start = Instant::now();
+        let step = Duration::from_secs(10);
+        tracker.set_focus(MAIN_WINDOW_LABEL, true);
+        tracker.sample(start, Some(user_id), None, Duration::from_secs(60), step);
+        tracker.sample(start + Duration::from_secs(5), Some(user_id), None, Duration::from_secs(60), step);
+
+        let usage = tracker.take().unwrap();
+        assert_eq!(usage.total_secs, 5);
+        let flush_id = usage.flush_id.clone();
+        tracker.retry_later(usage);
+
+        // 重试的是同一次写入，不会和新累计的时长合并
+        assert!(tracker.take().is_none());
+        let failed = tracker.take_failed();
+        assert_eq!(failed.len(), 1);
+        assert_eq!(failed[0].flush_id, flush_id);
+        assert_eq!(failed[0].total_secs, 5);
+        assert!(tracker.take_failed().is_empty());
+    }
+
+    #[tokio::test]
+    #[ignore = "需要本机 mongod"]
+    async fn retried_flush_is_counted_once() {
+        let db = TestDb::new("usage_flush").await;
+        ensure_indexes(&db.mongo).await.unwrap();
+        let user_id = ObjectId::new();
+        db.mongo.users()
+            .clone_with_type::<mongodb::bson::Document>()
+            .insert_one(doc! {"_id": user_id, "username": "alice", "totalUsageTime": 0})
+            .await
+            .unwrap();
+
+        let usage = UsageFlush {
+            flush_id: uuid::Uuid::new_v4().to_string(),
+            user_id,
+            total_secs: 30,
+            tools_secs: vec![(5, 20)],
+        };
+        flush(&db.mongo, TZ, &usage).await.unwrap();
+        // 模拟第一次写入后超时而重试
+        flush(&db.mongo, TZ, &usage).await.unwrap();
+
+        let user = db.mongo.users()
+            .clone_with_type::<mongodb::bson::Document>()
+            .find_one(doc! {"_id": user_id})
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(crate::metrics::get_number(&user, "totalUsageTime"), 30);
+        assert_eq!(db.mongo.usage_time().count_documents(doc! {}).await.unwrap(), 1);
+        let usage_secs: i64 = db.mongo.daily_stats()
+            .find(doc! {"userId": user_id})
+            .await
+            .unwrap()
+            .try_collect::<Vec<_>>()
+            .await
+            .unwrap()
+            .iter()
+            .map(|stat| stat.usage_secs)
+            .sum();
+        assert_eq!(usage_secs, 50);
+        db.drop().await;
+    }
+}
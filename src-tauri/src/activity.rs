@@ -0,0 +1,495 @@
+use futures::TryStreamExt;
+use mongodb::{
+    bson::{doc, oid::ObjectId, DateTime},
+    error::ErrorKind,
+    options::IndexOptions,
+    IndexModel,
+};
+use chrono_tz::Tz;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+use crate::auth::{AppState, MongoManager, UserResponse};
+use crate::daily_stats;
+use crate::guard::{current_caller, AuthError};
+use crate::rbac;
+use crate::tool_access;
+use crate::tools;
+
+// MongoDB 唯一索引冲突错误码
+const DUPLICATE_KEY_CODE: i32 = 11000;
+
+const MAX_SEARCH_QUERY_LEN: usize = 200;
+const MAX_ERROR_MESSAGE_LEN: usize = 1000;
+const MAX_USERNAME_LEN: usize = 64;
+const MAX_TOOL_SECS: i64 = 24 * 60 * 60;
+
+// 登录失败原因
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LoginFailureReason {
+    UnknownUser,
+    WrongPassword,
+    AccountDisabled,
+    Locked,
+    InvalidSecondFactor,
+}
+
+// 用户活动事件，按 type 字段区分
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", rename_all_fields = "camelCase")]
+pub enum ActivityEvent {
+    ToolOpened {
+        tool_id: i32,
+    },
+    ToolClosed {
+        tool_id: i32,
+        // 前端记录的打开时长，仅供参考，使用时长以前台时间统计为准
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        duration_secs: Option<i64>,
+    },
+    ToolError {
+        tool_id: i32,
+        message: String,
+    },
+    Search {
+        query: String,
+        result_count: u32,
+    },
+    FavoriteToggled {
+        tool_id: i32,
+        favorite: bool,
+    },
+    LoginSucceeded,
+    LoginFailed {
+        username: String,
+        reason: LoginFailureReason,
+    },
+}
+
+impl ActivityEvent {
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            ActivityEvent::ToolOpened { .. } => "tool_opened",
+            ActivityEvent::ToolClosed { .. } => "tool_closed",
+            ActivityEvent::ToolError { .. } => "tool_error",
+            ActivityEvent::Search { .. } => "search",
+            ActivityEvent::FavoriteToggled { .. } => "favorite_toggled",
+            ActivityEvent::LoginSucceeded => "login_succeeded",
+            ActivityEvent::LoginFailed { .. } => "login_failed",
+        }
+    }
+
+    pub fn tool_id(&self) -> Option<i32> {
+        match self {
+            ActivityEvent::ToolOpened { tool_id }
+            | ActivityEvent::ToolClosed { tool_id, .. }
+            | ActivityEvent::ToolError { tool_id, .. }
+            | ActivityEvent::FavoriteToggled { tool_id, .. } => Some(*tool_id),
+            _ => None,
+        }
+    }
+
+    // 登录事件只能由Rust端产生，前端不能伪造
+    pub fn is_client_event(&self) -> bool {
+        !matches!(self, ActivityEvent::LoginSucceeded | ActivityEvent::LoginFailed { .. })
+    }
+
+    // 校验并规范化事件内容
+    pub fn validate(&mut self) -> Result<(), String> {
+        if let Some(tool_id) = self.tool_id() {
+            if tool_id <= 0 {
+                return Err(format!("无效的工具ID: {}", tool_id));
+            }
+        }
+
+        match self {
+            ActivityEvent::ToolClosed { duration_secs: Some(secs), .. } if !(0..=MAX_TOOL_SECS).contains(secs) => {
+                return Err(format!("无效的使用时长: {}", secs));
+            }
+            ActivityEvent::ToolError { message, .. } => {
+                *message = truncate(message.trim(), MAX_ERROR_MESSAGE_LEN);
+                if message.is_empty() {
+                    return Err("错误信息不能为空".to_string());
+                }
+            }
+            ActivityEvent::Search { query, .. } => {
+                *query = query.trim().to_string();
+                if query.is_empty() {
+                    return Err("搜索关键词不能为空".to_string());
+                }
+                if query.chars().count() > MAX_SEARCH_QUERY_LEN {
+                    return Err(format!("搜索关键词不能超过{}个字符", MAX_SEARCH_QUERY_LEN));
+                }
+            }
+            ActivityEvent::LoginFailed { username, .. } => {
+                *username = truncate(username.trim(), MAX_USERNAME_LEN);
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+}
+
+fn truncate(value: &str, max_chars: usize) -> String {
+    value.chars().take(max_chars).collect()
+}
+
+// 活动事件记录（集合 activity_events，只追加不修改）
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ActivityRecord {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    pub id: Option<ObjectId>,
+    // 客户端生成的幂等键，重复上传时不会重复记录
+    #[serde(rename = "eventId")]
+    pub event_id: String,
+    #[serde(rename = "userId", default, skip_serializing_if = "Option::is_none")]
+    pub user_id: Option<ObjectId>,
+    #[serde(rename = "sessionId", default, skip_serializing_if = "Option::is_none")]
+    pub session_id: Option<ObjectId>,
+    #[serde(rename = "deviceId")]
+    pub device_id: String,
+    #[serde(rename = "appVersion")]
+    pub app_version: String,
+    pub timestamp: DateTime,
+    // 派生统计是否已更新，保证重复上传时不会重复计数
+    #[serde(rename = "countersApplied")]
+    pub counters_applied: bool,
+    // 已完成的派生统计更新，中途失败重试时跳过这些项
+    #[serde(rename = "countersDone", default, skip_serializing_if = "Vec::is_empty")]
+    pub counters_done: Vec<String>,
+    #[serde(flatten)]
+    pub event: ActivityEvent,
+}
+
+pub async fn ensure_indexes(mongo: &MongoManager) -> Result<(), String> {
+    let indexes = [
+        IndexModel::builder()
+            .keys(doc! {"eventId": 1})
+            .options(IndexOptions::builder().unique(true).build())
+            .build(),
+        IndexModel::builder()
+            .keys(doc! {"timestamp": -1})
+            .build(),
+        IndexModel::builder()
+            .keys(doc! {"userId": 1, "timestamp": -1})
+            .build(),
+        IndexModel::builder()
+            .keys(doc! {"type": 1, "toolId": 1, "timestamp": -1})
+            .build(),
+        // 工具分析中按打开时长排序取百分位
+        IndexModel::builder()
+            .keys(doc! {"toolId": 1, "type": 1, "durationSecs": 1})
+            .build(),
+    ];
+
+    mongo.activity_events()
+        .create_indexes(indexes)
+        .await
+        .map_err(|e| format!("创建活动事件索引失败: {}", e))?;
+    Ok(())
+}
+
+// 校验事件并附加会话、设备和版本信息
+pub async fn build_record(
+    state: &AppState,
+    user_id: Option<ObjectId>,
+    mut event: ActivityEvent,
+) -> Result<ActivityRecord, String> {
+    event.validate()?;
+    Ok(ActivityRecord {
+        id: None,
+        event_id: uuid::Uuid::new_v4().to_string(),
+        user_id,
+        session_id: *state.current_session.read().await,
+        device_id: state.device.device_id.clone(),
+        app_version: state.device.app_version.clone(),
+        timestamp: DateTime::now(),
+        // 目前只有打开工具事件需要更新统计
+        counters_applied: !matches!(event, ActivityEvent::ToolOpened { .. }),
+        counters_done: Vec::new(),
+        event,
+    })
+}
+
+// 批量保存事件并更新由事件派生的统计；已上传过的事件按 eventId 去重
+pub async fn upload_batch(mongo: &MongoManager, tz: Tz, records: &[ActivityRecord]) -> Result<(), String> {
+    if let Err(e) = mongo.activity_events().insert_many(records).ordered(false).await {
+        let duplicates_only = match *e.kind {
+            ErrorKind::InsertMany(ref err) => {
+                err.write_concern_error.is_none()
+                    && err.write_errors.as_ref().is_some_and(|errors| {
+                        errors.iter().all(|error| error.code == DUPLICATE_KEY_CODE)
+                    })
+            }
+            _ => false,
+        };
+        if !duplicates_only {
+            return Err(format!("保存活动事件失败: {}", e));
+        }
+    }
+    apply_counters(mongo, tz, records).await
+}
+
+// 打开工具事件派生的统计，每项单独累加
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum CounterStep {
+    // 每日统计中的工具记录和用户合计
+    ToolDay,
+    UserDay,
+    // 用户的工具使用记录和累计点击数
+    ToolUsage,
+    UserClicks,
+}
+
+impl CounterStep {
+    const ALL: [CounterStep; 4] = [
+        CounterStep::ToolDay,
+        CounterStep::UserDay,
+        CounterStep::ToolUsage,
+        CounterStep::UserClicks,
+    ];
+
+    fn as_str(self) -> &'static str {
+        match self {
+            CounterStep::ToolDay => "tool_day",
+            CounterStep::UserDay => "user_day",
+            CounterStep::ToolUsage => "tool_usage",
+            CounterStep::UserClicks => "user_clicks",
+        }
+    }
+}
+
+// 由打开工具事件更新点击计数和每日统计。逐个事件累加，每项成功后记入事件的 countersDone，
+// 全部完成才标记 countersApplied；失败时返回错误由上传任务退避重试，重试时跳过已完成的项
+async fn apply_counters(mongo: &MongoManager, tz: Tz, records: &[ActivityRecord]) -> Result<(), String> {
+    let event_ids: Vec<&str> = records
+        .iter()
+        .filter(|record| !record.counters_applied)
+        .map(|record| record.event_id.as_str())
+        .collect();
+    if event_ids.is_empty() {
+        return Ok(());
+    }
+
+    let pending: Vec<ActivityRecord> = mongo.activity_events()
+        .find(doc! {"eventId": {"$in": &event_ids}, "countersApplied": false})
+        .await
+        .map_err(|e| format!("查询活动事件失败: {}", e))?
+        .try_collect()
+        .await
+        .map_err(|e| format!("读取活动事件失败: {}", e))?;
+
+    // 同一批事件通常来自同一个用户，只查询一次
+    let mut users: HashMap<ObjectId, Option<UserResponse>> = HashMap::new();
+    for record in &pending {
+        if let (ActivityEvent::ToolOpened { tool_id }, Some(user_id)) = (&record.event, record.user_id) {
+            let user = match users.get(&user_id) {
+                Some(user) => user.clone(),
+                None => {
+                    let user = match mongo.users()
+                        .find_one(doc! {"_id": user_id})
+                        .await
+                        .map_err(|e| format!("查询用户失败: {}", e))?
+                    {
+                        Some(user) => Some(rbac::resolve_user(mongo, user).await?),
+                        None => None,
+                    };
+                    users.insert(user_id, user.clone());
+                    user
+                }
+            };
+            apply_tool_opened(mongo, tz, record, user_id, *tool_id, user.as_ref()).await?;
+        }
+
+        mongo.activity_events()
+            .update_one(
+                doc! {"eventId": &record.event_id, "countersApplied": false},
+                doc! {"$set": {"countersApplied": true}, "$unset": {"countersDone": ""}},
+            )
+            .await
+            .map_err(|e| format!("更新活动事件失败: {}", e))?;
+    }
+    Ok(())
+}
+
+async fn apply_tool_opened(
+    mongo: &MongoManager,
+    tz: Tz,
+    record: &ActivityRecord,
+    user_id: ObjectId,
+    tool_id: i32,
+    user: Option<&UserResponse>,
+) -> Result<(), String> {
+    // 事件可能在离线时产生，上传时再校验工具权限，无权使用的工具和已删除的用户不计入任何统计，
+    // 与重建每日统计时的筛选条件一致
+    let counted = match user {
+        Some(user) => tool_access::tool_allowed(mongo, user, tool_id).await?,
+        None => false,
+    };
+
+    for step in CounterStep::ALL {
+        if record.counters_done.iter().any(|done| done == step.as_str()) {
+            continue;
+        }
+        match step {
+            CounterStep::ToolDay if counted => {
+                daily_stats::add(mongo, tz, user_id, Some(tool_id), record.timestamp, doc! {"clicks": 1}).await?;
+            }
+            CounterStep::UserDay if counted => {
+                daily_stats::add(mongo, tz, user_id, None, record.timestamp, doc! {"clicks": 1}).await?;
+            }
+            CounterStep::ToolUsage if counted => {
+                let tool_name = tools::canonical_tool_name(mongo, tool_id, None).await?;
+                mongo.tool_usage()
+                    .update_one(
+                        doc! {"userId": user_id, "toolId": tool_id},
+                        doc! {
+                            "$inc": {"clickCount": 1},
+                            "$max": {"lastUsedAt": record.timestamp},
+                            "$set": {"toolName": tool_name},
+                            "$setOnInsert": {"totalUsageTime": 0}
+                        },
+                    )
+                    .upsert(true)
+                    .await
+                    .map_err(|e| format!("更新工具使用记录失败: {}", e))?;
+            }
+            // 用户累计点击数，用户分析按此排序
+            CounterStep::UserClicks if counted => {
+                mongo.users()
+                    .update_one(doc! {"_id": user_id}, doc! {"$inc": {"totalToolClicks": 1}})
+                    .await
+                    .map_err(|e| format!("更新用户点击数失败: {}", e))?;
+            }
+            _ => continue,
+        }
+
+        mongo.activity_events()
+            .update_one(
+                doc! {"eventId": &record.event_id, "countersApplied": false},
+                doc! {"$addToSet": {"countersDone": step.as_str()}},
+            )
+            .await
+            .map_err(|e| format!("更新活动事件失败: {}", e))?;
+    }
+    Ok(())
+}
+
+// 记录Rust端产生的事件（如登录），写入本地队列；失败只记录日志，不影响主流程
+pub async fn record(state: &AppState, user_id: Option<ObjectId>, event: ActivityEvent) {
+    let type_name = event.type_name();
+    let result = match build_record(state, user_id, event).await {
+        Ok(record) => state.events.enqueue(&record).await,
+        Err(e) => Err(e),
+    };
+    if let Err(e) = result {
+        log::warn!("⚠️ 记录活动事件 {} 失败: {}", type_name, e);
+    }
+}
+
+// 记录当前用户的活动事件
+#[tauri::command]
+pub async fn record_activity(
+    event: ActivityEvent,
+    state: tauri::State<'_, AppState>,
+) -> Result<(), String> {
+    if !event.is_client_event() {
+        return Err(format!("不允许从客户端提交 {} 事件", event.type_name()));
+    }
+
+    let current_user = current_caller(&state).await?;
+    let user_id = ObjectId::parse_str(&current_user.id).map_err(|e| format!("用户ID解析失败: {}", e))?;
+
+    // 在线时直接拒绝打开无权使用的工具；查询授权失败（如断网）时先写入队列，上传时再校验
+    if let ActivityEvent::ToolOpened { tool_id } = event {
+        let mongo = state.mongo.read().await;
+        match tool_access::tool_allowed(&mongo, &current_user, tool_id).await {
+            Ok(true) => {}
+            Ok(false) => {
+                log::warn!("❌ 工具访问拒绝 - 用户 {} 无权使用工具 {}", current_user.username, tool_id);
+                return Err(AuthError::ToolDenied { tool_id }.into());
+            }
+            Err(e) => log::warn!("⚠️ {}，上传时再校验工具权限", e),
+        }
+    }
+
+    // 先写入本地队列，由后台任务上传，断网时不会丢失
+    let record = build_record(&state, Some(user_id), event).await?;
+    state.events.enqueue(&record).await?;
+
+    log::info!("📊 活动事件: 用户={}, 类型={}", current_user.username, record.event.type_name());
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn validated(mut event: ActivityEvent) -> Result<ActivityEvent, String> {
+        event.validate().map(|_| event)
+    }
+
+    #[test]
+    fn validate_rejects_non_positive_tool_ids() {
+        for tool_id in [0, -1] {
+            assert!(validated(ActivityEvent::ToolOpened { tool_id }).is_err());
+            assert!(validated(ActivityEvent::FavoriteToggled { tool_id, favorite: true }).is_err());
+        }
+        assert!(validated(ActivityEvent::ToolOpened { tool_id: 1 }).is_ok());
+    }
+
+    #[test]
+    fn validate_bounds_reported_open_duration() {
+        let closed = |duration_secs| ActivityEvent::ToolClosed { tool_id: 1, duration_secs };
+        assert!(validated(closed(None)).is_ok());
+        assert!(validated(closed(Some(0))).is_ok());
+        assert!(validated(closed(Some(MAX_TOOL_SECS))).is_ok());
+        assert_eq!(validated(closed(Some(-1))).unwrap_err(), "无效的使用时长: -1");
+        assert!(validated(closed(Some(MAX_TOOL_SECS + 1))).is_err());
+    }
+
+    #[test]
+    fn validate_trims_and_limits_text_fields() {
+        assert_eq!(
+            validated(ActivityEvent::Search { query: "  模板  ".to_string(), result_count: 3 }).unwrap(),
+            ActivityEvent::Search { query: "模板".to_string(), result_count: 3 }
+        );
+        assert!(validated(ActivityEvent::Search { query: "   ".to_string(), result_count: 0 }).is_err());
+        let long = "字".repeat(MAX_SEARCH_QUERY_LEN + 1);
+        assert!(validated(ActivityEvent::Search { query: long, result_count: 0 }).is_err());
+        let longest = "字".repeat(MAX_SEARCH_QUERY_LEN);
+        assert!(validated(ActivityEvent::Search { query: longest, result_count: 0 }).is_ok());
+
+        assert!(validated(ActivityEvent::ToolError { tool_id: 1, message: " ".to_string() }).is_err());
+        let message = "错".repeat(MAX_ERROR_MESSAGE_LEN + 10);
+        match validated(ActivityEvent::ToolError { tool_id: 1, message }).unwrap() {
+            ActivityEvent::ToolError { message, .. } => assert_eq!(message.chars().count(), MAX_ERROR_MESSAGE_LEN),
+            other => panic!("unexpected event {:?}", other),
+        }
+
+        let username = format!(" {} ", "a".repeat(MAX_USERNAME_LEN + 1));
+        match validated(ActivityEvent::LoginFailed { username, reason: LoginFailureReason::UnknownUser }).unwrap() {
+            ActivityEvent::LoginFailed { username, .. } => assert_eq!(username, "a".repeat(MAX_USERNAME_LEN)),
+            other => panic!("unexpected event {:?}", other),
+        }
+    }
+
+    #[test]
+    fn login_events_cannot_come_from_the_client() {
+        let failed = ActivityEvent::LoginFailed { username: "alice".to_string(), reason: LoginFailureReason::WrongPassword };
+        assert!(!failed.is_client_event());
+        assert!(!ActivityEvent::LoginSucceeded.is_client_event());
+        assert!(ActivityEvent::ToolOpened { tool_id: 1 }.is_client_event());
+    }
+
+    #[test]
+    fn events_serialize_with_snake_case_type_and_camel_case_fields() {
+        let event = ActivityEvent::ToolClosed { tool_id: 3, duration_secs: Some(42) };
+        assert_eq!(
+            serde_json::to_value(&event).unwrap(),
+            serde_json::json!({"type": "tool_closed", "toolId": 3, "durationSecs": 42})
+        );
+        assert_eq!(event.type_name(), "tool_closed");
+    }
+}
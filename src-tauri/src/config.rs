@@ -0,0 +1,933 @@
+use serde::Deserialize;
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+use crate::password::{HashParams, PasswordPolicy};
+use crate::secret::Secret;
+
+// 配置文件名（位于应用配置目录下）
+pub const CONFIG_FILE_NAME: &str = "config.toml";
+
+// 环境变量覆盖项
+const ENV_CONFIG_PATH: &str = "CHENGSHANG_CONFIG";
+const ENV_MONGO_URI: &str = "CHENGSHANG_MONGO_URI";
+const ENV_MONGO_DATABASE: &str = "CHENGSHANG_MONGO_DATABASE";
+const ENV_JWT_SECRET: &str = "CHENGSHANG_JWT_SECRET";
+const ENV_SIGNING_KEYS_DIR: &str = "CHENGSHANG_SIGNING_KEYS_DIR";
+const ENV_REMEMBER_ME_DAYS: &str = "CHENGSHANG_REMEMBER_ME_DAYS";
+const ENV_AUTO_LOGIN_DAYS: &str = "CHENGSHANG_AUTO_LOGIN_DAYS";
+
+// 内置默认值：配置文件和环境变量都未设置的项使用这些值。
+// 数据库连接地址包含账号密码，没有内置默认值，必须通过配置文件或环境变量提供
+const DEFAULT_MONGO_DATABASE: &str = "chengshang_tools";
+const DEFAULT_REMEMBER_ME_DAYS: i64 = 30;
+const DEFAULT_AUTO_LOGIN_DAYS: i64 = 7;
+const DEFAULT_SIGNING_KEYS_DIR: &str = "signing_keys";
+const DEFAULT_RETIRED_KEY_DAYS: i64 = 7;
+
+const DEFAULT_LOCKOUT_MAX_FAILURES: u32 = 5;
+const DEFAULT_LOCKOUT_WINDOW_MINUTES: i64 = 15;
+const DEFAULT_LOCKOUT_MINUTES: i64 = 15;
+const DEFAULT_BACKOFF_BASE_SECS: i64 = 1;
+const DEFAULT_BACKOFF_MAX_SECS: i64 = 30;
+
+const DEFAULT_HEARTBEAT_SECS: u64 = 60;
+const DEFAULT_STALE_AFTER_SECS: u64 = 300;
+
+const DEFAULT_SAMPLE_SECS: u64 = 5;
+const DEFAULT_IDLE_AFTER_SECS: u64 = 300;
+const DEFAULT_FLUSH_SECS: u64 = 60;
+const DEFAULT_EVENT_FLUSH_SECS: u64 = 5;
+const DEFAULT_EVENT_BATCH_SIZE: usize = 500;
+const DEFAULT_EVENT_MAX_BACKOFF_SECS: u64 = 300;
+
+const DEFAULT_TIMEZONE: &str = "Asia/Shanghai";
+
+const DEFAULT_REPORT_WEEKDAY: &str = "mon";
+const DEFAULT_REPORT_HOUR: u32 = 8;
+const DEFAULT_REPORT_TOP_TOOLS: i64 = 10;
+const DEFAULT_REPORT_INACTIVE_DAYS: i64 = 30;
+const DEFAULT_REPORT_INACTIVE_LIMIT: i64 = 50;
+const DEFAULT_SMTP_PORT: u16 = 587;
+const DEFAULT_SMTP_TIMEOUT_SECS: u64 = 30;
+
+const DEFAULT_PASSWORD_MIN_LENGTH: usize = 8;
+const DEFAULT_PASSWORD_MIN_CHAR_CLASSES: usize = 2;
+const DEFAULT_PASSWORD_HISTORY_SIZE: usize = 5;
+
+const MIN_JWT_SECRET_LEN: usize = 32;
+const MAX_PASSWORD_HISTORY_SIZE: usize = 24;
+const MAX_TOKEN_DAYS: i64 = 365;
+
+// 配置加载错误
+#[derive(Debug)]
+pub enum ConfigError {
+    Io { path: PathBuf, source: std::io::Error },
+    Parse { path: PathBuf, message: String },
+    InvalidEnv { var: &'static str, message: String },
+    Invalid { field: &'static str, message: String },
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::Io { path, source } => {
+                write!(f, "读取配置文件 {} 失败: {}", path.display(), source)
+            }
+            ConfigError::Parse { path, message } => {
+                write!(f, "配置文件 {} 格式错误: {}", path.display(), message)
+            }
+            ConfigError::InvalidEnv { var, message } => {
+                write!(f, "环境变量 {} 无效: {}", var, message)
+            }
+            ConfigError::Invalid { field, message } => {
+                write!(f, "配置项 {} 无效: {}", field, message)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+// 应用配置
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AppConfig {
+    pub mongo: MongoConfig,
+    pub auth: AuthConfig,
+    pub password: PasswordConfig,
+    pub lockout: LockoutConfig,
+    pub session: SessionConfig,
+    pub activity: ActivityConfig,
+    pub analytics: AnalyticsConfig,
+    pub report: ReportConfig,
+}
+
+// MongoDB连接配置
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct MongoConfig {
+    pub uri: String,
+    pub database: String,
+}
+
+impl Default for MongoConfig {
+    fn default() -> Self {
+        MongoConfig {
+            uri: String::new(),
+            database: DEFAULT_MONGO_DATABASE.to_string(),
+        }
+    }
+}
+
+// Token签发配置
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AuthConfig {
+    // 仅用于验证升级前签发的旧版HS256 Token，未配置时旧Token一律失效
+    pub jwt_secret: Option<Secret<String>>,
+    // Ed25519签名密钥目录，相对路径基于应用配置目录
+    pub signing_keys_dir: PathBuf,
+    // 密钥轮换后旧密钥继续用于验证的天数
+    pub retired_key_days: i64,
+    // 记住我/自动登录刷新Token家族的有效期（轮换不会延长）
+    pub remember_me_days: i64,
+    pub auto_login_days: i64,
+    // 已废弃：不再签发访问Token，旧配置文件中的该项会被忽略
+    pub access_token_minutes: Option<i64>,
+    // 拥有用户管理等高危权限的账号必须启用两步验证
+    pub require_admin_totp: bool,
+}
+
+impl Default for AuthConfig {
+    fn default() -> Self {
+        AuthConfig {
+            jwt_secret: None,
+            signing_keys_dir: PathBuf::from(DEFAULT_SIGNING_KEYS_DIR),
+            retired_key_days: DEFAULT_RETIRED_KEY_DAYS,
+            remember_me_days: DEFAULT_REMEMBER_ME_DAYS,
+            auto_login_days: DEFAULT_AUTO_LOGIN_DAYS,
+            access_token_minutes: None,
+            require_admin_totp: false,
+        }
+    }
+}
+
+// 密码哈希成本和密码策略配置
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PasswordConfig {
+    pub memory_kib: u32,
+    pub iterations: u32,
+    pub parallelism: u32,
+    pub min_length: usize,
+    pub min_char_classes: usize,
+    pub history_size: usize,
+    pub blocklist: Vec<String>,
+}
+
+impl Default for PasswordConfig {
+    fn default() -> Self {
+        let params = HashParams::default();
+        PasswordConfig {
+            memory_kib: params.memory_kib,
+            iterations: params.iterations,
+            parallelism: params.parallelism,
+            min_length: DEFAULT_PASSWORD_MIN_LENGTH,
+            min_char_classes: DEFAULT_PASSWORD_MIN_CHAR_CLASSES,
+            history_size: DEFAULT_PASSWORD_HISTORY_SIZE,
+            blocklist: Vec::new(),
+        }
+    }
+}
+
+impl PasswordConfig {
+    pub fn hash_params(&self) -> HashParams {
+        HashParams {
+            memory_kib: self.memory_kib,
+            iterations: self.iterations,
+            parallelism: self.parallelism,
+        }
+    }
+
+    pub fn policy(&self) -> PasswordPolicy {
+        PasswordPolicy {
+            min_length: self.min_length,
+            min_char_classes: self.min_char_classes,
+            history_size: self.history_size,
+            blocklist: self.blocklist.clone(),
+        }
+    }
+}
+
+// 登录失败限制配置：窗口内连续失败时逐次加倍等待时间，达到上限后临时锁定
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LockoutConfig {
+    pub max_failures: u32,
+    pub window_minutes: i64,
+    pub lockout_minutes: i64,
+    pub backoff_base_secs: i64,
+    pub backoff_max_secs: i64,
+}
+
+impl Default for LockoutConfig {
+    fn default() -> Self {
+        LockoutConfig {
+            max_failures: DEFAULT_LOCKOUT_MAX_FAILURES,
+            window_minutes: DEFAULT_LOCKOUT_WINDOW_MINUTES,
+            lockout_minutes: DEFAULT_LOCKOUT_MINUTES,
+            backoff_base_secs: DEFAULT_BACKOFF_BASE_SECS,
+            backoff_max_secs: DEFAULT_BACKOFF_MAX_SECS,
+        }
+    }
+}
+
+// 会话心跳配置：客户端定期刷新会话心跳，超过 stale_after_secs 没有心跳的会话视为已中断
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SessionConfig {
+    pub heartbeat_secs: u64,
+    pub stale_after_secs: u64,
+}
+
+impl Default for SessionConfig {
+    fn default() -> Self {
+        SessionConfig {
+            heartbeat_secs: DEFAULT_HEARTBEAT_SECS,
+            stale_after_secs: DEFAULT_STALE_AFTER_SECS,
+        }
+    }
+}
+
+// 前台使用时长统计配置：每 sample_secs 秒采样一次窗口焦点和系统空闲时间，
+// 空闲超过 idle_after_secs 秒不计时，每 flush_secs 秒写入一次数据库；
+// 活动事件先写入本地队列，每 event_flush_secs 秒批量上传，失败后按2的幂退避
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ActivityConfig {
+    pub sample_secs: u64,
+    pub idle_after_secs: u64,
+    pub flush_secs: u64,
+    pub event_flush_secs: u64,
+    pub event_batch_size: usize,
+    pub event_max_backoff_secs: u64,
+}
+
+impl Default for ActivityConfig {
+    fn default() -> Self {
+        ActivityConfig {
+            sample_secs: DEFAULT_SAMPLE_SECS,
+            idle_after_secs: DEFAULT_IDLE_AFTER_SECS,
+            flush_secs: DEFAULT_FLUSH_SECS,
+            event_flush_secs: DEFAULT_EVENT_FLUSH_SECS,
+            event_batch_size: DEFAULT_EVENT_BATCH_SIZE,
+            event_max_backoff_secs: DEFAULT_EVENT_MAX_BACKOFF_SECS,
+        }
+    }
+}
+
+// 统计分析配置：“今天”、日/周/月统计区间均按业务时区（IANA 时区名）的自然日划分
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AnalyticsConfig {
+    pub timezone: String,
+}
+
+impl Default for AnalyticsConfig {
+    fn default() -> Self {
+        AnalyticsConfig {
+            timezone: DEFAULT_TIMEZONE.to_string(),
+        }
+    }
+}
+
+impl AnalyticsConfig {
+    // 配置加载时已校验，解析失败只可能是未经校验的配置，退回 UTC
+    pub fn tz(&self) -> chrono_tz::Tz {
+        self.timezone.parse().unwrap_or(chrono_tz::UTC)
+    }
+}
+
+// 每周使用报告：只在登录了可查看全部统计数据账号的电脑上生成，
+// 每周 weekday 的 hour 点（业务时区）之后生成上一周（周一至周日）的报告，
+// 保存到 output_dir 和/或通过 SMTP 发送
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ReportConfig {
+    pub enabled: bool,
+    pub weekday: String,
+    pub hour: u32,
+    // 报告保存目录，相对路径基于应用配置目录
+    pub output_dir: Option<PathBuf>,
+    // 自定义 HTML 模板，未配置时使用内置模板
+    pub template: Option<PathBuf>,
+    pub top_tools: i64,
+    // 列出超过多少天未活跃的用户，最多 inactive_limit 个
+    pub inactive_days: i64,
+    pub inactive_limit: i64,
+    pub smtp: Option<SmtpConfig>,
+}
+
+impl Default for ReportConfig {
+    fn default() -> Self {
+        ReportConfig {
+            enabled: false,
+            weekday: DEFAULT_REPORT_WEEKDAY.to_string(),
+            hour: DEFAULT_REPORT_HOUR,
+            output_dir: None,
+            template: None,
+            top_tools: DEFAULT_REPORT_TOP_TOOLS,
+            inactive_days: DEFAULT_REPORT_INACTIVE_DAYS,
+            inactive_limit: DEFAULT_REPORT_INACTIVE_LIMIT,
+            smtp: None,
+        }
+    }
+}
+
+impl ReportConfig {
+    // 配置加载时已校验，解析失败只可能是未经校验的配置，退回周一
+    pub fn weekday(&self) -> chrono::Weekday {
+        self.weekday.parse().unwrap_or(chrono::Weekday::Mon)
+    }
+}
+
+// SMTP 连接加密方式；none 只用于本机测试用的模拟 SMTP 服务器
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SmtpSecurity {
+    #[default]
+    Starttls,
+    Tls,
+    None,
+}
+
+// 发送周报的 SMTP 中继
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SmtpConfig {
+    pub host: String,
+    pub port: u16,
+    pub security: SmtpSecurity,
+    pub username: Option<String>,
+    pub password: Option<Secret<String>>,
+    pub from: String,
+    pub to: Vec<String>,
+    pub timeout_secs: u64,
+}
+
+impl Default for SmtpConfig {
+    fn default() -> Self {
+        SmtpConfig {
+            host: String::new(),
+            port: DEFAULT_SMTP_PORT,
+            security: SmtpSecurity::default(),
+            username: None,
+            password: None,
+            from: String::new(),
+            to: Vec::new(),
+            timeout_secs: DEFAULT_SMTP_TIMEOUT_SECS,
+        }
+    }
+}
+
+impl AppConfig {
+    // 加载顺序：内置默认值 -> 配置文件 -> 环境变量，最后统一校验
+    pub fn load(config_dir: &Path) -> Result<Self, ConfigError> {
+        let path = match std::env::var(ENV_CONFIG_PATH) {
+            Ok(path) if !path.trim().is_empty() => PathBuf::from(path),
+            _ => config_dir.join(CONFIG_FILE_NAME),
+        };
+
+        let mut config = Self::from_file(&path)?;
+        if config.auth.access_token_minutes.take().is_some() {
+            log::warn!("⚠️ auth.access_token_minutes 已不再使用，可以从配置文件中删除");
+        }
+        config.apply_env()?;
+        if config.auth.signing_keys_dir.is_relative() {
+            config.auth.signing_keys_dir = config_dir.join(&config.auth.signing_keys_dir);
+        }
+        for path in [&mut config.report.output_dir, &mut config.report.template].into_iter().flatten() {
+            if path.is_relative() {
+                *path = config_dir.join(&*path);
+            }
+        }
+        config.validate()?;
+
+        log::info!("⚙️ 配置加载完成: 数据库={}", config.mongo.database);
+        Ok(config)
+    }
+
+    // 配置文件不存在时使用默认值
+    fn from_file(path: &Path) -> Result<Self, ConfigError> {
+        if !path.exists() {
+            log::info!("⚙️ 未找到配置文件 {}，使用默认配置", path.display());
+            return Ok(AppConfig::default());
+        }
+
+        let content = std::fs::read_to_string(path).map_err(|source| ConfigError::Io {
+            path: path.to_path_buf(),
+            source,
+        })?;
+
+        toml::from_str(&content).map_err(|e| ConfigError::Parse {
+            path: path.to_path_buf(),
+            message: e.to_string(),
+        })
+    }
+
+    fn apply_env(&mut self) -> Result<(), ConfigError> {
+        if let Some(uri) = env_string(ENV_MONGO_URI) {
+            self.mongo.uri = uri;
+        }
+        if let Some(database) = env_string(ENV_MONGO_DATABASE) {
+            self.mongo.database = database;
+        }
+        if let Some(secret) = env_string(ENV_JWT_SECRET) {
+            self.auth.jwt_secret = Some(Secret::new(secret));
+        }
+        if let Some(dir) = env_string(ENV_SIGNING_KEYS_DIR) {
+            self.auth.signing_keys_dir = PathBuf::from(dir);
+        }
+        if let Some(days) = env_i64(ENV_REMEMBER_ME_DAYS)? {
+            self.auth.remember_me_days = days;
+        }
+        if let Some(days) = env_i64(ENV_AUTO_LOGIN_DAYS)? {
+            self.auth.auto_login_days = days;
+        }
+        Ok(())
+    }
+
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        let uri = self.mongo.uri.trim();
+        if uri.is_empty() {
+            return Err(ConfigError::Invalid {
+                field: "mongo.uri",
+                message: format!("未配置数据库连接地址，请在配置文件 [mongo] uri 或环境变量 {} 中设置", ENV_MONGO_URI),
+            });
+        }
+        if !(uri.starts_with("mongodb://") || uri.starts_with("mongodb+srv://")) {
+            return Err(ConfigError::Invalid {
+                field: "mongo.uri",
+                message: "必须以 mongodb:// 或 mongodb+srv:// 开头".to_string(),
+            });
+        }
+
+        let database = self.mongo.database.trim();
+        if database.is_empty()
+            || database.len() > 63
+            || database.contains(|c: char| "/\\. \"$".contains(c))
+        {
+            return Err(ConfigError::Invalid {
+                field: "mongo.database",
+                message: format!("无效的数据库名: \"{}\"", self.mongo.database),
+            });
+        }
+
+        if let Some(secret) = &self.auth.jwt_secret {
+            if secret.expose().len() < MIN_JWT_SECRET_LEN {
+                return Err(ConfigError::Invalid {
+                    field: "auth.jwt_secret",
+                    message: format!("长度至少需要 {} 个字符", MIN_JWT_SECRET_LEN),
+                });
+            }
+        }
+
+        if self.auth.signing_keys_dir.as_os_str().is_empty() {
+            return Err(ConfigError::Invalid {
+                field: "auth.signing_keys_dir",
+                message: "不能为空".to_string(),
+            });
+        }
+
+        for (field, days) in [
+            ("auth.remember_me_days", self.auth.remember_me_days),
+            ("auth.auto_login_days", self.auth.auto_login_days),
+        ] {
+            if !(1..=MAX_TOKEN_DAYS).contains(&days) {
+                return Err(ConfigError::Invalid {
+                    field,
+                    message: format!("必须在 1 到 {} 天之间，当前为 {}", MAX_TOKEN_DAYS, days),
+                });
+            }
+        }
+
+        if !(1..=128).contains(&self.password.min_length) {
+            return Err(ConfigError::Invalid {
+                field: "password.min_length",
+                message: format!("必须在 1 到 128 之间，当前为 {}", self.password.min_length),
+            });
+        }
+
+        if self.password.min_char_classes > 4 {
+            return Err(ConfigError::Invalid {
+                field: "password.min_char_classes",
+                message: format!("不能大于 4，当前为 {}", self.password.min_char_classes),
+            });
+        }
+
+        if self.password.history_size > MAX_PASSWORD_HISTORY_SIZE {
+            return Err(ConfigError::Invalid {
+                field: "password.history_size",
+                message: format!("不能大于 {}，当前为 {}", MAX_PASSWORD_HISTORY_SIZE, self.password.history_size),
+            });
+        }
+
+        if self.lockout.max_failures == 0 {
+            return Err(ConfigError::Invalid {
+                field: "lockout.max_failures",
+                message: "必须大于 0".to_string(),
+            });
+        }
+
+        for (field, value) in [
+            ("lockout.window_minutes", self.lockout.window_minutes),
+            ("lockout.lockout_minutes", self.lockout.lockout_minutes),
+            ("lockout.backoff_base_secs", self.lockout.backoff_base_secs),
+            ("lockout.backoff_max_secs", self.lockout.backoff_max_secs),
+        ] {
+            if value < 1 {
+                return Err(ConfigError::Invalid {
+                    field,
+                    message: format!("必须大于 0，当前为 {}", value),
+                });
+            }
+        }
+
+        if self.lockout.backoff_base_secs > self.lockout.backoff_max_secs {
+            return Err(ConfigError::Invalid {
+                field: "lockout.backoff_base_secs",
+                message: "不能大于 lockout.backoff_max_secs".to_string(),
+            });
+        }
+
+        if self.auth.retired_key_days < 1 {
+            return Err(ConfigError::Invalid {
+                field: "auth.retired_key_days",
+                message: format!("必须至少为 1 天，当前为 {}", self.auth.retired_key_days),
+            });
+        }
+
+        if self.session.heartbeat_secs == 0 {
+            return Err(ConfigError::Invalid {
+                field: "session.heartbeat_secs",
+                message: "必须大于 0".to_string(),
+            });
+        }
+
+        // 至少允许错过一次心跳，避免网络抖动时误关闭会话
+        if self.session.stale_after_secs < self.session.heartbeat_secs * 2 {
+            return Err(ConfigError::Invalid {
+                field: "session.stale_after_secs",
+                message: format!(
+                    "不能小于 session.heartbeat_secs 的两倍，当前为 {}",
+                    self.session.stale_after_secs
+                ),
+            });
+        }
+
+        if self.activity.sample_secs == 0 {
+            return Err(ConfigError::Invalid {
+                field: "activity.sample_secs",
+                message: "必须大于 0".to_string(),
+            });
+        }
+
+        for (field, value) in [
+            ("activity.idle_after_secs", self.activity.idle_after_secs),
+            ("activity.flush_secs", self.activity.flush_secs),
+        ] {
+            if value < self.activity.sample_secs {
+                return Err(ConfigError::Invalid {
+                    field,
+                    message: format!("不能小于 activity.sample_secs，当前为 {}", value),
+                });
+            }
+        }
+
+        if self.activity.event_flush_secs == 0 || self.activity.event_batch_size == 0 {
+            return Err(ConfigError::Invalid {
+                field: "activity.event_flush_secs",
+                message: "event_flush_secs 和 event_batch_size 必须大于 0".to_string(),
+            });
+        }
+
+        if self.activity.event_max_backoff_secs < self.activity.event_flush_secs {
+            return Err(ConfigError::Invalid {
+                field: "activity.event_max_backoff_secs",
+                message: "不能小于 activity.event_flush_secs".to_string(),
+            });
+        }
+
+        if self.analytics.timezone.parse::<chrono_tz::Tz>().is_err() {
+            return Err(ConfigError::Invalid {
+                field: "analytics.timezone",
+                message: format!("未知的时区: \"{}\"，请使用 IANA 时区名，如 Asia/Shanghai", self.analytics.timezone),
+            });
+        }
+
+        if self.report.enabled {
+            self.validate_report()?;
+        }
+
+        argon2::Params::new(
+            self.password.memory_kib,
+            self.password.iterations,
+            self.password.parallelism,
+            None,
+        )
+        .map_err(|e| ConfigError::Invalid {
+            field: "password",
+            message: e.to_string(),
+        })?;
+
+        Ok(())
+    }
+
+    fn validate_report(&self) -> Result<(), ConfigError> {
+        let report = &self.report;
+        if report.weekday.parse::<chrono::Weekday>().is_err() {
+            return Err(ConfigError::Invalid {
+                field: "report.weekday",
+                message: format!("无效的星期: \"{}\"，请使用 mon、tue 等英文缩写", report.weekday),
+            });
+        }
+
+        if report.hour > 23 {
+            return Err(ConfigError::Invalid {
+                field: "report.hour",
+                message: format!("必须在 0 到 23 之间，当前为 {}", report.hour),
+            });
+        }
+
+        if report.output_dir.is_none() && report.smtp.is_none() {
+            return Err(ConfigError::Invalid {
+                field: "report",
+                message: "启用周报时至少需要配置 output_dir 或 smtp".to_string(),
+            });
+        }
+
+        for (field, value, max) in [
+            ("report.top_tools", report.top_tools, 50),
+            ("report.inactive_days", report.inactive_days, 365),
+            ("report.inactive_limit", report.inactive_limit, 1000),
+        ] {
+            if !(1..=max).contains(&value) {
+                return Err(ConfigError::Invalid {
+                    field,
+                    message: format!("必须在 1 到 {} 之间，当前为 {}", max, value),
+                });
+            }
+        }
+
+        if let Some(smtp) = &report.smtp {
+            if smtp.host.trim().is_empty() || smtp.port == 0 {
+                return Err(ConfigError::Invalid {
+                    field: "report.smtp.host",
+                    message: "SMTP 服务器地址和端口不能为空".to_string(),
+                });
+            }
+            if smtp.to.is_empty() {
+                return Err(ConfigError::Invalid {
+                    field: "report.smtp.to",
+                    message: "至少需要一个收件人".to_string(),
+                });
+            }
+            for address in std::iter::once(&smtp.from).chain(&smtp.to) {
+                if address.parse::<lettre::message::Mailbox>().is_err() {
+                    return Err(ConfigError::Invalid {
+                        field: "report.smtp",
+                        message: format!("无效的邮箱地址: \"{}\"", address),
+                    });
+                }
+            }
+            if smtp.timeout_secs == 0 {
+                return Err(ConfigError::Invalid {
+                    field: "report.smtp.timeout_secs",
+                    message: "必须大于 0".to_string(),
+                });
+            }
+        }
+        Ok(())
+    }
+}
+
+fn env_string(var: &str) -> Option<String> {
+    std::env::var(var).ok().filter(|v| !v.trim().is_empty())
+}
+
+fn env_i64(var: &'static str) -> Result<Option<i64>, ConfigError> {
+    match env_string(var) {
+        Some(value) => value
+            .trim()
+            .parse::<i64>()
+            .map(Some)
+            .map_err(|e| ConfigError::InvalidEnv {
+                var,
+                message: format!("\"{}\" 不是有效的整数: {}", value, e),
+            }),
+        None => Ok(None),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mongo_uri_is_required() {
+        let mut config = AppConfig::default();
+        assert!(matches!(config.validate(), Err(ConfigError::Invalid { field: "mongo.uri", .. })));
+
+        config.mongo.uri = "mongodb://localhost:27017".to_string();
+        config.validate().unwrap();
+    }
+
+    fn valid() -> AppConfig {
+        let mut config = AppConfig::default();
+        config.mongo.uri = "mongodb://localhost:27017".to_string();
+        config
+    }
+
+    fn smtp() -> SmtpConfig {
+        SmtpConfig {
+            host: "smtp.example.com".to_string(),
+            from: "report@example.com".to_string(),
+            to: vec!["admin@example.com".to_string()],
+            ..Default::default()
+        }
+    }
+
+    // 每项修改使一个字段校验失败
+    type Mutation = fn(&mut AppConfig);
+
+    #[test]
+    fn validate_rejects_each_invalid_field() {
+        let cases: Vec<(&str, Mutation)> = vec![
+            ("mongo.uri", |c| c.mongo.uri = "localhost:27017".to_string()),
+            ("mongo.database", |c| c.mongo.database = " ".to_string()),
+            ("mongo.database", |c| c.mongo.database = "tools.prod".to_string()),
+            ("mongo.database", |c| c.mongo.database = "a".repeat(64)),
+            ("auth.jwt_secret", |c| c.auth.jwt_secret = Some(Secret::new("short".to_string()))),
+            ("auth.signing_keys_dir", |c| c.auth.signing_keys_dir = PathBuf::new()),
+            ("auth.remember_me_days", |c| c.auth.remember_me_days = 0),
+            ("auth.auto_login_days", |c| c.auth.auto_login_days = MAX_TOKEN_DAYS + 1),
+            ("password.min_length", |c| c.password.min_length = 0),
+            ("password.min_length", |c| c.password.min_length = 129),
+            ("password.min_char_classes", |c| c.password.min_char_classes = 5),
+            ("password.history_size", |c| c.password.history_size = MAX_PASSWORD_HISTORY_SIZE + 1),
+            ("lockout.max_failures", |c| c.lockout.max_failures = 0),
+            ("lockout.window_minutes", |c| c.lockout.window_minutes = 0),
+            ("lockout.lockout_minutes", |c| c.lockout.lockout_minutes = -1),
+            ("lockout.backoff_base_secs", |c| c.lockout.backoff_base_secs = 0),
+            ("lockout.backoff_max_secs", |c| c.lockout.backoff_max_secs = 0),
+            ("lockout.backoff_base_secs", |c| {
+                c.lockout.backoff_base_secs = 60;
+                c.lockout.backoff_max_secs = 30;
+            }),
+            ("auth.retired_key_days", |c| c.auth.retired_key_days = 0),
+            ("session.heartbeat_secs", |c| c.session.heartbeat_secs = 0),
+            ("session.stale_after_secs", |c| c.session.stale_after_secs = c.session.heartbeat_secs * 2 - 1),
+            ("activity.sample_secs", |c| c.activity.sample_secs = 0),
+            ("activity.idle_after_secs", |c| c.activity.idle_after_secs = c.activity.sample_secs - 1),
+            ("activity.flush_secs", |c| c.activity.flush_secs = c.activity.sample_secs - 1),
+            ("activity.event_flush_secs", |c| c.activity.event_flush_secs = 0),
+            ("activity.event_flush_secs", |c| c.activity.event_batch_size = 0),
+            ("activity.event_max_backoff_secs", |c| {
+                c.activity.event_max_backoff_secs = c.activity.event_flush_secs - 1
+            }),
+            ("analytics.timezone", |c| c.analytics.timezone = "Asia/Nowhere".to_string()),
+            ("password", |c| c.password.memory_kib = 1),
+            ("report", |c| c.report.enabled = true),
+            ("report.weekday", |c| {
+                c.report.enabled = true;
+                c.report.output_dir = Some(PathBuf::from("reports"));
+                c.report.weekday = "someday".to_string();
+            }),
+            ("report.hour", |c| {
+                c.report.enabled = true;
+                c.report.output_dir = Some(PathBuf::from("reports"));
+                c.report.hour = 24;
+            }),
+            ("report.top_tools", |c| {
+                c.report.enabled = true;
+                c.report.output_dir = Some(PathBuf::from("reports"));
+                c.report.top_tools = 0;
+            }),
+            ("report.inactive_days", |c| {
+                c.report.enabled = true;
+                c.report.output_dir = Some(PathBuf::from("reports"));
+                c.report.inactive_days = 366;
+            }),
+            ("report.inactive_limit", |c| {
+                c.report.enabled = true;
+                c.report.output_dir = Some(PathBuf::from("reports"));
+                c.report.inactive_limit = 1001;
+            }),
+            ("report.smtp.host", |c| {
+                c.report.enabled = true;
+                c.report.smtp = Some(SmtpConfig { host: String::new(), ..smtp() });
+            }),
+            ("report.smtp.host", |c| {
+                c.report.enabled = true;
+                c.report.smtp = Some(SmtpConfig { port: 0, ..smtp() });
+            }),
+            ("report.smtp.to", |c| {
+                c.report.enabled = true;
+                c.report.smtp = Some(SmtpConfig { to: Vec::new(), ..smtp() });
+            }),
+            ("report.smtp", |c| {
+                c.report.enabled = true;
+                c.report.smtp = Some(SmtpConfig { from: "not an address".to_string(), ..smtp() });
+            }),
+            ("report.smtp.timeout_secs", |c| {
+                c.report.enabled = true;
+                c.report.smtp = Some(SmtpConfig { timeout_secs: 0, ..smtp() });
+            }),
+        ];
+
+        for (expected, mutate) in cases {
+            let mut config = valid();
+            mutate(&mut config);
+            match config.validate() {
+                Err(ConfigError::Invalid { field, .. }) => assert_eq!(field, expected),
+                other => panic!("期望 {} 校验失败，实际为 {:?}", expected, other),
+            }
+        }
+
+        // 禁用周报时不校验周报配置
+        let mut config = valid();
+        config.report.hour = 24;
+        config.validate().unwrap();
+
+        // 周报只配置邮件投递也可以
+        let mut config = valid();
+        config.report.enabled = true;
+        config.report.smtp = Some(smtp());
+        config.validate().unwrap();
+    }
+
+    // 恢复测试修改过的环境变量
+    struct EnvGuard(Vec<&'static str>);
+
+    impl EnvGuard {
+        fn set(&mut self, var: &'static str, value: &str) {
+            std::env::set_var(var, value);
+            self.0.push(var);
+        }
+    }
+
+    impl Drop for EnvGuard {
+        fn drop(&mut self) {
+            for var in &self.0 {
+                std::env::remove_var(var);
+            }
+        }
+    }
+
+    // 环境变量为进程共享，覆盖规则都放在这一个测试里，避免并行测试相互干扰
+    #[test]
+    fn environment_overrides_file_which_overrides_defaults() {
+        let dir = std::env::temp_dir().join(format!("chengshang-test-{}", uuid::Uuid::new_v4().simple()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(
+            dir.join(CONFIG_FILE_NAME),
+            r#"
+[mongo]
+uri = "mongodb://file:27017"
+database = "from_file"
+
+[auth]
+signing_keys_dir = "keys"
+remember_me_days = 10
+auto_login_days = 3
+"#,
+        )
+        .unwrap();
+        let mut env = EnvGuard(Vec::new());
+
+        // 只有配置文件：文件中的值覆盖默认值，未写的项保持默认，相对路径按配置目录解析
+        let config = AppConfig::load(&dir).unwrap();
+        assert_eq!(config.mongo.uri, "mongodb://file:27017");
+        assert_eq!(config.mongo.database, "from_file");
+        assert_eq!(config.auth.remember_me_days, 10);
+        assert_eq!(config.auth.auto_login_days, 3);
+        assert_eq!(config.auth.signing_keys_dir, dir.join("keys"));
+        assert_eq!(config.session.heartbeat_secs, DEFAULT_HEARTBEAT_SECS);
+
+        // 环境变量覆盖文件中的值，空白的环境变量视为未设置
+        env.set(ENV_MONGO_URI, "mongodb://env:27017");
+        env.set(ENV_REMEMBER_ME_DAYS, " 20 ");
+        env.set(ENV_AUTO_LOGIN_DAYS, "  ");
+        let config = AppConfig::load(&dir).unwrap();
+        assert_eq!(config.mongo.uri, "mongodb://env:27017");
+        assert_eq!(config.mongo.database, "from_file");
+        assert_eq!(config.auth.remember_me_days, 20);
+        assert_eq!(config.auth.auto_login_days, 3);
+
+        // 覆盖后的值同样需要通过校验
+        env.set(ENV_REMEMBER_ME_DAYS, "0");
+        assert!(matches!(
+            AppConfig::load(&dir),
+            Err(ConfigError::Invalid { field: "auth.remember_me_days", .. })
+        ));
+        env.set(ENV_AUTO_LOGIN_DAYS, "abc");
+        assert!(matches!(
+            AppConfig::load(&dir),
+            Err(ConfigError::InvalidEnv { var: ENV_AUTO_LOGIN_DAYS, .. })
+        ));
+        env.set(ENV_REMEMBER_ME_DAYS, "20");
+        env.set(ENV_AUTO_LOGIN_DAYS, "5");
+
+        // 指定的配置文件不存在时只使用默认值和环境变量
+        env.set(ENV_CONFIG_PATH, dir.join("missing.toml").to_str().unwrap());
+        let config = AppConfig::load(&dir).unwrap();
+        assert_eq!(config.mongo.uri, "mongodb://env:27017");
+        assert_eq!(config.mongo.database, DEFAULT_MONGO_DATABASE);
+        assert_eq!(config.auth.signing_keys_dir, dir.join(DEFAULT_SIGNING_KEYS_DIR));
+        assert_eq!(config.auth.auto_login_days, 5);
+
+        drop(env);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
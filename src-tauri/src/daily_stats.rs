@@ -0,0 +1,396 @@
+use chrono::NaiveDate;
+use chrono_tz::Tz;
+use mongodb::{
+    bson::{doc, oid::ObjectId, Bson, DateTime, Document},
+    options::IndexOptions,
+    IndexModel,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::auth::{AppState, MongoManager};
+use crate::guard::{require, Permission};
+use crate::metrics;
+use crate::tool_access;
+
+// 重建时的临时汇总集合
+pub const REBUILD_COLLECTION: &str = "daily_stats_rebuild";
+
+// 用户当天合计记录的 toolId。工具ID从1开始，$merge 的匹配字段不能为 null
+pub const USER_TOTAL: i32 = 0;
+
+// 每日统计汇总（集合 daily_stats）：按 业务日期 + 用户 + 工具 汇总，
+// toolId 为 USER_TOTAL 的记录是该用户当天的合计。统计页面只读汇总，不再扫描原始记录
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DailyStat {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    pub id: Option<ObjectId>,
+    // 业务时区当天零点
+    pub date: DateTime,
+    #[serde(rename = "userId")]
+    pub user_id: ObjectId,
+    #[serde(rename = "toolId")]
+    pub tool_id: i32,
+    #[serde(default)]
+    pub sessions: i64,
+    // 已结束且有时长的会话数和总时长，按登录当天计入，用于平均会话时长
+    #[serde(rename = "endedSessions", default)]
+    pub ended_sessions: i64,
+    #[serde(rename = "sessionSecs", default)]
+    pub session_secs: i64,
+    #[serde(default)]
+    pub clicks: i64,
+    #[serde(rename = "usageSecs", default)]
+    pub usage_secs: i64,
+}
+
+// 重建结果响应结构
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RebuildDailyStatsResponse {
+    #[serde(rename = "startDate")]
+    pub start_date: Option<String>,
+    #[serde(rename = "endDate")]
+    pub end_date: Option<String>,
+    // 重建后范围内的汇总记录数
+    pub rows: u64,
+}
+
+pub async fn ensure_indexes(mongo: &MongoManager) -> Result<(), String> {
+    let indexes = [
+        summary_key(),
+        IndexModel::builder()
+            .keys(doc! {"toolId": 1, "date": 1})
+            .build(),
+        IndexModel::builder()
+            .keys(doc! {"userId": 1, "date": -1})
+            .build(),
+    ];
+
+    // 旧版本用 toolId 为 null 表示用户合计
+    mongo.daily_stats()
+        .update_many(doc! {"toolId": null}, doc! {"$set": {"toolId": USER_TOTAL}})
+        .await
+        .map_err(|e| format!("迁移每日统计失败: {}", e))?;
+    mongo.daily_stats()
+        .create_indexes(indexes)
+        .await
+        .map_err(|e| format!("创建每日统计索引失败: {}", e))?;
+    Ok(())
+}
+
+// 累加某用户某天的统计，tool_id 为 None 时累加用户当天合计
+pub async fn add(
+    mongo: &MongoManager,
+    tz: Tz,
+    user_id: ObjectId,
+    tool_id: Option<i32>,
+    at: DateTime,
+    inc: Document,
+) -> Result<(), String> {
+    mongo.daily_stats()
+        .update_one(
+            doc! {
+                "date": metrics::business_day(tz, at),
+                "userId": user_id,
+                "toolId": tool_id.unwrap_or(USER_TOTAL)
+            },
+            doc! {"$inc": inc},
+        )
+        .upsert(true)
+        .await
+        .map_err(|e| format!("更新每日统计失败: {}", e))?;
+    Ok(())
+}
+
+// 原始记录按业务日期分组的表达式
+fn day_of(field: &str, tz: Tz) -> Document {
+    doc! {"$dateTrunc": {"date": format!("${}", field), "unit": "day", "timezone": tz.name()}}
+}
+
+fn summary_key() -> IndexModel {
+    IndexModel::builder()
+        .keys(doc! {"date": 1, "userId": 1, "toolId": 1})
+        .options(IndexOptions::builder().unique(true).build())
+        .build()
+}
+
+// 写入临时集合的 $merge 阶段；同一条汇总记录的各字段分别来自不同的原始集合
+fn merge_stage() -> Document {
+    doc! {
+        "$merge": {
+            "into": REBUILD_COLLECTION,
+            "on": ["date", "userId", "toolId"],
+            "whenMatched": "merge",
+            "whenNotMatched": "insert"
+        }
+    }
+}
+
+fn time_filter(field: &str, from: Option<DateTime>, to: Option<DateTime>) -> Document {
+    let mut range = doc! {"$exists": true};
+    if let Some(from) = from {
+        range.insert("$gte", from);
+    }
+    if let Some(to) = to {
+        range.insert("$lt", to);
+    }
+    doc! {field: range}
+}
+
+// 从原始会话、活动事件和使用时长明细重新计算 [from, to) 范围内的汇总。
+// 先汇总到临时集合，全部成功后再替换 daily_stats 中的记录，失败时原有汇总保持不变
+pub async fn rebuild(
+    mongo: &MongoManager,
+    tz: Tz,
+    from: Option<DateTime>,
+    to: Option<DateTime>,
+) -> Result<u64, String> {
+    let range = time_filter("date", from, to);
+    let staging = mongo.daily_stats_rebuild();
+    staging
+        .drop()
+        .await
+        .map_err(|e| format!("清理临时汇总失败: {}", e))?;
+    staging
+        .create_index(summary_key())
+        .await
+        .map_err(|e| format!("创建临时汇总索引失败: {}", e))?;
+
+    // 会话数和已结束会话的时长（用户合计）
+    let mut sessions_match = time_filter("loginAt", from, to);
+    sessions_match.insert("userId", doc! {"$exists": true});
+    let ended = doc! {"$gt": ["$sessionDuration", 0]};
+    let sessions = vec![
+        doc! {"$match": sessions_match},
+        doc! {
+            "$group": {
+                "_id": {"date": day_of("loginAt", tz), "userId": "$userId"},
+                "sessions": {"$sum": 1},
+                "endedSessions": {"$sum": {"$cond": [ended.clone(), 1, 0]}},
+                "sessionSecs": {"$sum": {"$cond": [ended, "$sessionDuration", 0]}}
+            }
+        },
+        doc! {
+            "$project": {
+                "_id": 0,
+                "date": "$_id.date",
+                "userId": "$_id.userId",
+                "toolId": {"$literal": USER_TOTAL},
+                "sessions": 1,
+                "endedSessions": 1,
+                "sessionSecs": 1
+            }
+        },
+        merge_stage(),
+    ];
+
+    // 打开工具次数（工具记录和用户合计），与上传时一样不计无权使用的工具和已删除的用户
+    let mut clicks_match = time_filter("timestamp", from, to);
+    clicks_match.insert("type", "tool_opened");
+    clicks_match.extend(tool_access::counted_opens_filter(mongo).await?);
+    let mut clicks = Vec::new();
+    for tool_id in [Bson::from("$toolId"), Bson::from(doc! {"$literal": USER_TOTAL})] {
+        clicks.push(vec![
+            doc! {"$match": clicks_match.clone()},
+            doc! {"$group": {"_id": {"date": day_of("timestamp", tz), "userId": "$userId", "toolId": tool_id}, "clicks": {"$sum": 1}}},
+            doc! {"$project": {"_id": 0, "date": "$_id.date", "userId": "$_id.userId", "toolId": "$_id.toolId", "clicks": 1}},
+            merge_stage(),
+        ]);
+    }
+
+    // 前台使用时长（用户合计和各工具）
+    let usage_match = time_filter("recordedAt", from, to);
+    let usage_total = vec![
+        doc! {"$match": usage_match.clone()},
+        doc! {"$group": {"_id": {"date": day_of("recordedAt", tz), "userId": "$userId"}, "usageSecs": {"$sum": "$totalSecs"}}},
+        doc! {"$project": {"_id": 0, "date": "$_id.date", "userId": "$_id.userId", "toolId": {"$literal": USER_TOTAL}, "usageSecs": 1}},
+        merge_stage(),
+    ];
+    let usage_tools = vec![
+        doc! {"$match": usage_match},
+        doc! {"$unwind": "$tools"},
+        doc! {"$group": {"_id": {"date": day_of("recordedAt", tz), "userId": "$userId", "toolId": "$tools.toolId"}, "usageSecs": {"$sum": "$tools.secs"}}},
+        doc! {"$project": {"_id": 0, "date": "$_id.date", "userId": "$_id.userId", "toolId": "$_id.toolId", "usageSecs": 1}},
+        merge_stage(),
+    ];
+
+    mongo.user_sessions()
+        .aggregate(sessions)
+        .await
+        .map_err(|e| format!("汇总会话数失败: {}", e))?;
+    for pipeline in clicks {
+        mongo.activity_events()
+            .aggregate(pipeline)
+            .await
+            .map_err(|e| format!("汇总工具点击失败: {}", e))?;
+    }
+    for pipeline in [usage_total, usage_tools] {
+        mongo.usage_time()
+            .aggregate(pipeline)
+            .await
+            .map_err(|e| format!("汇总使用时长失败: {}", e))?;
+    }
+
+    // 用新的汇总覆盖范围内的记录，再删除本次没有写入的旧记录
+    let rebuilt_at = DateTime::now();
+    staging
+        .aggregate(vec![
+            doc! {"$unset": "_id"},
+            doc! {"$set": {"rebuiltAt": rebuilt_at}},
+            doc! {
+                "$merge": {
+                    "into": "daily_stats",
+                    "on": ["date", "userId", "toolId"],
+                    "whenMatched": "replace",
+                    "whenNotMatched": "insert"
+                }
+            },
+        ])
+        .await
+        .map_err(|e| format!("写入每日统计失败: {}", e))?;
+    let mut stale = range.clone();
+    stale.insert("rebuiltAt", doc! {"$ne": rebuilt_at});
+    mongo.daily_stats()
+        .delete_many(stale)
+        .await
+        .map_err(|e| format!("清理每日统计失败: {}", e))?;
+    staging
+        .drop()
+        .await
+        .map_err(|e| format!("清理临时汇总失败: {}", e))?;
+
+    mongo.daily_stats()
+        .count_documents(range)
+        .await
+        .map_err(|e| format!("统计汇总记录数失败: {}", e))
+}
+
+// 重新计算用户累计点击数（用于用户分析排序），以各工具的累计使用记录为准。
+// 累计值不分日期，只在全量重建或清除测试数据后执行，按日期范围重建时不受影响
+pub async fn recompute_totals(mongo: &MongoManager) -> Result<(), String> {
+    mongo.users()
+        .update_many(doc! {}, doc! {"$set": {"totalToolClicks": 0}})
+        .await
+        .map_err(|e| format!("重置用户点击数失败: {}", e))?;
+    mongo.tool_usage()
+        .aggregate(vec![
+            doc! {"$group": {"_id": "$userId", "totalToolClicks": {"$sum": "$clickCount"}}},
+            doc! {"$merge": {"into": "users", "on": "_id", "whenMatched": "merge", "whenNotMatched": "discard"}},
+        ])
+        .await
+        .map_err(|e| format!("汇总用户点击数失败: {}", e))?;
+    Ok(())
+}
+
+// 从原始记录重建每日统计汇总；不指定日期时重建全部历史并重新计算用户累计点击数。
+// 更改业务时区后需要重建，重建期间写入的数据可能重复或遗漏，建议在空闲时执行
+#[tauri::command]
+pub async fn rebuild_daily_stats(
+    start_date: Option<String>,
+    end_date: Option<String>,
+    state: tauri::State<'_, AppState>,
+) -> Result<RebuildDailyStatsResponse, String> {
+    let current_user = require(&state, Permission::ManageUsers).await?;
+    let tz = state.config.analytics.tz();
+
+    let parse_date = |value: &Option<String>| -> Result<Option<NaiveDate>, String> {
+        value
+            .as_deref()
+            .map(|value| {
+                NaiveDate::parse_from_str(value.trim(), "%Y-%m-%d")
+                    .map_err(|e| format!("无效的日期 {}: {}", value, e))
+            })
+            .transpose()
+    };
+    let start = parse_date(&start_date)?;
+    let end = parse_date(&end_date)?;
+    if let (Some(start), Some(end)) = (start, end) {
+        if start > end {
+            return Err("开始日期不能晚于结束日期".to_string());
+        }
+    }
+
+    let from = start.map(|date| metrics::day_start(tz, date));
+    let to = end.map(|date| metrics::day_start(tz, date + chrono::Duration::days(1)));
+
+    let mongo = state.mongo.read().await;
+    let rows = rebuild(&mongo, tz, from, to).await?;
+    if from.is_none() && to.is_none() {
+        recompute_totals(&mongo).await?;
+    }
+
+    log::info!(
+        "🔄 {} 重建每日统计: {} ~ {}，共 {} 条",
+        current_user.username,
+        start_date.as_deref().unwrap_or("最早"),
+        end_date.as_deref().unwrap_or("今天"),
+        rows
+    );
+    Ok(RebuildDailyStatsResponse { start_date, end_date, rows })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::TryStreamExt;
+
+    use crate::auth::UserSession;
+    use crate::session::SessionEndReason;
+    use crate::test_support::TestDb;
+
+    const TZ: Tz = chrono_tz::Asia::Shanghai;
+
+    fn day(value: &str) -> DateTime {
+        metrics::day_start(TZ, NaiveDate::parse_from_str(value, "%Y-%m-%d").unwrap())
+    }
+
+    fn session(user_id: ObjectId, login_at: DateTime, duration: Option<i64>) -> UserSession {
+        UserSession {
+            id: None,
+            user_id,
+            login_at,
+            logout_at: duration.map(|secs| DateTime::from_millis(login_at.timestamp_millis() + secs * 1000)),
+            session_duration: duration,
+            device_id: None,
+            last_heartbeat_at: None,
+            end_reason: duration.map(|_| SessionEndReason::Logout),
+        }
+    }
+
+    #[tokio::test]
+    #[ignore = "需要本机 mongod"]
+    async fn ranged_rebuild_sums_sessions_and_keeps_lifetime_totals() {
+        let db = TestDb::new("daily_stats_rebuild").await;
+        let user_id = ObjectId::new();
+        db.mongo.users()
+            .clone_with_type::<Document>()
+            .insert_one(doc! {"_id": user_id, "username": "alice", "totalToolClicks": 42_i64})
+            .await
+            .unwrap();
+        db.mongo.user_sessions()
+            .insert_many([
+                session(user_id, day("2024-01-02"), Some(600)),
+                session(user_id, day("2024-01-02"), Some(1200)),
+                session(user_id, day("2024-01-02"), None),
+                session(user_id, day("2024-01-05"), Some(300)),
+            ])
+            .await
+            .unwrap();
+
+        rebuild(&db.mongo, TZ, Some(day("2024-01-01")), Some(day("2024-01-03"))).await.unwrap();
+
+        let stats: Vec<DailyStat> = db.mongo.daily_stats()
+            .find(doc! {})
+            .await
+            .unwrap()
+            .try_collect()
+            .await
+            .unwrap();
+        assert_eq!(stats.len(), 1);
+        assert_eq!((stats[0].sessions, stats[0].ended_sessions, stats[0].session_secs), (3, 2, 1800));
+
+        // 按范围重建不改动用户累计点击数
+        let user = db.mongo.users().clone_with_type::<Document>().find_one(doc! {"_id": user_id}).await.unwrap().unwrap();
+        assert_eq!(user.get_i64("totalToolClicks").unwrap(), 42);
+        db.drop().await;
+    }
+}
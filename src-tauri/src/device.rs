@@ -0,0 +1,268 @@
+use futures::TryStreamExt;
+use mongodb::{
+    bson::{doc, oid::ObjectId, DateTime},
+    options::IndexOptions,
+    IndexModel,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::path::Path;
+
+use crate::auth::{AppState, MongoManager};
+use crate::guard::{current_caller, require_any, require_target, Permission};
+use crate::refresh_token;
+
+// 设备标识文件名（位于应用数据目录下）
+const DEVICE_ID_FILE: &str = "device_id";
+const MAX_DEVICE_NAME_LEN: usize = 50;
+
+// 本机设备信息，启动时由Rust端采集
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DeviceInfo {
+    #[serde(rename = "deviceId")]
+    pub device_id: String,
+    pub hostname: String,
+    pub os: String,
+    #[serde(rename = "appVersion")]
+    pub app_version: String,
+}
+
+impl DeviceInfo {
+    pub fn collect(data_dir: &Path, app_version: String) -> Result<Self, String> {
+        let hostname = hostname::get()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_else(|e| {
+                log::warn!("⚠️ 获取主机名失败: {}", e);
+                "未知设备".to_string()
+            });
+
+        let info = os_info::get();
+        Ok(DeviceInfo {
+            device_id: load_or_create_device_id(data_dir)?,
+            hostname,
+            os: format!("{} {} ({})", info.os_type(), info.version(), std::env::consts::ARCH),
+            app_version,
+        })
+    }
+}
+
+// 用户登录过的设备（集合 user_devices，每个用户/设备一条）
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct UserDevice {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    pub id: Option<ObjectId>,
+    #[serde(rename = "userId")]
+    pub user_id: ObjectId,
+    #[serde(rename = "deviceId")]
+    pub device_id: String,
+    // 用户自定义名称，默认为主机名
+    pub name: String,
+    pub hostname: String,
+    pub os: String,
+    #[serde(rename = "appVersion")]
+    pub app_version: String,
+    #[serde(rename = "firstSeenAt")]
+    pub first_seen_at: DateTime,
+    #[serde(rename = "lastSeenAt")]
+    pub last_seen_at: DateTime,
+}
+
+// 设备响应结构
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct DeviceResponse {
+    #[serde(rename = "deviceId")]
+    pub device_id: String,
+    pub name: String,
+    pub hostname: String,
+    pub os: String,
+    #[serde(rename = "appVersion")]
+    pub app_version: String,
+    #[serde(rename = "firstSeenAt")]
+    pub first_seen_at: String,
+    #[serde(rename = "lastSeenAt")]
+    pub last_seen_at: String,
+    // 是否为当前这台电脑
+    pub current: bool,
+    // 是否持有有效的记住我/自动登录Token
+    #[serde(rename = "hasActiveToken")]
+    pub has_active_token: bool,
+}
+
+// 读取本机设备标识，首次运行时生成并持久化
+pub fn load_or_create_device_id(data_dir: &Path) -> Result<String, String> {
+    let path = data_dir.join(DEVICE_ID_FILE);
+
+    if let Ok(content) = std::fs::read_to_string(&path) {
+        let device_id = content.trim();
+        if uuid::Uuid::parse_str(device_id).is_ok() {
+            return Ok(device_id.to_string());
+        }
+        log::warn!("⚠️ 设备标识文件内容无效，重新生成: {}", path.display());
+    }
+
+    let device_id = uuid::Uuid::new_v4().to_string();
+    std::fs::create_dir_all(data_dir)
+        .map_err(|e| format!("创建应用数据目录失败: {}", e))?;
+    std::fs::write(&path, &device_id)
+        .map_err(|e| format!("保存设备标识失败: {}", e))?;
+
+    log::info!("🖥️ 已生成新的设备标识: {}", device_id);
+    Ok(device_id)
+}
+
+pub async fn ensure_indexes(mongo: &MongoManager) -> Result<(), String> {
+    mongo.user_devices()
+        .create_index(
+            IndexModel::builder()
+                .keys(doc! {"userId": 1, "deviceId": 1})
+                .options(IndexOptions::builder().unique(true).build())
+                .build(),
+        )
+        .await
+        .map_err(|e| format!("创建设备索引失败: {}", e))?;
+    Ok(())
+}
+
+// 登录或恢复会话时记录本机设备
+pub async fn touch(mongo: &MongoManager, user_id: ObjectId, device: &DeviceInfo) -> Result<(), String> {
+    let now = DateTime::now();
+    mongo.user_devices()
+        .update_one(
+            doc! {"userId": user_id, "deviceId": &device.device_id},
+            doc! {
+                "$set": {
+                    "hostname": &device.hostname,
+                    "os": &device.os,
+                    "appVersion": &device.app_version,
+                    "lastSeenAt": now
+                },
+                "$setOnInsert": {
+                    "name": &device.hostname,
+                    "firstSeenAt": now
+                }
+            },
+        )
+        .upsert(true)
+        .await
+        .map_err(|e| format!("记录登录设备失败: {}", e))?;
+    Ok(())
+}
+
+async fn load_devices(
+    mongo: &MongoManager,
+    user_id: ObjectId,
+    current_device_id: Option<&str>,
+) -> Result<Vec<DeviceResponse>, String> {
+    let cursor = mongo.user_devices()
+        .find(doc! {"userId": user_id})
+        .sort(doc! {"lastSeenAt": -1})
+        .await
+        .map_err(|e| format!("查询设备失败: {}", e))?;
+    let devices: Vec<UserDevice> = cursor.try_collect().await.map_err(|e| format!("读取设备失败: {}", e))?;
+
+    let active: HashSet<String> = refresh_token::active_device_ids(mongo, user_id).await?;
+
+    Ok(devices
+        .into_iter()
+        .map(|device| DeviceResponse {
+            current: current_device_id == Some(device.device_id.as_str()),
+            has_active_token: active.contains(&device.device_id),
+            device_id: device.device_id,
+            name: device.name,
+            hostname: device.hostname,
+            os: device.os,
+            app_version: device.app_version,
+            first_seen_at: device.first_seen_at.try_to_rfc3339_string().unwrap_or_default(),
+            last_seen_at: device.last_seen_at.try_to_rfc3339_string().unwrap_or_default(),
+        })
+        .collect())
+}
+
+// 获取当前用户登录过的设备
+#[tauri::command]
+pub async fn list_my_devices(
+    state: tauri::State<'_, AppState>,
+) -> Result<Vec<DeviceResponse>, String> {
+    let current_user = current_caller(&state).await?;
+    let user_id = ObjectId::parse_str(&current_user.id).map_err(|e| format!("用户ID解析失败: {}", e))?;
+
+    let mongo = state.mongo.read().await;
+    load_devices(&mongo, user_id, Some(&state.device.device_id)).await
+}
+
+// 重命名自己的设备
+#[tauri::command]
+pub async fn rename_device(
+    device_id: String,
+    name: String,
+    state: tauri::State<'_, AppState>,
+) -> Result<(), String> {
+    let current_user = current_caller(&state).await?;
+    let user_id = ObjectId::parse_str(&current_user.id).map_err(|e| format!("用户ID解析失败: {}", e))?;
+
+    let name = name.trim();
+    if name.is_empty() || name.chars().count() > MAX_DEVICE_NAME_LEN {
+        return Err(format!("设备名称长度需在1到{}个字符之间", MAX_DEVICE_NAME_LEN));
+    }
+
+    let mongo = state.mongo.read().await;
+    let result = mongo.user_devices()
+        .update_one(
+            doc! {"userId": user_id, "deviceId": &device_id},
+            doc! {"$set": {"name": name}},
+        )
+        .await
+        .map_err(|e| format!("重命名设备失败: {}", e))?;
+
+    if result.matched_count == 0 {
+        return Err("设备不存在".to_string());
+    }
+    Ok(())
+}
+
+// 撤销自己某台设备的记住我/自动登录Token，该设备下次启动需重新登录
+#[tauri::command]
+pub async fn revoke_device(
+    device_id: String,
+    state: tauri::State<'_, AppState>,
+) -> Result<(), String> {
+    let current_user = current_caller(&state).await?;
+    let user_id = ObjectId::parse_str(&current_user.id).map_err(|e| format!("用户ID解析失败: {}", e))?;
+
+    let mongo = state.mongo.read().await;
+    let exists = mongo.user_devices()
+        .find_one(doc! {"userId": user_id, "deviceId": &device_id})
+        .await
+        .map_err(|e| format!("查询设备失败: {}", e))?
+        .is_some();
+    if !exists {
+        return Err("设备不存在".to_string());
+    }
+
+    let revoked = refresh_token::revoke_device_tokens(&mongo, user_id, &device_id).await?;
+    log::info!("🔒 用户 {} 撤销了设备 {} 的Token ({} 个)", current_user.username, device_id, revoked);
+    Ok(())
+}
+
+// 查看指定用户登录过的设备 - 管理员功能
+#[tauri::command]
+pub async fn list_user_devices(
+    user_id: String,
+    state: tauri::State<'_, AppState>,
+) -> Result<Vec<DeviceResponse>, String> {
+    let current_user = require_any(&state, &[Permission::ManageUsers, Permission::ManageDepartmentUsers]).await?;
+
+    let user_object_id = ObjectId::parse_str(&user_id)
+        .map_err(|e| format!("无效的用户ID: {}", e))?;
+
+    let mongo = state.mongo.read().await;
+    let target = mongo.users()
+        .find_one(doc! {"_id": user_object_id})
+        .await
+        .map_err(|e| format!("查询用户失败: {}", e))?
+        .ok_or("用户不存在")?;
+
+    require_target(&mongo, &current_user, &target).await?;
+
+    load_devices(&mongo, user_object_id, None).await
+}
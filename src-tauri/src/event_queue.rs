@@ -0,0 +1,407 @@
+use chrono::Utc;
+use chrono_tz::Tz;
+use serde::{Deserialize, Serialize};
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::Duration;
+
+use crate::activity::{self, ActivityRecord};
+use crate::auth::{AppState, MongoManager};
+use crate::guard::current_caller;
+
+// 本地事件队列文件名（位于应用数据目录下），每行一条JSON
+const QUEUE_FILE: &str = "activity_queue.jsonl";
+
+// 队列状态响应结构
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct EventQueueStatus {
+    // 尚未上传的事件数
+    pub depth: usize,
+    #[serde(rename = "lastFlushAt")]
+    pub last_flush_at: Option<String>,
+    #[serde(rename = "lastError")]
+    pub last_error: Option<String>,
+    #[serde(rename = "nextRetryAt")]
+    pub next_retry_at: Option<String>,
+}
+
+// 持久化的活动事件队列：事件先追加到本地文件，由后台任务批量上传，
+// 断网或数据库不可用时事件保留在本地，恢复后继续上传
+pub struct EventQueue {
+    path: PathBuf,
+    // 串行化文件读写
+    file: tokio::sync::Mutex<()>,
+    status: Mutex<EventQueueStatus>,
+}
+
+impl EventQueue {
+    pub fn open(data_dir: &Path) -> Result<Self, String> {
+        std::fs::create_dir_all(data_dir)
+            .map_err(|e| format!("创建应用数据目录失败: {}", e))?;
+        let path = data_dir.join(QUEUE_FILE);
+
+        let depth = repair(&path)?;
+        if depth > 0 {
+            log::info!("📦 本地事件队列中有 {} 条待上传事件", depth);
+        }
+
+        Ok(EventQueue {
+            path,
+            file: tokio::sync::Mutex::new(()),
+            status: Mutex::new(EventQueueStatus { depth, ..EventQueueStatus::default() }),
+        })
+    }
+
+    fn update_status(&self, f: impl FnOnce(&mut EventQueueStatus)) {
+        let mut status = self.status.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        f(&mut status);
+    }
+
+    pub fn status(&self) -> EventQueueStatus {
+        self.status.lock().unwrap_or_else(|poisoned| poisoned.into_inner()).clone()
+    }
+
+    // 追加事件并落盘
+    pub async fn enqueue(&self, record: &ActivityRecord) -> Result<(), String> {
+        let line = serde_json::to_string(record).map_err(|e| format!("序列化活动事件失败: {}", e))?;
+
+        let _guard = self.file.lock().await;
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .map_err(|e| format!("打开事件队列失败: {}", e))?;
+        writeln!(file, "{}", line)
+            .and_then(|_| file.sync_data())
+            .map_err(|e| format!("写入事件队列失败: {}", e))?;
+
+        self.update_status(|status| status.depth += 1);
+        Ok(())
+    }
+
+    // 读取队首最多 limit 行，返回解析出的事件和读取的行数；无法解析的行直接丢弃
+    async fn peek(&self, limit: usize) -> Result<(Vec<ActivityRecord>, usize), String> {
+        let _guard = self.file.lock().await;
+        let mut reader = match File::open(&self.path) {
+            Ok(file) => BufReader::new(file),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok((Vec::new(), 0)),
+            Err(e) => return Err(format!("读取事件队列失败: {}", e)),
+        };
+
+        let mut records = Vec::new();
+        let mut consumed = 0;
+        let mut line = Vec::new();
+        while consumed < limit {
+            line.clear();
+            reader
+                .read_until(b'\n', &mut line)
+                .map_err(|e| format!("读取事件队列失败: {}", e))?;
+            // 没有换行符的末行是未写完的记录
+            if line.last() != Some(&b'\n') {
+                break;
+            }
+            consumed += 1;
+            if is_blank(&line) {
+                continue;
+            }
+            match serde_json::from_slice::<ActivityRecord>(&line) {
+                Ok(record) => records.push(record),
+                Err(e) => log::warn!("⚠️ 丢弃无法解析的队列事件: {}", e),
+            }
+        }
+        Ok((records, consumed))
+    }
+
+    // 删除队首已上传的行；上传期间新事件只会追加到末尾
+    async fn remove_front(&self, count: usize) -> Result<(), String> {
+        let _guard = self.file.lock().await;
+        let read_err = |e: std::io::Error| format!("读取事件队列失败: {}", e);
+        let write_err = |e: std::io::Error| format!("更新事件队列失败: {}", e);
+        let mut reader = BufReader::new(File::open(&self.path).map_err(read_err)?);
+
+        let mut line = Vec::new();
+        for _ in 0..count {
+            line.clear();
+            if reader.read_until(b'\n', &mut line).map_err(read_err)? == 0 {
+                break;
+            }
+        }
+
+        // 其余的行原样复制到临时文件后替换队列文件
+        let tmp = self.path.with_extension("jsonl.tmp");
+        let mut writer = BufWriter::new(File::create(&tmp).map_err(write_err)?);
+        let mut depth = 0;
+        loop {
+            line.clear();
+            if reader.read_until(b'\n', &mut line).map_err(read_err)? == 0 {
+                break;
+            }
+            if !is_blank(&line) {
+                depth += 1;
+            }
+            writer.write_all(&line).map_err(write_err)?;
+        }
+        writer
+            .into_inner()
+            .map_err(|e| write_err(e.into_error()))?
+            .sync_data()
+            .and_then(|_| std::fs::rename(&tmp, &self.path))
+            .map_err(write_err)?;
+
+        self.update_status(|status| status.depth = depth);
+        Ok(())
+    }
+
+    // 上传一批事件，返回上传的行数
+    pub async fn flush(&self, mongo: &MongoManager, tz: Tz, batch_size: usize) -> Result<usize, String> {
+        let (records, consumed) = self.peek(batch_size).await?;
+        if consumed == 0 {
+            return Ok(0);
+        }
+        if !records.is_empty() {
+            activity::upload_batch(mongo, tz, &records).await?;
+        }
+        self.remove_front(consumed).await?;
+        Ok(consumed)
+    }
+}
+
+fn is_blank(line: &[u8]) -> bool {
+    line.iter().all(u8::is_ascii_whitespace)
+}
+
+// 启动时检查队列文件，返回待上传的事件数；
+// 写入中途崩溃留下的不完整末行会被截掉，否则下一条事件会接在它后面一起损坏
+fn repair(path: &Path) -> Result<usize, String> {
+    let read_err = |e: std::io::Error| format!("读取事件队列失败: {}", e);
+    let mut reader = match File::open(path) {
+        Ok(file) => BufReader::new(file),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(0),
+        Err(e) => return Err(read_err(e)),
+    };
+
+    let mut depth = 0;
+    let mut complete_len = 0;
+    let mut line = Vec::new();
+    loop {
+        line.clear();
+        let read = reader.read_until(b'\n', &mut line).map_err(read_err)?;
+        if read == 0 {
+            return Ok(depth);
+        }
+        if line.last() != Some(&b'\n') {
+            break;
+        }
+        complete_len += read as u64;
+        if !is_blank(&line) {
+            depth += 1;
+        }
+    }
+
+    log::warn!("⚠️ 事件队列末尾有未写完的记录，已丢弃");
+    OpenOptions::new()
+        .write(true)
+        .open(path)
+        .and_then(|file| file.set_len(complete_len))
+        .map_err(|e| format!("修复事件队列失败: {}", e))?;
+    Ok(depth)
+}
+
+// 后台任务：定期上传队列中的事件，失败后指数退避重试
+pub fn spawn_uploader(state: &AppState) {
+    let config = state.config.clone();
+    let mongo = state.mongo.clone();
+    let queue = state.events.clone();
+
+    tauri::async_runtime::spawn(async move {
+        let activity = &config.activity;
+        let flush_every = Duration::from_secs(activity.event_flush_secs);
+        let max_backoff = Duration::from_secs(activity.event_max_backoff_secs);
+        let tz = config.analytics.tz();
+
+        let mut delay = flush_every;
+        loop {
+            tokio::time::sleep(delay).await;
+
+            let result = queue.flush(&*mongo.read().await, tz, activity.event_batch_size).await;
+            match result {
+                Ok(count) => {
+                    if count > 0 {
+                        log::info!("📤 已上传 {} 条活动事件", count);
+                        queue.update_status(|status| {
+                            status.last_flush_at = Some(Utc::now().to_rfc3339());
+                            status.last_error = None;
+                        });
+                    }
+                    queue.update_status(|status| status.next_retry_at = None);
+                    // 队列中还有积压时立即上传下一批
+                    delay = if count >= activity.event_batch_size { Duration::ZERO } else { flush_every };
+                }
+                Err(e) => {
+                    delay = (delay * 2).clamp(flush_every, max_backoff);
+                    log::warn!("⚠️ 上传活动事件失败，{} 秒后重试: {}", delay.as_secs(), e);
+                    let next_retry_at = Utc::now() + chrono::Duration::from_std(delay).unwrap_or_default();
+                    queue.update_status(|status| {
+                        status.last_error = Some(e);
+                        status.next_retry_at = Some(next_retry_at.to_rfc3339());
+                    });
+                }
+            }
+        }
+    });
+}
+
+// 查询本地事件队列状态
+#[tauri::command]
+pub async fn get_event_queue_status(
+    state: tauri::State<'_, AppState>,
+) -> Result<EventQueueStatus, String> {
+    current_caller(&state).await?;
+    Ok(state.events.status())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::activity::ActivityEvent;
+    use crate::test_support::TestDb;
+    use mongodb::bson::{doc, DateTime};
+
+    struct TempDir(PathBuf);
+
+    impl TempDir {
+        fn new() -> Self {
+            TempDir(std::env::temp_dir().join(format!("chengshang-test-{}", uuid::Uuid::new_v4().simple())))
+        }
+
+        fn queue_file(&self) -> PathBuf {
+            self.0.join(QUEUE_FILE)
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.0);
+        }
+    }
+
+    fn record(query: &str) -> ActivityRecord {
+        ActivityRecord {
+            id: None,
+            event_id: uuid::Uuid::new_v4().to_string(),
+            user_id: None,
+            session_id: None,
+            device_id: "test-device".to_string(),
+            app_version: "0.0.0".to_string(),
+            timestamp: DateTime::now(),
+            counters_applied: false,
+            counters_done: Vec::new(),
+            event: ActivityEvent::Search { query: query.to_string(), result_count: 0 },
+        }
+    }
+
+    fn queries(records: &[ActivityRecord]) -> Vec<&str> {
+        records
+            .iter()
+            .map(|record| match &record.event {
+                ActivityEvent::Search { query, .. } => query.as_str(),
+                other => panic!("unexpected event {:?}", other),
+            })
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn peek_reads_in_order_and_remove_front_drops_uploaded_lines() {
+        let dir = TempDir::new();
+        let queue = EventQueue::open(&dir.0).unwrap();
+        assert_eq!(queue.peek(10).await.unwrap().1, 0);
+
+        for query in ["a", "b", "c"] {
+            queue.enqueue(&record(query)).await.unwrap();
+        }
+        assert_eq!(queue.status().depth, 3);
+
+        let (records, consumed) = queue.peek(2).await.unwrap();
+        assert_eq!((queries(&records), consumed), (vec!["a", "b"], 2));
+
+        // 上传期间追加的事件保留在队尾
+        queue.enqueue(&record("d")).await.unwrap();
+        queue.remove_front(consumed).await.unwrap();
+        assert_eq!(queue.status().depth, 2);
+        let (records, consumed) = queue.peek(10).await.unwrap();
+        assert_eq!((queries(&records), consumed), (vec!["c", "d"], 2));
+
+        // 重新打开时按文件恢复待上传数
+        drop(queue);
+        assert_eq!(EventQueue::open(&dir.0).unwrap().status().depth, 2);
+    }
+
+    #[tokio::test]
+    async fn unparseable_lines_are_consumed_and_dropped() {
+        let dir = TempDir::new();
+        std::fs::create_dir_all(&dir.0).unwrap();
+        let valid = serde_json::to_string(&record("a")).unwrap();
+        std::fs::write(dir.queue_file(), format!("not json\n\n{}\n{{\"eventId\": 1}}\n", valid)).unwrap();
+
+        let queue = EventQueue::open(&dir.0).unwrap();
+        assert_eq!(queue.status().depth, 3);
+        let (records, consumed) = queue.peek(10).await.unwrap();
+        assert_eq!((queries(&records), consumed), (vec!["a"], 4));
+
+        queue.remove_front(consumed).await.unwrap();
+        assert_eq!(queue.status().depth, 0);
+        assert_eq!(std::fs::read_to_string(dir.queue_file()).unwrap(), "");
+    }
+
+    #[tokio::test]
+    async fn truncated_last_line_is_discarded_on_open() {
+        let dir = TempDir::new();
+        std::fs::create_dir_all(&dir.0).unwrap();
+        let valid = serde_json::to_string(&record("a")).unwrap();
+        let partial = &serde_json::to_string(&record("b")).unwrap()[..20];
+        std::fs::write(dir.queue_file(), format!("{}\n{}", valid, partial)).unwrap();
+
+        let queue = EventQueue::open(&dir.0).unwrap();
+        assert_eq!(queue.status().depth, 1);
+        assert_eq!(std::fs::read_to_string(dir.queue_file()).unwrap(), format!("{}\n", valid));
+
+        // 之后追加的事件不会接在残缺行后面
+        queue.enqueue(&record("c")).await.unwrap();
+        let (records, consumed) = queue.peek(10).await.unwrap();
+        assert_eq!((queries(&records), consumed), (vec!["a", "c"], 2));
+    }
+
+    #[tokio::test]
+    async fn peek_stops_before_an_unterminated_line() {
+        let dir = TempDir::new();
+        let queue = EventQueue::open(&dir.0).unwrap();
+        queue.enqueue(&record("a")).await.unwrap();
+        let mut file = OpenOptions::new().append(true).open(dir.queue_file()).unwrap();
+        write!(file, "{{\"eventId\"").unwrap();
+
+        let (records, consumed) = queue.peek(10).await.unwrap();
+        assert_eq!((queries(&records), consumed), (vec!["a"], 1));
+    }
+
+    #[tokio::test]
+    #[ignore = "需要本机 mongod"]
+    async fn flush_uploads_in_batches_until_empty() {
+        let db = TestDb::new("event_queue_flush").await;
+        let dir = TempDir::new();
+        let queue = EventQueue::open(&dir.0).unwrap();
+        for query in ["a", "b", "c"] {
+            queue.enqueue(&record(query)).await.unwrap();
+        }
+
+        let tz = chrono_tz::Asia::Shanghai;
+        assert_eq!(queue.flush(&db.mongo, tz, 2).await.unwrap(), 2);
+        assert_eq!(queue.flush(&db.mongo, tz, 2).await.unwrap(), 1);
+        assert_eq!(queue.flush(&db.mongo, tz, 2).await.unwrap(), 0);
+        assert_eq!(queue.status().depth, 0);
+        assert_eq!(db.mongo.activity_events().count_documents(doc! {"type": "search"}).await.unwrap(), 3);
+
+        db.drop().await;
+    }
+}
@@ -0,0 +1,610 @@
+use mongodb::bson::{doc, Document};
+use rust_xlsxwriter::{Workbook, XlsxError};
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use tauri::{AppHandle, Emitter};
+use tauri_plugin_dialog::DialogExt;
+
+use crate::auth::{self, AppState, UserAnalyticsQuery};
+use crate::daily_stats;
+use crate::guard::{require, require_any, Permission};
+use crate::metrics::{self, DateRange};
+use crate::tools;
+
+// 导出进度事件，每写入 PROGRESS_EVERY 行发送一次
+pub const EXPORT_PROGRESS_EVENT: &str = "export-progress";
+const PROGRESS_EVERY: u64 = 500;
+// XLSX 单个工作表最多 1048576 行（含表头）
+const XLSX_MAX_ROWS: u64 = 1_048_575;
+// Excel 需要 BOM 才能按 UTF-8 识别 CSV 中的中文
+const UTF8_BOM: &[u8] = b"\xEF\xBB\xBF";
+
+// 导出的数据
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum ExportKind {
+    // 用户分析列表（与 get_user_analytics 相同的筛选和排序，不分页）
+    UserAnalytics,
+    // 系统分析的用户增长和工具使用趋势
+    SystemAnalytics,
+    // 各工具在每个统计区间的点击、时长和使用人数
+    ToolTrends,
+}
+
+impl ExportKind {
+    fn title(&self) -> &'static str {
+        match self {
+            ExportKind::UserAnalytics => "用户分析",
+            ExportKind::SystemAnalytics => "系统趋势",
+            ExportKind::ToolTrends => "工具趋势",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ExportFormat {
+    Csv,
+    Xlsx,
+    Json,
+}
+
+impl ExportFormat {
+    fn extension(&self) -> &'static str {
+        match self {
+            ExportFormat::Csv => "csv",
+            ExportFormat::Xlsx => "xlsx",
+            ExportFormat::Json => "json",
+        }
+    }
+
+    fn filter_name(&self) -> &'static str {
+        match self {
+            ExportFormat::Csv => "CSV 文件",
+            ExportFormat::Xlsx => "Excel 工作簿",
+            ExportFormat::Json => "JSON 文件",
+        }
+    }
+}
+
+// 导出请求；query 只用于用户分析，日期范围和粒度用于趋势数据
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ExportRequest {
+    pub kind: ExportKind,
+    pub format: ExportFormat,
+    // 前端用于匹配进度事件，未指定时自动生成
+    pub export_id: Option<String>,
+    #[serde(default)]
+    pub query: UserAnalyticsQuery,
+    pub start_date: Option<String>,
+    pub end_date: Option<String>,
+    pub granularity: Option<String>,
+    // 工具趋势只导出指定工具
+    pub tool_id: Option<i32>,
+}
+
+// 导出结果响应结构
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ExportResult {
+    #[serde(rename = "exportId")]
+    pub export_id: String,
+    pub path: String,
+    pub rows: u64,
+}
+
+// 导出进度事件
+#[derive(Debug, Clone, Serialize)]
+pub struct ExportProgress {
+    #[serde(rename = "exportId")]
+    pub export_id: String,
+    pub rows: u64,
+    // 预计总行数，无法预先统计时为空
+    pub total: Option<u64>,
+    pub done: bool,
+}
+
+// 单元格；数值列在 XLSX 中写为数字，在 JSON 中写为数字
+enum Cell {
+    Text(String),
+    Number(i64),
+}
+
+impl From<String> for Cell {
+    fn from(value: String) -> Self {
+        Cell::Text(value)
+    }
+}
+
+impl From<i64> for Cell {
+    fn from(value: i64) -> Self {
+        Cell::Number(value)
+    }
+}
+
+// 列定义：(JSON 字段名, 表头)
+type Columns = &'static [(&'static str, &'static str)];
+
+const USER_COLUMNS: Columns = &[
+    ("id", "用户ID"),
+    ("username", "用户名"),
+    ("role", "角色"),
+    ("department", "部门"),
+    ("isActive", "状态"),
+    ("totalToolClicks", "工具点击次数"),
+    ("totalUsageTime", "使用时长（秒）"),
+    ("loginCount", "登录次数"),
+    ("lastLoginAt", "最后登录时间"),
+    ("createdAt", "创建时间"),
+    ("favoriteTools", "常用工具"),
+];
+
+const SYSTEM_COLUMNS: Columns = &[
+    ("date", "日期"),
+    ("newUsers", "新增用户"),
+    ("activeUsers", "活跃用户"),
+    ("totalSessions", "会话数"),
+    ("totalClicks", "工具点击次数"),
+    ("totalUsageTime", "使用时长（秒）"),
+    ("uniqueUsers", "使用工具人数"),
+];
+
+const TOOL_COLUMNS: Columns = &[
+    ("date", "日期"),
+    ("toolId", "工具ID"),
+    ("toolName", "工具名称"),
+    ("clicks", "点击次数"),
+    ("usageSecs", "使用时长（秒）"),
+    ("users", "使用人数"),
+];
+
+// 按格式逐行写入文件，不在内存中保留已写入的数据
+enum ExportWriter {
+    Csv(Box<csv::Writer<BufWriter<File>>>),
+    Json {
+        out: BufWriter<File>,
+        columns: Columns,
+        first: bool,
+    },
+    // 常量内存模式：已写完的行会刷到临时文件
+    Xlsx {
+        workbook: Box<Workbook>,
+        path: PathBuf,
+        row: u32,
+    },
+}
+
+fn xlsx_error(e: XlsxError) -> String {
+    format!("写入Excel文件失败: {}", e)
+}
+
+impl ExportWriter {
+    fn create(path: &Path, format: ExportFormat, sheet_name: &str, columns: Columns) -> Result<Self, String> {
+        let mut writer = match format {
+            ExportFormat::Csv => {
+                let mut out = BufWriter::new(File::create(path).map_err(|e| format!("创建导出文件失败: {}", e))?);
+                out.write_all(UTF8_BOM).map_err(|e| format!("写入导出文件失败: {}", e))?;
+                ExportWriter::Csv(Box::new(csv::Writer::from_writer(out)))
+            }
+            ExportFormat::Json => {
+                let mut out = BufWriter::new(File::create(path).map_err(|e| format!("创建导出文件失败: {}", e))?);
+                out.write_all(b"[").map_err(|e| format!("写入导出文件失败: {}", e))?;
+                ExportWriter::Json { out, columns, first: true }
+            }
+            ExportFormat::Xlsx => {
+                let mut workbook = Workbook::new();
+                workbook
+                    .add_worksheet_with_constant_memory()
+                    .set_name(sheet_name)
+                    .map_err(xlsx_error)?;
+                ExportWriter::Xlsx { workbook: Box::new(workbook), path: path.to_path_buf(), row: 0 }
+            }
+        };
+
+        // JSON 以字段名作为键，不需要表头
+        let headers: Vec<Cell> = columns.iter().map(|(_, header)| Cell::Text(header.to_string())).collect();
+        match writer {
+            ExportWriter::Json { .. } => {}
+            _ => writer.write_row(headers)?,
+        }
+        Ok(writer)
+    }
+
+    fn write_row(&mut self, cells: Vec<Cell>) -> Result<(), String> {
+        match self {
+            ExportWriter::Csv(writer) => {
+                let record: Vec<String> = cells
+                    .into_iter()
+                    .map(|cell| match cell {
+                        Cell::Text(value) => value,
+                        Cell::Number(value) => value.to_string(),
+                    })
+                    .collect();
+                writer.write_record(&record).map_err(|e| format!("写入CSV文件失败: {}", e))
+            }
+            ExportWriter::Json { out, columns, first } => {
+                let object: serde_json::Map<String, serde_json::Value> = columns
+                    .iter()
+                    .zip(cells)
+                    .map(|((key, _), cell)| {
+                        let value = match cell {
+                            Cell::Text(value) => serde_json::Value::String(value),
+                            Cell::Number(value) => serde_json::Value::from(value),
+                        };
+                        (key.to_string(), value)
+                    })
+                    .collect();
+                let separator: &[u8] = if *first { b"\n" } else { b",\n" };
+                *first = false;
+                out.write_all(separator).map_err(|e| format!("写入导出文件失败: {}", e))?;
+                serde_json::to_writer(&mut *out, &object).map_err(|e| format!("写入JSON文件失败: {}", e))
+            }
+            ExportWriter::Xlsx { workbook, row, .. } => {
+                if *row as u64 > XLSX_MAX_ROWS {
+                    return Err(format!("数据超过Excel工作表的最大行数 {}，请改用CSV格式", XLSX_MAX_ROWS));
+                }
+                let worksheet = workbook.worksheet_from_index(0).map_err(xlsx_error)?;
+                for (col, cell) in cells.into_iter().enumerate() {
+                    match cell {
+                        Cell::Text(value) => worksheet.write_string(*row, col as u16, value),
+                        Cell::Number(value) => worksheet.write_number(*row, col as u16, value as f64),
+                    }
+                    .map_err(xlsx_error)?;
+                }
+                *row += 1;
+                Ok(())
+            }
+        }
+    }
+
+    fn finish(&mut self) -> Result<(), String> {
+        match self {
+            ExportWriter::Csv(writer) => writer.flush().map_err(|e| format!("写入CSV文件失败: {}", e)),
+            ExportWriter::Json { out, first, .. } => {
+                let end: &[u8] = if *first { b"]\n" } else { b"\n]\n" };
+                out.write_all(end).map_err(|e| format!("写入导出文件失败: {}", e))?;
+                out.flush().map_err(|e| format!("写入导出文件失败: {}", e))
+            }
+            ExportWriter::Xlsx { workbook, path, .. } => workbook.save(path).map_err(xlsx_error),
+        }
+    }
+}
+
+// 写入行并按间隔发送进度事件；report 负责把进度发给前端
+struct ExportSink<'a> {
+    report: &'a (dyn Fn(ExportProgress) + Send + Sync),
+    writer: ExportWriter,
+    export_id: String,
+    rows: u64,
+    total: Option<u64>,
+}
+
+impl ExportSink<'_> {
+    fn push(&mut self, cells: Vec<Cell>) -> Result<(), String> {
+        self.writer.write_row(cells)?;
+        self.rows += 1;
+        if self.rows % PROGRESS_EVERY == 0 {
+            self.progress(false);
+        }
+        Ok(())
+    }
+
+    fn progress(&self, done: bool) {
+        (self.report)(ExportProgress {
+            export_id: self.export_id.clone(),
+            rows: self.rows,
+            total: self.total,
+            done,
+        });
+    }
+}
+
+// 用户分析：与列表页相同的筛选和排序，直接遍历聚合游标，不分页
+async fn export_user_analytics(
+    sink: &mut ExportSink<'_>,
+    state: &AppState,
+    current_user: &auth::UserResponse,
+    query: &UserAnalyticsQuery,
+) -> Result<(), String> {
+    let tz = state.config.analytics.tz();
+    let mongo = state.mongo.read().await;
+    let Some(match_stage) = auth::user_analytics_filter(current_user, &mongo, tz, query).await? else {
+        return Ok(());
+    };
+
+    sink.total = Some(
+        mongo.users()
+            .count_documents(match_stage.clone())
+            .await
+            .map_err(|e| format!("统计用户数失败: {}", e))?,
+    );
+    sink.progress(false);
+
+    // 导出只需要常用工具，不关联全部工具使用明细
+    let query = UserAnalyticsQuery { include_details: Some(false), ..query.clone() };
+    let mut cursor = mongo.users()
+        .aggregate(auth::user_analytics_pipeline(match_stage, &query, None))
+        .await
+        .map_err(|e| format!("聚合查询失败: {}", e))?;
+
+    let catalog_names = tools::tool_names(&mongo).await?;
+    while cursor.advance().await.map_err(|e| format!("遍历聚合结果失败: {}", e))? {
+        let document = cursor.deserialize_current().map_err(|e| format!("反序列化聚合结果失败: {}", e))?;
+        let user = auth::user_analytics_from_document(&document, &catalog_names, false);
+        sink.push(vec![
+            user.id.into(),
+            user.username.into(),
+            user.role.into(),
+            user.department.unwrap_or_default().into(),
+            (if user.is_active { "启用" } else { "禁用" }).to_string().into(),
+            user.total_tool_clicks.into(),
+            user.total_usage_time.into(),
+            user.login_count.into(),
+            user.last_login_at.unwrap_or_default().into(),
+            user.created_at.into(),
+            user.favorite_tools.join("、").into(),
+        ])?;
+    }
+    Ok(())
+}
+
+// 系统趋势：每个统计区间一行，区间数不超过 DateRange 的上限
+async fn export_system_analytics(
+    sink: &mut ExportSink<'_>,
+    state: &AppState,
+    range: &DateRange,
+) -> Result<(), String> {
+    let mongo = state.mongo.read().await;
+    let (growth, usage) = metrics::trends(&mongo, range).await?;
+    sink.total = Some(growth.len() as u64);
+
+    for (growth, usage) in growth.into_iter().zip(usage) {
+        sink.push(vec![
+            growth.date.into(),
+            growth.new_users.into(),
+            growth.active_users.into(),
+            growth.total_sessions.into(),
+            usage.total_clicks.into(),
+            usage.total_usage_time.into(),
+            usage.unique_users.into(),
+        ])?;
+    }
+    Ok(())
+}
+
+// 工具趋势：从每日统计按 区间 + 工具 分组，按区间和工具顺序逐行读取
+async fn export_tool_trends(
+    sink: &mut ExportSink<'_>,
+    state: &AppState,
+    range: &DateRange,
+    tool_id: Option<i32>,
+) -> Result<(), String> {
+    let mongo = state.mongo.read().await;
+    let tool_match = match tool_id {
+        Some(tool_id) if tool_id <= 0 => return Err(format!("无效的工具ID: {}", tool_id)),
+        Some(tool_id) => doc! {"toolId": tool_id},
+        None => doc! {"toolId": {"$ne": daily_stats::USER_TOTAL}},
+    };
+    let mut match_stage = tool_match;
+    match_stage.insert("date", doc! {"$gte": range.lower_bound(), "$lt": range.upper_bound()});
+
+    let pipeline: Vec<Document> = vec![
+        doc! {"$match": match_stage},
+        doc! {
+            "$group": {
+                "_id": {
+                    "date": {
+                        "$dateToString": {
+                            "format": "%Y-%m-%d",
+                            "timezone": range.tz.name(),
+                            "date": {
+                                "$dateTrunc": {
+                                    "date": "$date",
+                                    "unit": range.granularity.unit(),
+                                    "timezone": range.tz.name(),
+                                    "startOfWeek": "monday"
+                                }
+                            }
+                        }
+                    },
+                    "toolId": "$toolId"
+                },
+                "clicks": {"$sum": "$clicks"},
+                "usageSecs": {"$sum": "$usageSecs"},
+                "users": {"$addToSet": "$userId"}
+            }
+        },
+        doc! {"$project": {"clicks": 1, "usageSecs": 1, "users": {"$size": "$users"}}},
+        doc! {"$sort": {"_id.date": 1, "_id.toolId": 1}},
+    ];
+
+    let mut cursor = mongo.daily_stats()
+        .aggregate(pipeline)
+        .allow_disk_use(true)
+        .await
+        .map_err(|e| format!("工具趋势聚合失败: {}", e))?;
+
+    let catalog_names = tools::tool_names(&mongo).await?;
+    while cursor.advance().await.map_err(|e| format!("遍历工具趋势失败: {}", e))? {
+        let document = cursor.deserialize_current().map_err(|e| format!("反序列化工具趋势失败: {}", e))?;
+        let key = document.get_document("_id").map_err(|e| format!("工具趋势格式错误: {}", e))?;
+        let tool_id = key.get_i32("toolId").unwrap_or(0);
+        sink.push(vec![
+            key.get_str("date").unwrap_or("").to_string().into(),
+            (tool_id as i64).into(),
+            catalog_names
+                .get(&tool_id)
+                .cloned()
+                .unwrap_or_else(|| format!("工具{}", tool_id))
+                .into(),
+            metrics::get_number(&document, "clicks").into(),
+            metrics::get_number(&document, "usageSecs").into(),
+            metrics::get_number(&document, "users").into(),
+        ])?;
+    }
+    Ok(())
+}
+
+// 导出统计数据：先校验权限和参数，再弹出保存对话框，用户取消时返回 None。
+// 进度通过 export-progress 事件通知前端，导出失败时删除未写完的文件
+#[tauri::command]
+pub async fn export_analytics(
+    request: ExportRequest,
+    app: AppHandle,
+    state: tauri::State<'_, AppState>,
+) -> Result<Option<ExportResult>, String> {
+    let current_user = match request.kind {
+        ExportKind::UserAnalytics => require_any(&state, &[
+            Permission::ViewAnalytics,
+            Permission::ViewDepartmentAnalytics,
+        ]).await?,
+        ExportKind::SystemAnalytics | ExportKind::ToolTrends => require(&state, Permission::ViewAnalytics).await?,
+    };
+
+    let tz = state.config.analytics.tz();
+    let range = DateRange::parse(
+        request.start_date.clone(),
+        request.end_date.clone(),
+        request.granularity.clone(),
+        tz,
+    )?;
+
+    let file_name = format!(
+        "{}_{}.{}",
+        request.kind.title(),
+        metrics::today(tz).format("%Y%m%d"),
+        request.format.extension()
+    );
+    let (tx, rx) = tokio::sync::oneshot::channel();
+    app.dialog()
+        .file()
+        .set_title(format!("导出{}", request.kind.title()))
+        .set_file_name(file_name)
+        .add_filter(request.format.filter_name(), &[request.format.extension()])
+        .save_file(move |path| {
+            let _ = tx.send(path);
+        });
+    let Some(path) = rx.await.map_err(|_| "保存对话框已关闭".to_string())? else {
+        log::info!("📤 {} 取消了导出", current_user.username);
+        return Ok(None);
+    };
+    let path = path.into_path().map_err(|e| format!("无效的保存路径: {}", e))?;
+
+    let export_id = request.export_id.clone().unwrap_or_else(|| uuid::Uuid::new_v4().to_string());
+    let columns = match request.kind {
+        ExportKind::UserAnalytics => USER_COLUMNS,
+        ExportKind::SystemAnalytics => SYSTEM_COLUMNS,
+        ExportKind::ToolTrends => TOOL_COLUMNS,
+    };
+    log::info!("📤 {} 开始导出{}: {}", current_user.username, request.kind.title(), path.display());
+
+    let report = |progress: ExportProgress| {
+        if let Err(e) = app.emit(EXPORT_PROGRESS_EVENT, progress) {
+            log::warn!("⚠️ 发送导出进度失败: {}", e);
+        }
+    };
+    let mut sink = ExportSink {
+        report: &report,
+        writer: ExportWriter::create(&path, request.format, request.kind.title(), columns)?,
+        export_id: export_id.clone(),
+        rows: 0,
+        total: None,
+    };
+    let exported = match request.kind {
+        ExportKind::UserAnalytics => export_user_analytics(&mut sink, &state, &current_user, &request.query).await,
+        ExportKind::SystemAnalytics => export_system_analytics(&mut sink, &state, &range).await,
+        ExportKind::ToolTrends => export_tool_trends(&mut sink, &state, &range, request.tool_id).await,
+    };
+
+    if let Err(e) = exported.and_then(|_| sink.writer.finish()) {
+        log::error!("❌ 导出{}失败: {}", request.kind.title(), e);
+        drop(sink);
+        let _ = std::fs::remove_file(&path);
+        return Err(e);
+    }
+    sink.progress(true);
+
+    log::info!("✅ 导出{}完成，共 {} 行", request.kind.title(), sink.rows);
+    Ok(Some(ExportResult {
+        export_id,
+        path: path.to_string_lossy().to_string(),
+        rows: sink.rows,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    const COLUMNS: Columns = &[("name", "名称"), ("count", "次数")];
+
+    struct TempFile(PathBuf);
+
+    impl TempFile {
+        fn new(extension: &str) -> Self {
+            TempFile(std::env::temp_dir().join(format!("chengshang-export-{}.{}", uuid::Uuid::new_v4().simple(), extension)))
+        }
+    }
+
+    impl Drop for TempFile {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_file(&self.0);
+        }
+    }
+
+    fn export(format: ExportFormat, rows: Vec<(&str, i64)>) -> Vec<u8> {
+        let file = TempFile::new(format.extension());
+        let mut writer = ExportWriter::create(&file.0, format, "测试", COLUMNS).unwrap();
+        for (name, count) in rows {
+            writer.write_row(vec![name.to_string().into(), count.into()]).unwrap();
+        }
+        writer.finish().unwrap();
+        drop(writer);
+        std::fs::read(&file.0).unwrap()
+    }
+
+    #[test]
+    fn csv_starts_with_bom_and_quotes_special_cells() {
+        let bytes = export(ExportFormat::Csv, vec![("常用, 工具", 3), ("说\"明\"", 4), ("多\n行", -1)]);
+        assert!(bytes.starts_with(UTF8_BOM));
+        let content = String::from_utf8(bytes[UTF8_BOM.len()..].to_vec()).unwrap();
+        assert_eq!(content, "名称,次数\n\"常用, 工具\",3\n\"说\"\"明\"\"\",4\n\"多\n行\",-1\n");
+    }
+
+    #[test]
+    fn json_uses_field_names_and_numbers() {
+        let rows: serde_json::Value = serde_json::from_slice(&export(ExportFormat::Json, vec![("模板", 3), ("海报", 0)])).unwrap();
+        assert_eq!(rows, serde_json::json!([{"name": "模板", "count": 3}, {"name": "海报", "count": 0}]));
+
+        let empty: serde_json::Value = serde_json::from_slice(&export(ExportFormat::Json, vec![])).unwrap();
+        assert_eq!(empty, serde_json::json!([]));
+    }
+
+    #[test]
+    fn progress_is_reported_every_batch_and_on_completion() {
+        let file = TempFile::new("csv");
+        let events = Mutex::new(Vec::new());
+        let report = |progress: ExportProgress| events.lock().unwrap().push((progress.rows, progress.total, progress.done));
+        let mut sink = ExportSink {
+            report: &report,
+            writer: ExportWriter::create(&file.0, ExportFormat::Csv, "测试", COLUMNS).unwrap(),
+            export_id: "export-1".to_string(),
+            rows: 0,
+            total: Some(PROGRESS_EVERY * 2 + 1),
+        };
+        for _ in 0..PROGRESS_EVERY * 2 + 1 {
+            sink.push(vec!["工具".to_string().into(), 1.into()]).unwrap();
+        }
+        sink.writer.finish().unwrap();
+        sink.progress(true);
+
+        let total = Some(PROGRESS_EVERY * 2 + 1);
+        assert_eq!(
+            events.into_inner().unwrap(),
+            [(PROGRESS_EVERY, total, false), (PROGRESS_EVERY * 2, total, false), (PROGRESS_EVERY * 2 + 1, total, true)]
+        );
+    }
+}
@@ -0,0 +1,409 @@
+use mongodb::bson::{doc, oid::ObjectId, Document};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+use crate::auth::{AppState, MongoManager, User, UserResponse};
+use crate::rbac;
+use crate::totp;
+
+// 特权操作所需的权限，角色通过权限集合授予
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Permission {
+    // 管理所有用户
+    ManageUsers,
+    // 仅管理本部门用户
+    ManageDepartmentUsers,
+    // 查看全部分析数据
+    ViewAnalytics,
+    // 仅查看本部门分析数据
+    ViewDepartmentAnalytics,
+    ManageRoles,
+    // 管理工具目录与工具授权
+    ManageTools,
+    // 查看和轮换Token签名密钥
+    ManageSigningKeys,
+    ManageTestData,
+    Debug,
+}
+
+impl Permission {
+    pub const ALL: [Permission; 9] = [
+        Permission::ManageUsers,
+        Permission::ManageDepartmentUsers,
+        Permission::ViewAnalytics,
+        Permission::ViewDepartmentAnalytics,
+        Permission::ManageRoles,
+        Permission::ManageTools,
+        Permission::ManageSigningKeys,
+        Permission::ManageTestData,
+        Permission::Debug,
+    ];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            Permission::ManageUsers => "用户管理",
+            Permission::ManageDepartmentUsers => "部门用户管理",
+            Permission::ViewAnalytics => "数据分析",
+            Permission::ViewDepartmentAnalytics => "部门数据分析",
+            Permission::ManageRoles => "角色管理",
+            Permission::ManageTools => "工具管理",
+            Permission::ManageSigningKeys => "签名密钥管理",
+            Permission::ManageTestData => "测试数据管理",
+            Permission::Debug => "调试",
+        }
+    }
+}
+
+// 权限校验错误，前端通过错误码前缀区分
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AuthError {
+    Unauthenticated,
+    Forbidden { permission: Permission },
+    OutOfScope,
+    // 目标用户拥有调用者没有的权限
+    TargetPrivileged { permission: Permission },
+    ToolDenied { tool_id: i32 },
+    // 连续登录失败导致的临时锁定
+    AccountLocked { retry_after_secs: i64 },
+    // 登录失败后的递增等待期内再次尝试
+    LoginThrottled { retry_after_secs: i64 },
+    // 策略要求启用两步验证但尚未绑定
+    TotpRequired,
+    Internal(String),
+}
+
+impl AuthError {
+    pub fn code(&self) -> &'static str {
+        match self {
+            AuthError::Unauthenticated => "UNAUTHENTICATED",
+            AuthError::Forbidden { .. } | AuthError::OutOfScope | AuthError::TargetPrivileged { .. } => "FORBIDDEN",
+            AuthError::ToolDenied { .. } => "TOOL_FORBIDDEN",
+            AuthError::AccountLocked { .. } => "ACCOUNT_LOCKED",
+            AuthError::LoginThrottled { .. } => "LOGIN_THROTTLED",
+            AuthError::TotpRequired => "TOTP_REQUIRED",
+            AuthError::Internal(_) => "INTERNAL",
+        }
+    }
+}
+
+impl fmt::Display for AuthError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AuthError::Unauthenticated => write!(f, "{}: 未登录", self.code()),
+            AuthError::Forbidden { permission } => {
+                write!(f, "{}: 权限不足，需要{}权限", self.code(), permission.label())
+            }
+            AuthError::OutOfScope => write!(f, "{}: 只能管理本部门的用户", self.code()),
+            AuthError::TargetPrivileged { permission } => {
+                write!(f, "{}: 不能管理拥有{}权限的用户", self.code(), permission.label())
+            }
+            AuthError::ToolDenied { tool_id } => write!(f, "{}: 无权使用工具 {}", self.code(), tool_id),
+            AuthError::AccountLocked { retry_after_secs } => write!(
+                f,
+                "{}: 登录失败次数过多，账号已临时锁定，请在{}分钟后重试或联系管理员解锁",
+                self.code(),
+                (retry_after_secs + 59) / 60
+            ),
+            AuthError::LoginThrottled { retry_after_secs } => {
+                write!(f, "{}: 登录尝试过于频繁，请在{}秒后重试", self.code(), retry_after_secs)
+            }
+            AuthError::TotpRequired => write!(f, "{}: 管理员账号必须先启用两步验证", self.code()),
+            AuthError::Internal(message) => write!(f, "{}: 权限校验失败: {}", self.code(), message),
+        }
+    }
+}
+
+impl std::error::Error for AuthError {}
+
+impl From<AuthError> for String {
+    fn from(err: AuthError) -> Self {
+        err.to_string()
+    }
+}
+
+impl UserResponse {
+    pub fn has_permission(&self, permission: Permission) -> bool {
+        self.permissions.contains(&permission)
+    }
+
+    // 是否可以管理指定部门的用户
+    pub fn can_manage_department(&self, department: Option<&str>) -> bool {
+        if self.has_permission(Permission::ManageUsers) {
+            return true;
+        }
+        self.has_permission(Permission::ManageDepartmentUsers)
+            && self.department.is_some()
+            && self.department.as_deref() == department
+    }
+
+    // 分析数据的可见范围
+    pub fn analytics_scope(&self) -> DepartmentScope<'_> {
+        if self.has_permission(Permission::ViewAnalytics) {
+            DepartmentScope::All
+        } else {
+            match self.department.as_deref() {
+                Some(department) => DepartmentScope::Only(department),
+                None => DepartmentScope::Nothing,
+            }
+        }
+    }
+
+    // 按分析数据的可见范围生成用户的部门筛选条件，department 为调用方指定的部门；
+    // 返回 None 表示没有可查看的数据
+    pub fn analytics_department_filter(&self, department: Option<String>) -> Result<Option<Document>, String> {
+        let department = department.map(|d| d.trim().to_string()).filter(|d| !d.is_empty());
+        match self.analytics_scope() {
+            DepartmentScope::All => Ok(Some(match department {
+                Some(department) => doc! {"department": department},
+                None => doc! {},
+            })),
+            DepartmentScope::Only(own) => {
+                if department.as_deref().is_some_and(|department| department != own) {
+                    return Err("只能查看本部门的数据".to_string());
+                }
+                Ok(Some(doc! {"department": own}))
+            }
+            DepartmentScope::Nothing => Ok(None),
+        }
+    }
+}
+
+// 部门级权限的数据范围
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DepartmentScope<'a> {
+    All,
+    Only(&'a str),
+    // 部门负责人未分配部门时看不到任何数据
+    Nothing,
+}
+
+// 基于已解析权限的调用者判断是否具备任一指定权限
+pub fn authorize(
+    caller: Option<&UserResponse>,
+    permissions: &[Permission],
+) -> Result<UserResponse, AuthError> {
+    let user = caller.ok_or(AuthError::Unauthenticated)?;
+
+    if !permissions.iter().any(|p| user.has_permission(*p)) {
+        // 没有列出任何权限时一律拒绝，不视为无需权限
+        let Some(&permission) = permissions.first() else {
+            return Err(AuthError::Internal("未指定所需权限".to_string()));
+        };
+        log::warn!(
+            "❌ 权限拒绝 - 用户 {} ({}) 缺少{}权限",
+            user.username,
+            user.role,
+            permission.label()
+        );
+        return Err(AuthError::Forbidden { permission });
+    }
+
+    Ok(user.clone())
+}
+
+// 校验调用者能否管理目标用户：目标须在调用者的管理范围内，且目标的权限不能超出调用者，
+// 防止部门负责人重置本部门管理员的密码、禁用或删除管理员、修改管理员的角色
+pub fn authorize_target(caller: &UserResponse, target: &UserResponse) -> Result<(), AuthError> {
+    if !caller.can_manage_department(target.department.as_deref()) {
+        log::warn!("❌ 权限拒绝 - {} 尝试管理其他部门用户: {}", caller.username, target.username);
+        return Err(AuthError::OutOfScope);
+    }
+    if let Some(permission) = target.permissions.iter().find(|p| !caller.has_permission(**p)) {
+        log::warn!(
+            "❌ 权限拒绝 - {} 尝试管理拥有{}权限的用户: {}",
+            caller.username,
+            permission.label(),
+            target.username
+        );
+        return Err(AuthError::TargetPrivileged { permission: *permission });
+    }
+    Ok(())
+}
+
+// 解析目标用户当前角色的权限后校验能否管理
+pub async fn require_target(mongo: &MongoManager, caller: &UserResponse, target: &User) -> Result<(), AuthError> {
+    let target = rbac::resolve_user(mongo, target.clone())
+        .await
+        .map_err(AuthError::Internal)?;
+    authorize_target(caller, &target)
+}
+
+// 从数据库重新加载当前登录用户及其权限，确保角色变更和禁用立即生效
+pub async fn current_caller(state: &AppState) -> Result<UserResponse, AuthError> {
+    let user_id = {
+        let current_user = state.current_user.read().await;
+        current_user.as_ref().ok_or(AuthError::Unauthenticated)?.id.clone()
+    };
+    let user_object_id = ObjectId::parse_str(&user_id).map_err(|_| AuthError::Unauthenticated)?;
+
+    let mongo = state.mongo.read().await;
+    let user = mongo.users()
+        .find_one(doc! {"_id": user_object_id})
+        .await
+        .map_err(|e| AuthError::Internal(e.to_string()))?
+        .ok_or(AuthError::Unauthenticated)?;
+
+    if !user.is_active {
+        return Err(AuthError::Unauthenticated);
+    }
+
+    rbac::resolve_user(&mongo, user)
+        .await
+        .map_err(AuthError::Internal)
+}
+
+// 所有特权命令的统一入口：解析调用者并校验权限，返回调用者信息
+pub async fn require(state: &AppState, permission: Permission) -> Result<UserResponse, AuthError> {
+    require_any(state, &[permission]).await
+}
+
+pub async fn require_any(
+    state: &AppState,
+    permissions: &[Permission],
+) -> Result<UserResponse, AuthError> {
+    let caller = current_caller(state).await?;
+    let user = authorize(Some(&caller), permissions)?;
+    ensure_second_factor(state, &user).await?;
+    Ok(user)
+}
+
+// require_admin_totp 策略：高危账号未绑定两步验证前不能执行特权操作
+async fn ensure_second_factor(state: &AppState, user: &UserResponse) -> Result<(), AuthError> {
+    if !state.config.auth.require_admin_totp || !totp::is_privileged(user) {
+        return Ok(());
+    }
+    let user_id = ObjectId::parse_str(&user.id).map_err(|_| AuthError::Unauthenticated)?;
+
+    let mongo = state.mongo.read().await;
+    if totp::is_enabled(&mongo, user_id).await.map_err(AuthError::Internal)? {
+        Ok(())
+    } else {
+        log::warn!("❌ 权限拒绝 - 用户 {} 未启用两步验证", user.username);
+        Err(AuthError::TotpRequired)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rbac::{self, ROLE_ADMIN, ROLE_DEPARTMENT_LEAD, ROLE_USER};
+
+    // 按内置角色的默认权限构造调用者
+    fn caller(role: &str, department: Option<&str>) -> UserResponse {
+        let permissions = rbac::builtin_roles()
+            .into_iter()
+            .find(|(name, ..)| *name == role)
+            .map(|(.., permissions)| permissions)
+            .unwrap_or_default();
+        UserResponse {
+            id: ObjectId::new().to_hex(),
+            username: format!("{}-test", role),
+            role: role.to_string(),
+            roles: vec![role.to_string()],
+            department: department.map(str::to_string),
+            permissions,
+            is_active: true,
+            created_at: String::new(),
+            last_login_at: None,
+            total_usage_time: 0,
+            login_count: 0,
+        }
+    }
+
+    #[test]
+    fn unauthenticated_caller_is_rejected_for_every_permission() {
+        for permission in Permission::ALL {
+            assert_eq!(
+                authorize(None, &[permission]).unwrap_err(),
+                AuthError::Unauthenticated,
+                "{:?}",
+                permission
+            );
+        }
+    }
+
+    #[test]
+    fn user_role_is_forbidden_every_permission() {
+        let user = caller(ROLE_USER, Some("设计部"));
+        for permission in Permission::ALL {
+            assert_eq!(
+                authorize(Some(&user), &[permission]).unwrap_err(),
+                AuthError::Forbidden { permission },
+                "{:?}",
+                permission
+            );
+        }
+    }
+
+    #[test]
+    fn builtin_roles_grant_expected_permissions() {
+        let cases = [
+            (ROLE_ADMIN, Permission::ALL.to_vec()),
+            (ROLE_USER, vec![]),
+            (
+                ROLE_DEPARTMENT_LEAD,
+                vec![Permission::ManageDepartmentUsers, Permission::ViewDepartmentAnalytics],
+            ),
+        ];
+        for (role, granted) in cases {
+            let user = caller(role, Some("设计部"));
+            for permission in Permission::ALL {
+                let result = authorize(Some(&user), &[permission]);
+                if granted.contains(&permission) {
+                    assert_eq!(result.unwrap().id, user.id, "{} {:?}", role, permission);
+                } else {
+                    assert_eq!(result.unwrap_err(), AuthError::Forbidden { permission }, "{} {:?}", role, permission);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn any_listed_permission_is_enough_and_first_is_reported() {
+        let lead = caller(ROLE_DEPARTMENT_LEAD, Some("设计部"));
+        let either = [Permission::ViewAnalytics, Permission::ViewDepartmentAnalytics];
+        assert!(authorize(Some(&lead), &either).is_ok());
+
+        let user = caller(ROLE_USER, None);
+        assert_eq!(
+            authorize(Some(&user), &either).unwrap_err(),
+            AuthError::Forbidden { permission: Permission::ViewAnalytics }
+        );
+    }
+
+    #[test]
+    fn empty_permission_list_is_rejected() {
+        let admin = caller(ROLE_ADMIN, None);
+        assert!(matches!(authorize(Some(&admin), &[]).unwrap_err(), AuthError::Internal(_)));
+        assert_eq!(authorize(None, &[]).unwrap_err(), AuthError::Unauthenticated);
+    }
+
+    #[test]
+    fn department_lead_manages_only_unprivileged_users_in_own_department() {
+        let lead = caller(ROLE_DEPARTMENT_LEAD, Some("设计部"));
+
+        assert!(authorize_target(&lead, &caller(ROLE_USER, Some("设计部"))).is_ok());
+        assert_eq!(
+            authorize_target(&lead, &caller(ROLE_USER, Some("运营部"))).unwrap_err(),
+            AuthError::OutOfScope
+        );
+        assert_eq!(
+            authorize_target(&lead, &caller(ROLE_USER, None)).unwrap_err(),
+            AuthError::OutOfScope
+        );
+        assert_eq!(
+            authorize_target(&lead, &caller(ROLE_ADMIN, Some("设计部"))).unwrap_err(),
+            AuthError::TargetPrivileged { permission: Permission::ManageUsers }
+        );
+        assert!(authorize_target(&lead, &caller(ROLE_DEPARTMENT_LEAD, Some("设计部"))).is_ok());
+    }
+
+    #[test]
+    fn admin_manages_everyone() {
+        let admin = caller(ROLE_ADMIN, None);
+        for role in [ROLE_ADMIN, ROLE_USER, ROLE_DEPARTMENT_LEAD] {
+            assert!(authorize_target(&admin, &caller(role, Some("运营部"))).is_ok(), "{}", role);
+        }
+    }
+}
@@ -0,0 +1,399 @@
+use chrono::{DateTime, Duration, Utc};
+use jsonwebtoken::{decode, decode_header, Algorithm, DecodingKey, Validation};
+use ring::signature::{Ed25519KeyPair, KeyPair};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+use crate::auth::AppState;
+use crate::guard::{require, Permission};
+
+// 密钥清单文件名（位于签名密钥目录下）
+const MANIFEST_FILE: &str = "keyring.toml";
+const PEM_TAG: &str = "PRIVATE KEY";
+
+// 密钥清单：记录当前签名密钥和仍用于验证的旧密钥，私钥单独保存为 <kid>.pem
+#[derive(Debug, Serialize, Deserialize)]
+struct Manifest {
+    active_kid: String,
+    #[serde(default)]
+    keys: Vec<KeyEntry>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct KeyEntry {
+    kid: String,
+    created_at: DateTime<Utc>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    retired_at: Option<DateTime<Utc>>,
+}
+
+struct LoadedKey {
+    entry: KeyEntry,
+    decoding: DecodingKey,
+}
+
+// 签名密钥响应结构
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct SigningKeyResponse {
+    pub kid: String,
+    pub active: bool,
+    #[serde(rename = "createdAt")]
+    pub created_at: String,
+    #[serde(rename = "retiredAt")]
+    pub retired_at: Option<String>,
+    // 旧密钥停止验证的时间
+    #[serde(rename = "removeAfter")]
+    pub remove_after: Option<String>,
+}
+
+// Ed25519签名密钥环：按Token头中的 kid 选择验证密钥。
+// 登录不再签发JWT，密钥环只用于验证旧版本签发、尚未过期的记住我/自动登录Token
+pub struct KeyRing {
+    dir: PathBuf,
+    retired_key_days: i64,
+    active_kid: String,
+    keys: Vec<LoadedKey>,
+}
+
+impl KeyRing {
+    // 加载密钥目录，首次运行时生成第一把密钥；清理超过保留期的旧密钥
+    pub fn load(dir: &Path, retired_key_days: i64) -> Result<Self, String> {
+        let manifest_path = dir.join(MANIFEST_FILE);
+        if !manifest_path.exists() {
+            std::fs::create_dir_all(dir)
+                .map_err(|e| format!("创建签名密钥目录失败: {}", e))?;
+            let entry = generate_key(dir)?;
+            let manifest = Manifest {
+                active_kid: entry.kid.clone(),
+                keys: vec![entry],
+            };
+            write_manifest(dir, &manifest)?;
+            log::info!("🔑 已生成新的Token签名密钥: {}", manifest.active_kid);
+        }
+
+        let content = std::fs::read_to_string(&manifest_path)
+            .map_err(|e| format!("读取签名密钥清单失败: {}", e))?;
+        let mut manifest: Manifest = toml::from_str(&content)
+            .map_err(|e| format!("签名密钥清单格式错误: {}", e))?;
+
+        let now = Utc::now();
+        let before = manifest.keys.len();
+        manifest.keys.retain(|entry| {
+            entry.kid == manifest.active_kid || !is_expired(entry, now, retired_key_days)
+        });
+        if manifest.keys.len() != before {
+            write_manifest(dir, &manifest)?;
+            log::info!("🔑 已移除 {} 个超过保留期的旧签名密钥", before - manifest.keys.len());
+        }
+
+        if !manifest.keys.iter().any(|entry| entry.kid == manifest.active_kid) {
+            return Err(format!("签名密钥清单中找不到当前密钥 {}", manifest.active_kid));
+        }
+
+        let mut keys = Vec::with_capacity(manifest.keys.len());
+        for entry in manifest.keys {
+            let der = read_private_key(dir, &entry.kid)?;
+            let pair = Ed25519KeyPair::from_pkcs8_maybe_unchecked(&der)
+                .map_err(|e| format!("签名密钥 {} 无效: {}", entry.kid, e))?;
+            keys.push(LoadedKey {
+                decoding: DecodingKey::from_ed_der(pair.public_key().as_ref()),
+                entry,
+            });
+        }
+
+        Ok(KeyRing {
+            dir: dir.to_path_buf(),
+            retired_key_days,
+            active_kid: manifest.active_kid,
+            keys,
+        })
+    }
+
+    pub fn active_kid(&self) -> &str {
+        &self.active_kid
+    }
+
+    // 按 kid 查找当前或已退役的密钥验证Token
+    pub fn verify<T: DeserializeOwned>(&self, token: &str) -> Result<T, String> {
+        let header = decode_header(token).map_err(|e| format!("Token验证失败: {}", e))?;
+        let kid = header.kid.ok_or("Token验证失败: 缺少密钥标识")?;
+        let key = self.keys
+            .iter()
+            .find(|key| key.entry.kid == kid)
+            .ok_or("Token验证失败: 签名密钥不存在或已过期")?;
+
+        decode::<T>(token, &key.decoding, &Validation::new(Algorithm::EdDSA))
+            .map(|data| data.claims)
+            .map_err(|e| format!("Token验证失败: {}", e))
+    }
+
+    // 生成新密钥并设为当前密钥，原密钥退役但在保留期内继续用于验证
+    pub fn rotate(&mut self) -> Result<String, String> {
+        // 以磁盘上的清单为准，避免覆盖其他进程写入的密钥
+        let mut current = KeyRing::load(&self.dir, self.retired_key_days)?;
+
+        let entry = generate_key(&current.dir)?;
+        let now = Utc::now();
+        let mut manifest = Manifest {
+            active_kid: entry.kid.clone(),
+            keys: current.keys.drain(..).map(|key| key.entry).collect(),
+        };
+        for old in manifest.keys.iter_mut().filter(|key| key.retired_at.is_none()) {
+            old.retired_at = Some(now);
+        }
+        manifest.keys.push(entry);
+        write_manifest(&current.dir, &manifest)?;
+
+        *self = KeyRing::load(&current.dir, current.retired_key_days)?;
+        Ok(self.active_kid.clone())
+    }
+
+    pub fn summaries(&self) -> Vec<SigningKeyResponse> {
+        self.keys
+            .iter()
+            .map(|key| SigningKeyResponse {
+                kid: key.entry.kid.clone(),
+                active: key.entry.kid == self.active_kid,
+                created_at: key.entry.created_at.to_rfc3339(),
+                retired_at: key.entry.retired_at.map(|at| at.to_rfc3339()),
+                remove_after: key.entry.retired_at
+                    .map(|at| (at + Duration::days(self.retired_key_days)).to_rfc3339()),
+            })
+            .collect()
+    }
+}
+
+fn is_expired(entry: &KeyEntry, now: DateTime<Utc>, retired_key_days: i64) -> bool {
+    entry
+        .retired_at
+        .is_some_and(|at| at + Duration::days(retired_key_days) <= now)
+}
+
+// 生成Ed25519密钥，私钥以PKCS#8 PEM格式保存为 <kid>.pem
+fn generate_key(dir: &Path) -> Result<KeyEntry, String> {
+    let rng = ring::rand::SystemRandom::new();
+    let pkcs8 = Ed25519KeyPair::generate_pkcs8(&rng)
+        .map_err(|e| format!("生成签名密钥失败: {}", e))?;
+
+    let now = Utc::now();
+    let suffix = uuid::Uuid::new_v4().simple().to_string();
+    let kid = format!("{}-{}", now.format("%Y%m%d"), &suffix[..8]);
+
+    let content = pem::encode(&pem::Pem::new(PEM_TAG, pkcs8.as_ref().to_vec()));
+    write_private(&dir.join(format!("{}.pem", kid)), &content)?;
+
+    Ok(KeyEntry {
+        kid,
+        created_at: now,
+        retired_at: None,
+    })
+}
+
+fn read_private_key(dir: &Path, kid: &str) -> Result<Vec<u8>, String> {
+    let path = dir.join(format!("{}.pem", kid));
+    let content = std::fs::read(&path)
+        .map_err(|e| format!("读取签名密钥 {} 失败: {}", path.display(), e))?;
+    let parsed = pem::parse(content)
+        .map_err(|e| format!("签名密钥 {} 格式错误: {}", path.display(), e))?;
+    if parsed.tag() != PEM_TAG {
+        return Err(format!("签名密钥 {} 必须是PKCS#8格式的私钥", path.display()));
+    }
+    Ok(parsed.into_contents())
+}
+
+// 先写临时文件再替换，避免写到一半时清单损坏
+fn write_manifest(dir: &Path, manifest: &Manifest) -> Result<(), String> {
+    let content = toml::to_string_pretty(manifest)
+        .map_err(|e| format!("序列化签名密钥清单失败: {}", e))?;
+    let tmp = dir.join(format!("{}.tmp", MANIFEST_FILE));
+    write_private(&tmp, &content)?;
+    std::fs::rename(&tmp, dir.join(MANIFEST_FILE))
+        .map_err(|e| format!("保存签名密钥清单失败: {}", e))
+}
+
+fn write_private(path: &Path, content: &str) -> Result<(), String> {
+    std::fs::write(path, content)
+        .map_err(|e| format!("保存 {} 失败: {}", path.display(), e))?;
+
+    // 私钥文件仅允许当前用户读取
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        std::fs::set_permissions(path, std::fs::Permissions::from_mode(0o600))
+            .map_err(|e| format!("设置 {} 权限失败: {}", path.display(), e))?;
+    }
+    Ok(())
+}
+
+// 获取签名密钥列表 - 管理员功能
+#[tauri::command]
+pub async fn list_signing_keys(
+    state: tauri::State<'_, AppState>,
+) -> Result<Vec<SigningKeyResponse>, String> {
+    require(&state, Permission::ManageSigningKeys).await?;
+
+    let keyring = state.keyring.read().map_err(|_| "签名密钥环不可用".to_string())?;
+    Ok(keyring.summaries())
+}
+
+// 轮换Token签名密钥 - 管理员功能；已签发的Token在到期前仍然有效
+#[tauri::command]
+pub async fn rotate_signing_key(
+    state: tauri::State<'_, AppState>,
+) -> Result<Vec<SigningKeyResponse>, String> {
+    let current_user = require(&state, Permission::ManageSigningKeys).await?;
+
+    let mut keyring = state.keyring.write().map_err(|_| "签名密钥环不可用".to_string())?;
+    let previous = keyring.active_kid().to_string();
+    let kid = keyring.rotate()?;
+
+    log::info!("🔑 Token签名密钥已由 {} 轮换: {} -> {}", current_user.username, previous, kid);
+    Ok(keyring.summaries())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::guard::AuthError;
+    use crate::rbac::ROLE_USER;
+    use crate::test_support::{app_state, sign_in, TestDb};
+    use jsonwebtoken::{encode, EncodingKey, Header};
+    use tauri::Manager;
+
+    const RETIRED_KEY_DAYS: i64 = 30;
+
+    #[derive(Debug, Serialize, Deserialize)]
+    struct Claims {
+        sub: String,
+        exp: i64,
+    }
+
+    fn temp_dir() -> PathBuf {
+        std::env::temp_dir().join(format!("chengshang-test-{}", uuid::Uuid::new_v4().simple()))
+    }
+
+    // 用目录中 kid 对应的私钥签名，header_kid 为写入Token头的密钥标识
+    fn sign(dir: &Path, kid: &str, header_kid: &str, exp: DateTime<Utc>) -> String {
+        let key = EncodingKey::from_ed_der(&read_private_key(dir, kid).unwrap());
+        let header = Header { kid: Some(header_kid.to_string()), ..Header::new(Algorithm::EdDSA) };
+        let claims = Claims { sub: "alice".to_string(), exp: exp.timestamp() };
+        encode(&header, &claims, &key).unwrap()
+    }
+
+    fn valid_until() -> DateTime<Utc> {
+        Utc::now() + Duration::days(1)
+    }
+
+    #[test]
+    fn first_load_generates_an_active_key() {
+        let dir = temp_dir();
+        let keyring = KeyRing::load(&dir, RETIRED_KEY_DAYS).unwrap();
+        let kid = keyring.active_kid().to_string();
+        assert!(dir.join(MANIFEST_FILE).exists());
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = std::fs::metadata(dir.join(format!("{}.pem", kid))).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+
+        let summaries = keyring.summaries();
+        assert_eq!(summaries.len(), 1);
+        assert!(summaries[0].active && summaries[0].retired_at.is_none());
+
+        // 再次加载沿用已有密钥
+        assert_eq!(KeyRing::load(&dir, RETIRED_KEY_DAYS).unwrap().active_kid(), kid);
+        let token = sign(&dir, &kid, &kid, valid_until());
+        assert_eq!(keyring.verify::<Claims>(&token).unwrap().sub, "alice");
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn rotated_out_key_still_verifies() {
+        let dir = temp_dir();
+        let mut keyring = KeyRing::load(&dir, RETIRED_KEY_DAYS).unwrap();
+        let old = keyring.active_kid().to_string();
+        let old_token = sign(&dir, &old, &old, valid_until());
+
+        let new = keyring.rotate().unwrap();
+        assert_ne!(new, old);
+        assert_eq!(keyring.active_kid(), new);
+        assert_eq!(keyring.verify::<Claims>(&old_token).unwrap().sub, "alice");
+        assert_eq!(keyring.verify::<Claims>(&sign(&dir, &new, &new, valid_until())).unwrap().sub, "alice");
+
+        let summaries = keyring.summaries();
+        let retired = summaries.iter().find(|key| key.kid == old).unwrap();
+        assert!(!retired.active && retired.retired_at.is_some() && retired.remove_after.is_some());
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn keys_past_retention_are_pruned_on_load() {
+        let dir = temp_dir();
+        let mut keyring = KeyRing::load(&dir, RETIRED_KEY_DAYS).unwrap();
+        let old = keyring.active_kid().to_string();
+        let old_token = sign(&dir, &old, &old, valid_until());
+        keyring.rotate().unwrap();
+
+        // 把旧密钥的退役时间提前到保留期之外
+        let content = std::fs::read_to_string(dir.join(MANIFEST_FILE)).unwrap();
+        let mut manifest: Manifest = toml::from_str(&content).unwrap();
+        for entry in manifest.keys.iter_mut().filter(|entry| entry.kid == old) {
+            entry.retired_at = Some(Utc::now() - Duration::days(RETIRED_KEY_DAYS + 1));
+        }
+        write_manifest(&dir, &manifest).unwrap();
+
+        let keyring = KeyRing::load(&dir, RETIRED_KEY_DAYS).unwrap();
+        assert_eq!(keyring.summaries().len(), 1);
+        assert_eq!(
+            keyring.verify::<Claims>(&old_token).unwrap_err(),
+            "Token验证失败: 签名密钥不存在或已过期"
+        );
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn unknown_kid_and_expired_tokens_are_rejected() {
+        let dir = temp_dir();
+        let keyring = KeyRing::load(&dir, RETIRED_KEY_DAYS).unwrap();
+        let kid = keyring.active_kid().to_string();
+
+        assert_eq!(
+            keyring.verify::<Claims>(&sign(&dir, &kid, "unknown", valid_until())).unwrap_err(),
+            "Token验证失败: 签名密钥不存在或已过期"
+        );
+        let expired = sign(&dir, &kid, &kid, Utc::now() - Duration::hours(1));
+        assert!(keyring.verify::<Claims>(&expired).is_err());
+
+        // 用其他密钥签名却声称是当前密钥
+        let other = temp_dir();
+        let forged_kid = KeyRing::load(&other, RETIRED_KEY_DAYS).unwrap().active_kid().to_string();
+        assert!(keyring.verify::<Claims>(&sign(&other, &forged_kid, &kid, valid_until())).is_err());
+        let _ = std::fs::remove_dir_all(dir);
+        let _ = std::fs::remove_dir_all(other);
+    }
+
+    #[tokio::test]
+    #[ignore = "需要本机 mongod"]
+    async fn key_commands_require_permission() {
+        let db = TestDb::new("key_commands").await;
+        let dir = std::env::temp_dir().join(format!("chengshang-test-{}", uuid::Uuid::new_v4().simple()));
+        let app = tauri::test::mock_app();
+        app.manage(app_state(&db, &dir).await);
+        let state = app.state::<AppState>();
+        let active = state.keyring.read().unwrap().active_kid().to_string();
+
+        let forbidden = AuthError::Forbidden { permission: Permission::ManageSigningKeys }.to_string();
+        for expected in [AuthError::Unauthenticated.to_string(), forbidden] {
+            assert_eq!(list_signing_keys(state.clone()).await.unwrap_err(), expected);
+            assert_eq!(rotate_signing_key(state.clone()).await.unwrap_err(), expected);
+
+            sign_in(&state, "plain", ROLE_USER).await;
+        }
+        // 被拒绝的轮换不能生效
+        assert_eq!(state.keyring.read().unwrap().active_kid(), active);
+
+        db.drop().await;
+        let _ = std::fs::remove_dir_all(dir);
+    }
+}
@@ -1,6 +1,30 @@
 use tauri::Manager;
 
+mod active_time;
+mod activity;
 mod auth;
+mod config;
+mod daily_stats;
+mod device;
+mod event_queue;
+mod export;
+mod guard;
+mod keyring;
+mod lockout;
+mod metrics;
+mod password;
+mod rbac;
+mod refresh_token;
+mod report;
+mod retention;
+mod secret;
+mod session;
+#[cfg(test)]
+mod test_support;
+mod tool_access;
+mod tool_analytics;
+mod tools;
+mod totp;
 
 // 添加调试信息命令
 #[tauri::command]
@@ -62,9 +86,21 @@ async fn open_url(url: String) -> Result<String, String> {
 async fn create_kiosk_window(
   app: tauri::AppHandle,
   url: String,
-  title: String
+  title: String,
+  tool_id: Option<i32>,
+  state: tauri::State<'_, auth::AppState>
 ) -> Result<String, String> {
   use tauri::{WebviewUrl, WebviewWindowBuilder};
+
+  // 拒绝打开当前用户无权使用的工具
+  let current_user = guard::current_caller(&state).await?;
+  {
+    let mongo = state.mongo.read().await;
+    if let Some(tool_id) = tool_id {
+      tool_access::ensure_tool_allowed(&mongo, &current_user, tool_id).await?;
+    }
+    tool_access::ensure_url_allowed(&mongo, &current_user, &url).await?;
+  }
   
   let window_label = format!("kiosk_{}", chrono::Utc::now().timestamp_millis());
   
@@ -143,7 +179,13 @@ async fn create_kiosk_window(
       .center()
       .build()
       {
-        Ok(_) => Ok(format!("已在无地址栏窗口打开: {}", title)),
+        Ok(_) => {
+          // 工具窗口在前台的时间计入该工具的使用时长
+          if let Some(tool_id) = tool_id {
+            state.active_time.register_tool_window(&window_label, tool_id);
+          }
+          Ok(format!("已在无地址栏窗口打开: {}", title))
+        },
         Err(e) => Err(format!("创建窗口失败: {}", e))
       }
     },
@@ -155,14 +197,17 @@ async fn create_kiosk_window(
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
 pub fn run() {
   tauri::Builder::default()
+    // 跟踪主窗口和工具窗口的焦点变化，用于统计前台使用时长
+    .on_window_event(|window, event| {
+      if let Some(state) = window.try_state::<auth::AppState>() {
+        match event {
+          tauri::WindowEvent::Focused(focused) => state.active_time.set_focus(window.label(), *focused),
+          tauri::WindowEvent::Destroyed => state.active_time.window_closed(window.label()),
+          _ => {}
+        }
+      }
+    })
     .setup(|app| {
-      // 初始化MongoDB连接和应用状态
-      let app_state = tauri::async_runtime::block_on(async {
-        auth::AppState::new().await
-      }).expect("Failed to initialize app state");
-
-      app.manage(app_state);
-
       // 强制启用日志插件，便于调试
       app.handle().plugin(
         tauri_plugin_log::Builder::default()
@@ -170,6 +215,37 @@ pub fn run() {
           .build(),
       )?;
 
+      // 加载配置（配置文件 + 环境变量），配置无效时直接终止启动
+      let config_dir = app.path().app_config_dir()?;
+      let app_config = config::AppConfig::load(&config_dir).map_err(|e| {
+        log::error!("❌ 配置加载失败: {}", e);
+        e
+      })?;
+
+      // 本机设备信息，用于登录失败的按设备统计和多设备Token管理
+      let data_dir = app.path().app_data_dir()?;
+      let device_info = device::DeviceInfo::collect(&data_dir, app.package_info().version.to_string())?;
+      log::info!("🖥️ 当前设备: {} ({}, {})", device_info.hostname, device_info.os, device_info.device_id);
+
+      // 本地活动事件队列，断网时事件先保存在本机
+      let event_queue = event_queue::EventQueue::open(&data_dir)?;
+
+      // 初始化MongoDB连接和应用状态
+      let app_state = tauri::async_runtime::block_on(async {
+        auth::AppState::new(app_config, device_info, event_queue).await
+      }).expect("Failed to initialize app state");
+
+      // 会话心跳和超时会话清理
+      session::spawn_heartbeat(&app_state);
+      // 前台使用时长采样
+      active_time::spawn_sampler(&app_state);
+      // 活动事件批量上传
+      event_queue::spawn_uploader(&app_state);
+      // 每周使用报告（仅在启用且管理员登录时生成）
+      report::spawn_scheduler(&app_state);
+
+      app.manage(app_state);
+
       // 获取主窗口并确保可见
       if let Some(window) = app.get_webview_window("main") {
         // 确保窗口可见并置于前台
@@ -201,15 +277,24 @@ pub fn run() {
       get_debug_info,
       open_url,
       create_kiosk_window,
+      activity::record_activity,
+      event_queue::get_event_queue_status,
       auth::login,
       auth::logout,
       auth::check_session,
       auth::verify_token_and_login,
+      refresh_token::refresh_session,
       auth::get_all_users_admin,
       auth::get_system_overview,
       auth::track_user_activity,
       auth::get_user_analytics,
       auth::get_system_analytics,
+      daily_stats::rebuild_daily_stats,
+      retention::get_retention_cohorts,
+      retention::get_churned_users,
+      tool_analytics::get_tool_analytics,
+      export::export_analytics,
+      report::generate_weekly_report,
       auth::generate_test_data,
       auth::clear_test_data,
       auth::debug_user_data,
@@ -221,7 +306,38 @@ pub fn run() {
       auth::edit_user,
       auth::delete_user,
       auth::reset_user_password,
-      auth::toggle_user_status
+      auth::change_password,
+      auth::toggle_user_status,
+      rbac::list_roles,
+      rbac::create_role,
+      rbac::edit_role,
+      rbac::delete_role,
+      rbac::assign_user_roles,
+      device::list_my_devices,
+      device::rename_device,
+      device::revoke_device,
+      device::list_user_devices,
+      keyring::list_signing_keys,
+      keyring::rotate_signing_key,
+      lockout::get_login_lockouts,
+      lockout::unlock_user,
+      tool_access::get_accessible_tools,
+      tool_access::list_tool_grants,
+      tool_access::set_tool_grant,
+      tool_access::remove_tool_grant,
+      tools::list_tools,
+      tools::list_all_tools,
+      tools::create_tool,
+      tools::import_tools,
+      tools::update_tool,
+      tools::set_tool_enabled,
+      tools::reorder_tools,
+      totp::verify_totp,
+      totp::get_totp_status,
+      totp::begin_totp_enrollment,
+      totp::confirm_totp_enrollment,
+      totp::disable_totp,
+      totp::reset_user_totp
     ])
     .run(tauri::generate_context!())
     .expect("error while running tauri application");
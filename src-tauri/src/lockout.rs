@@ -0,0 +1,475 @@
+use futures::TryStreamExt;
+use mongodb::{
+    bson::{doc, oid::ObjectId, DateTime, Document},
+    options::{IndexOptions, ReturnDocument},
+    IndexModel,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::auth::{AppState, MongoManager};
+use crate::config::LockoutConfig;
+use crate::guard::{require, require_any, require_target, AuthError, Permission};
+
+// 登录失败的计数维度
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AttemptScope {
+    // 按用户名计数
+    Account,
+    // 按设备计数
+    Device,
+}
+
+impl AttemptScope {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AttemptScope::Account => "account",
+            AttemptScope::Device => "device",
+        }
+    }
+}
+
+// 登录失败记录（集合 login_attempts，每个用户名/设备一条）
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct LoginAttempt {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    pub id: Option<ObjectId>,
+    pub scope: AttemptScope,
+    pub key: String,
+    pub failures: u32,
+    #[serde(rename = "firstFailedAt")]
+    pub first_failed_at: DateTime,
+    #[serde(rename = "lastFailedAt")]
+    pub last_failed_at: DateTime,
+    #[serde(rename = "lockedUntil", default, skip_serializing_if = "Option::is_none")]
+    pub locked_until: Option<DateTime>,
+}
+
+// 登录失败记录响应结构
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct LoginAttemptResponse {
+    pub scope: AttemptScope,
+    pub key: String,
+    pub failures: u32,
+    #[serde(rename = "lastFailedAt")]
+    pub last_failed_at: String,
+    #[serde(rename = "lockedUntil")]
+    pub locked_until: Option<String>,
+    #[serde(rename = "retryAfterSecs")]
+    pub retry_after_secs: i64,
+}
+
+impl LoginAttempt {
+    // 锁定已过期或统计窗口已过，之前的失败不再计数
+    pub fn is_stale(&self, now: DateTime, config: &LockoutConfig) -> bool {
+        if let Some(locked_until) = self.locked_until {
+            return locked_until <= now;
+        }
+        let window_ms = config.window_minutes * 60_000;
+        now.timestamp_millis() - self.first_failed_at.timestamp_millis() >= window_ms
+    }
+
+    // 第 n 次失败后需要等待的时间：base * 2^(n-1)，不超过上限
+    pub fn backoff_secs(&self, config: &LockoutConfig) -> i64 {
+        if self.failures == 0 {
+            return 0;
+        }
+        let exponent = (self.failures - 1).min(30);
+        config
+            .backoff_base_secs
+            .saturating_mul(1_i64 << exponent)
+            .min(config.backoff_max_secs)
+    }
+
+    // 距离允许再次尝试还需等待的秒数，0 表示可以尝试
+    pub fn retry_after_secs(&self, now: DateTime, config: &LockoutConfig) -> i64 {
+        if self.is_stale(now, config) {
+            return 0;
+        }
+        let until_ms = match self.locked_until {
+            Some(locked_until) => locked_until.timestamp_millis(),
+            None => self.last_failed_at.timestamp_millis() + self.backoff_secs(config) * 1000,
+        };
+        let remaining_ms = until_ms - now.timestamp_millis();
+        if remaining_ms <= 0 {
+            0
+        } else {
+            (remaining_ms + 999) / 1000
+        }
+    }
+
+    // 检查当前是否允许尝试登录
+    pub fn check(&self, now: DateTime, config: &LockoutConfig) -> Result<(), AuthError> {
+        let retry_after_secs = self.retry_after_secs(now, config);
+        if retry_after_secs == 0 {
+            Ok(())
+        } else if self.locked_until.is_some() {
+            Err(AuthError::AccountLocked { retry_after_secs })
+        } else {
+            Err(AuthError::LoginThrottled { retry_after_secs })
+        }
+    }
+
+    pub fn to_response(&self, now: DateTime, config: &LockoutConfig) -> LoginAttemptResponse {
+        LoginAttemptResponse {
+            scope: self.scope,
+            key: self.key.clone(),
+            failures: self.failures,
+            last_failed_at: self.last_failed_at.try_to_rfc3339_string().unwrap_or_default(),
+            locked_until: self
+                .locked_until
+                .filter(|until| *until > now)
+                .map(|until| until.try_to_rfc3339_string().unwrap_or_default()),
+            retry_after_secs: self.retry_after_secs(now, config),
+        }
+    }
+}
+
+pub async fn ensure_indexes(mongo: &MongoManager) -> Result<(), String> {
+    mongo.login_attempts()
+        .create_index(
+            IndexModel::builder()
+                .keys(doc! {"scope": 1, "key": 1})
+                .options(IndexOptions::builder().unique(true).build())
+                .build(),
+        )
+        .await
+        .map_err(|e| format!("创建登录失败记录索引失败: {}", e))?;
+    Ok(())
+}
+
+fn scoped_keys<'a>(username: &'a str, device_id: &'a str) -> [(AttemptScope, &'a str); 2] {
+    [
+        (AttemptScope::Account, username),
+        (AttemptScope::Device, device_id),
+    ]
+}
+
+async fn find_attempt(
+    mongo: &MongoManager,
+    scope: AttemptScope,
+    key: &str,
+) -> Result<Option<LoginAttempt>, AuthError> {
+    mongo.login_attempts()
+        .find_one(doc! {"scope": scope.as_str(), "key": key})
+        .await
+        .map_err(|e| AuthError::Internal(format!("查询登录失败记录失败: {}", e)))
+}
+
+// 登录前检查账号和设备是否处于锁定或等待期；查询失败时拒绝登录
+pub async fn ensure_can_attempt(
+    mongo: &MongoManager,
+    config: &LockoutConfig,
+    username: &str,
+    device_id: &str,
+) -> Result<(), AuthError> {
+    let now = DateTime::now();
+    for (scope, key) in scoped_keys(username, device_id) {
+        if let Some(attempt) = find_attempt(mongo, scope, key).await? {
+            attempt.check(now, config).map_err(|e| {
+                log::warn!("🔒 登录被限制 - {:?} {}: {}", scope, key, e);
+                e
+            })?;
+        }
+    }
+    Ok(())
+}
+
+// 锁定已到期或统计窗口已过的失败记录，与 LoginAttempt::is_stale 的判断一致
+fn stale_filter(now: DateTime, config: &LockoutConfig) -> Document {
+    let window_start = DateTime::from_millis(now.timestamp_millis() - config.window_minutes * 60_000);
+    doc! {
+        "$or": [
+            {"lockedUntil": {"$lte": now}},
+            {"lockedUntil": {"$exists": false}, "firstFailedAt": {"$lte": window_start}}
+        ]
+    }
+}
+
+// 记录一次登录失败（账号和设备各计一次）
+pub async fn record_failure(
+    mongo: &MongoManager,
+    config: &LockoutConfig,
+    username: &str,
+    device_id: &str,
+) -> Result<(), AuthError> {
+    record_failure_at(mongo, config, username, device_id, DateTime::now()).await
+}
+
+// 失败次数用一次原子的 $inc 累加，同时进行的多次失败不会互相覆盖
+async fn record_failure_at(
+    mongo: &MongoManager,
+    config: &LockoutConfig,
+    username: &str,
+    device_id: &str,
+    now: DateTime,
+) -> Result<(), AuthError> {
+    for (scope, key) in scoped_keys(username, device_id) {
+        let filter = doc! {"scope": scope.as_str(), "key": key};
+
+        // 之前的失败已过期时从头计数
+        let mut stale = filter.clone();
+        stale.extend(stale_filter(now, config));
+        mongo.login_attempts()
+            .update_one(
+                stale,
+                doc! {"$set": {"failures": 0, "firstFailedAt": now}, "$unset": {"lockedUntil": ""}},
+            )
+            .await
+            .map_err(|e| AuthError::Internal(format!("重置登录失败记录失败: {}", e)))?;
+
+        let attempt = mongo.login_attempts()
+            .find_one_and_update(
+                filter,
+                doc! {
+                    "$inc": {"failures": 1},
+                    "$set": {"lastFailedAt": now},
+                    "$setOnInsert": {"firstFailedAt": now}
+                },
+            )
+            .upsert(true)
+            .return_document(ReturnDocument::After)
+            .await
+            .map_err(|e| AuthError::Internal(format!("保存登录失败记录失败: {}", e)))?
+            .ok_or_else(|| AuthError::Internal("保存登录失败记录失败".to_string()))?;
+
+        // 达到阈值时锁定，已锁定的不再延长
+        if attempt.failures >= config.max_failures && attempt.locked_until.is_none() {
+            let locked_until = DateTime::from_millis(now.timestamp_millis() + config.lockout_minutes * 60_000);
+            mongo.login_attempts()
+                .update_one(
+                    doc! {"_id": attempt.id, "lockedUntil": {"$exists": false}},
+                    doc! {"$set": {"lockedUntil": locked_until}},
+                )
+                .await
+                .map_err(|e| AuthError::Internal(format!("保存登录锁定失败: {}", e)))?;
+            log::warn!("🔒 连续登录失败 {} 次，已锁定 {:?} {}", attempt.failures, scope, key);
+        }
+    }
+    Ok(())
+}
+
+// 登录成功后清除账号和设备的失败计数
+pub async fn record_success(
+    mongo: &MongoManager,
+    username: &str,
+    device_id: &str,
+) -> Result<(), String> {
+    for (scope, key) in scoped_keys(username, device_id) {
+        mongo.login_attempts()
+            .delete_one(doc! {"scope": scope.as_str(), "key": key})
+            .await
+            .map_err(|e| format!("清除登录失败记录失败: {}", e))?;
+    }
+    Ok(())
+}
+
+// 获取当前处于锁定或等待期的账号和设备 - 管理员功能
+#[tauri::command]
+pub async fn get_login_lockouts(
+    state: tauri::State<'_, AppState>,
+) -> Result<Vec<LoginAttemptResponse>, String> {
+    require(&state, Permission::ManageUsers).await?;
+
+    let config = &state.config.lockout;
+    let now = DateTime::now();
+    let window_start = DateTime::from_millis(now.timestamp_millis() - config.window_minutes * 60_000);
+
+    let mongo = state.mongo.read().await;
+    let cursor = mongo.login_attempts()
+        .find(doc! {
+            "$or": [
+                {"lockedUntil": {"$gt": now}},
+                {"lockedUntil": {"$exists": false}, "firstFailedAt": {"$gt": window_start}}
+            ]
+        })
+        .sort(doc! {"lastFailedAt": -1})
+        .await
+        .map_err(|e| format!("查询登录失败记录失败: {}", e))?;
+    let attempts: Vec<LoginAttempt> = cursor.try_collect().await.map_err(|e| format!("读取登录失败记录失败: {}", e))?;
+
+    Ok(attempts.iter().map(|attempt| attempt.to_response(now, config)).collect())
+}
+
+// 解除用户的登录锁定 - 管理员功能
+#[tauri::command]
+pub async fn unlock_user(
+    user_id: String,
+    state: tauri::State<'_, AppState>,
+) -> Result<(), String> {
+    let current_user = require_any(&state, &[Permission::ManageUsers, Permission::ManageDepartmentUsers]).await?;
+
+    let user_object_id = ObjectId::parse_str(&user_id)
+        .map_err(|e| format!("无效的用户ID: {}", e))?;
+
+    let mongo = state.mongo.read().await;
+    let target = mongo.users()
+        .find_one(doc! {"_id": user_object_id})
+        .await
+        .map_err(|e| format!("查询用户失败: {}", e))?
+        .ok_or("用户不存在")?;
+
+    require_target(&mongo, &current_user, &target).await?;
+
+    mongo.login_attempts()
+        .delete_one(doc! {"scope": AttemptScope::Account.as_str(), "key": &target.username})
+        .await
+        .map_err(|e| format!("解除锁定失败: {}", e))?;
+
+    log::info!("🔓 用户 {} 的登录锁定已被 {} 解除", target.username, current_user.username);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::TestDb;
+
+    const MINUTE_MS: i64 = 60_000;
+
+    fn config() -> LockoutConfig {
+        LockoutConfig {
+            max_failures: 3,
+            window_minutes: 15,
+            lockout_minutes: 30,
+            backoff_base_secs: 1,
+            backoff_max_secs: 8,
+        }
+    }
+
+    fn at(minutes: i64) -> DateTime {
+        DateTime::from_millis(1_700_000_000_000 + minutes * MINUTE_MS)
+    }
+
+    fn attempt(failures: u32, first: DateTime, last: DateTime, locked_until: Option<DateTime>) -> LoginAttempt {
+        LoginAttempt {
+            id: None,
+            scope: AttemptScope::Account,
+            key: "alice".to_string(),
+            failures,
+            first_failed_at: first,
+            last_failed_at: last,
+            locked_until,
+        }
+    }
+
+    #[test]
+    fn failures_expire_after_window() {
+        let config = config();
+        let attempt = attempt(2, at(0), at(1), None);
+        assert!(!attempt.is_stale(at(14), &config));
+        assert!(attempt.is_stale(at(15), &config));
+        assert_eq!(attempt.retry_after_secs(at(15), &config), 0);
+    }
+
+    #[test]
+    fn lock_expires_after_lockout() {
+        let config = config();
+        let attempt = attempt(3, at(0), at(2), Some(at(32)));
+        assert_eq!(
+            attempt.check(at(31), &config).unwrap_err(),
+            AuthError::AccountLocked { retry_after_secs: 60 }
+        );
+        assert!(attempt.check(at(32), &config).is_ok());
+    }
+
+    #[test]
+    fn backoff_doubles_up_to_max() {
+        let config = config();
+        let secs: Vec<i64> = (1..=6)
+            .map(|failures| attempt(failures, at(0), at(0), None).backoff_secs(&config))
+            .collect();
+        assert_eq!(secs, vec![1, 2, 4, 8, 8, 8]);
+        assert_eq!(
+            attempt(2, at(0), at(0), None).check(at(0), &config).unwrap_err(),
+            AuthError::LoginThrottled { retry_after_secs: 2 }
+        );
+    }
+
+    async fn account_attempt(mongo: &MongoManager) -> Option<LoginAttempt> {
+        find_attempt(mongo, AttemptScope::Account, "alice").await.unwrap()
+    }
+
+    #[tokio::test]
+    #[ignore = "需要本机 mongod"]
+    async fn failures_lock_account_within_window() {
+        let db = TestDb::new("lockout_lock").await;
+        let config = config();
+        for minute in 0..3 {
+            record_failure_at(&db.mongo, &config, "alice", "device-1", at(minute)).await.unwrap();
+        }
+
+        let attempt = account_attempt(&db.mongo).await.unwrap();
+        assert_eq!(attempt.failures, 3);
+        assert_eq!(attempt.first_failed_at, at(0));
+        assert_eq!(attempt.locked_until, Some(at(32)));
+        let device = find_attempt(&db.mongo, AttemptScope::Device, "device-1").await.unwrap().unwrap();
+        assert_eq!(device.failures, 3);
+        db.drop().await;
+    }
+
+    #[tokio::test]
+    #[ignore = "需要本机 mongod"]
+    async fn failures_restart_after_window_expires() {
+        let db = TestDb::new("lockout_window").await;
+        let config = config();
+        record_failure_at(&db.mongo, &config, "alice", "device-1", at(0)).await.unwrap();
+        record_failure_at(&db.mongo, &config, "alice", "device-1", at(1)).await.unwrap();
+        record_failure_at(&db.mongo, &config, "alice", "device-1", at(15)).await.unwrap();
+
+        let attempt = account_attempt(&db.mongo).await.unwrap();
+        assert_eq!(attempt.failures, 1);
+        assert_eq!(attempt.first_failed_at, at(15));
+        assert_eq!(attempt.locked_until, None);
+        db.drop().await;
+    }
+
+    #[tokio::test]
+    #[ignore = "需要本机 mongod"]
+    async fn failures_restart_after_lock_expires() {
+        let db = TestDb::new("lockout_expired").await;
+        let config = config();
+        for minute in 0..3 {
+            record_failure_at(&db.mongo, &config, "alice", "device-1", at(minute)).await.unwrap();
+        }
+        record_failure_at(&db.mongo, &config, "alice", "device-1", at(40)).await.unwrap();
+
+        let attempt = account_attempt(&db.mongo).await.unwrap();
+        assert_eq!(attempt.failures, 1);
+        assert_eq!(attempt.locked_until, None);
+        db.drop().await;
+    }
+
+    #[tokio::test]
+    #[ignore = "需要本机 mongod"]
+    async fn concurrent_failures_are_all_counted() {
+        let db = TestDb::new("lockout_concurrent").await;
+        let config = LockoutConfig { max_failures: 100, ..config() };
+        let failures = (0..20).map(|_| record_failure_at(&db.mongo, &config, "alice", "device-1", at(0)));
+        for result in futures::future::join_all(failures).await {
+            result.unwrap();
+        }
+
+        assert_eq!(account_attempt(&db.mongo).await.unwrap().failures, 20);
+        db.drop().await;
+    }
+
+    #[tokio::test]
+    #[ignore = "需要本机 mongod"]
+    async fn success_resets_failures() {
+        let db = TestDb::new("lockout_success").await;
+        let config = config();
+        record_failure_at(&db.mongo, &config, "alice", "device-1", at(0)).await.unwrap();
+        record_failure_at(&db.mongo, &config, "alice", "device-1", at(1)).await.unwrap();
+        record_success(&db.mongo, "alice", "device-1").await.unwrap();
+
+        assert!(account_attempt(&db.mongo).await.is_none());
+        ensure_can_attempt(&db.mongo, &config, "alice", "device-1").await.unwrap();
+
+        record_failure_at(&db.mongo, &config, "alice", "device-1", at(2)).await.unwrap();
+        let attempt = account_attempt(&db.mongo).await.unwrap();
+        assert_eq!(attempt.failures, 1);
+        assert_eq!(attempt.first_failed_at, at(2));
+        db.drop().await;
+    }
+}
@@ -0,0 +1,597 @@
+use chrono::{Datelike, Duration, Months, NaiveDate, TimeZone, Utc};
+use chrono_tz::Tz;
+use mongodb::{
+    bson::{doc, oid::ObjectId, Bson, DateTime, Document},
+    Collection,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+
+use crate::auth::{DailyGrowth, DailyUsage, MongoManager, PopularTool};
+use crate::daily_stats;
+
+// 未指定时间范围时默认统计最近30天
+const DEFAULT_RANGE_DAYS: i64 = 30;
+const MAX_BUCKETS: usize = 366;
+// 周活、月活统计的自然日天数（含今天）
+const WAU_DAYS: i64 = 7;
+const MAU_DAYS: i64 = 30;
+
+// 活跃用户：统计区间内登录过、打开过工具或有前台使用时长记录的用户（按用户去重），
+// 即 daily_stats 中有当天汇总的用户。使用时长每分钟左右写入一次，
+// 跨零点持续使用的用户在第二天同样计为活跃
+
+// 趋势统计粒度
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Granularity {
+    Day,
+    Week,
+    Month,
+}
+
+impl Granularity {
+    pub fn parse(value: &str) -> Result<Self, String> {
+        match value {
+            "day" => Ok(Granularity::Day),
+            "week" => Ok(Granularity::Week),
+            "month" => Ok(Granularity::Month),
+            other => Err(format!("无效的统计粒度: {}，支持 day、week、month", other)),
+        }
+    }
+
+    // 对应 $dateTrunc 的 unit
+    pub fn unit(&self) -> &'static str {
+        match self {
+            Granularity::Day => "day",
+            Granularity::Week => "week",
+            Granularity::Month => "month",
+        }
+    }
+
+    // 所在分桶的第一天（周从周一开始）
+    pub fn truncate(&self, date: NaiveDate) -> NaiveDate {
+        match self {
+            Granularity::Day => date,
+            Granularity::Week => date - Duration::days(date.weekday().num_days_from_monday() as i64),
+            Granularity::Month => date.with_day(1).unwrap_or(date),
+        }
+    }
+
+    pub fn next(&self, bucket: NaiveDate) -> NaiveDate {
+        match self {
+            Granularity::Day => bucket + Duration::days(1),
+            Granularity::Week => bucket + Duration::days(7),
+            Granularity::Month => bucket + Months::new(1),
+        }
+    }
+}
+
+// 业务时区的今天
+pub fn today(tz: Tz) -> NaiveDate {
+    Utc::now().with_timezone(&tz).date_naive()
+}
+
+// 业务时区某天零点对应的时间点
+pub fn day_start(tz: Tz, date: NaiveDate) -> DateTime {
+    let midnight = date.and_hms_opt(0, 0, 0).unwrap_or_default();
+    // 夏令时切换导致零点不存在时取当天最早的有效时间
+    let start = tz
+        .from_local_datetime(&midnight)
+        .earliest()
+        .or_else(|| tz.from_local_datetime(&(midnight + Duration::hours(1))).earliest())
+        .map(|start| start.timestamp_millis())
+        .unwrap_or_else(|| midnight.and_utc().timestamp_millis());
+    DateTime::from_millis(start)
+}
+
+// 时间点在业务时区的日期
+pub fn business_date(tz: Tz, at: DateTime) -> NaiveDate {
+    chrono::DateTime::from_timestamp_millis(at.timestamp_millis())
+        .unwrap_or_default()
+        .with_timezone(&tz)
+        .date_naive()
+}
+
+// 时间点所在的业务日零点
+pub fn business_day(tz: Tz, at: DateTime) -> DateTime {
+    day_start(tz, business_date(tz, at))
+}
+
+// 统计时间范围（按业务时区的日期，包含首尾两天）
+#[derive(Debug, Clone, Copy)]
+pub struct DateRange {
+    pub start: NaiveDate,
+    pub end: NaiveDate,
+    pub granularity: Granularity,
+    pub tz: Tz,
+}
+
+impl DateRange {
+    // 日期格式为 YYYY-MM-DD；未指定结束日期时为今天，未指定开始日期时为结束日期前30天
+    pub fn parse(
+        start_date: Option<String>,
+        end_date: Option<String>,
+        granularity: Option<String>,
+        tz: Tz,
+    ) -> Result<Self, String> {
+        let parse_date = |value: &str| {
+            NaiveDate::parse_from_str(value.trim(), "%Y-%m-%d")
+                .map_err(|e| format!("无效的日期 {}: {}", value, e))
+        };
+
+        let end = match end_date.as_deref() {
+            Some(value) => parse_date(value)?,
+            None => today(tz),
+        };
+        let start = match start_date.as_deref() {
+            Some(value) => parse_date(value)?,
+            None => end - Duration::days(DEFAULT_RANGE_DAYS - 1),
+        };
+        if start > end {
+            return Err("开始日期不能晚于结束日期".to_string());
+        }
+
+        let granularity = match granularity.as_deref() {
+            Some(value) => Granularity::parse(value)?,
+            None => Granularity::Day,
+        };
+
+        let range = DateRange { start, end, granularity, tz };
+        if range.buckets().len() > MAX_BUCKETS {
+            return Err(format!("时间范围过大，最多 {} 个统计区间", MAX_BUCKETS));
+        }
+        Ok(range)
+    }
+
+    // 范围内所有分桶的起始日期
+    pub fn buckets(&self) -> Vec<NaiveDate> {
+        let mut buckets = Vec::new();
+        let mut bucket = self.granularity.truncate(self.start);
+        while bucket <= self.end && buckets.len() <= MAX_BUCKETS {
+            buckets.push(bucket);
+            bucket = self.granularity.next(bucket);
+        }
+        buckets
+    }
+
+    // 查询下界：开始日期零点
+    pub fn lower_bound(&self) -> DateTime {
+        day_start(self.tz, self.start)
+    }
+
+    // 查询上界（不含）：结束日期次日零点
+    pub fn upper_bound(&self) -> DateTime {
+        day_start(self.tz, self.end + Duration::days(1))
+    }
+}
+
+pub fn bucket_key(date: NaiveDate) -> String {
+    date.format("%Y-%m-%d").to_string()
+}
+
+// 聚合结果中的数值字段可能是 Int32、Int64 或 Double
+pub fn get_number(doc: &Document, key: &str) -> i64 {
+    match doc.get(key) {
+        Some(Bson::Int32(value)) => *value as i64,
+        Some(Bson::Int64(value)) => *value,
+        Some(Bson::Double(value)) => *value as i64,
+        _ => 0,
+    }
+}
+
+fn get_user_ids(doc: &Document, key: &str) -> HashSet<ObjectId> {
+    doc.get_array(key)
+        .map(|values| values.iter().filter_map(Bson::as_object_id).collect())
+        .unwrap_or_default()
+}
+
+// 按时间字段分桶聚合，返回 分桶起始日期 -> 聚合结果
+pub async fn bucket_totals<T: Send + Sync>(
+    collection: Collection<T>,
+    time_field: &str,
+    filter: Document,
+    range: &DateRange,
+    accumulators: Document,
+) -> Result<HashMap<String, Document>, String> {
+    let mut match_stage = filter;
+    match_stage.insert(time_field, doc! {"$gte": range.lower_bound(), "$lt": range.upper_bound()});
+
+    let mut group_stage = doc! {
+        "_id": {
+            "$dateToString": {
+                "format": "%Y-%m-%d",
+                "timezone": range.tz.name(),
+                "date": {
+                    "$dateTrunc": {
+                        "date": format!("${}", time_field),
+                        "unit": range.granularity.unit(),
+                        "timezone": range.tz.name(),
+                        "startOfWeek": "monday"
+                    }
+                }
+            }
+        }
+    };
+    group_stage.extend(accumulators);
+
+    let mut cursor = collection
+        .aggregate(vec![doc! {"$match": match_stage}, doc! {"$group": group_stage}])
+        .await
+        .map_err(|e| format!("趋势统计聚合失败: {}", e))?;
+
+    let mut totals = HashMap::new();
+    while cursor.advance().await.map_err(|e| format!("遍历趋势统计失败: {}", e))? {
+        let doc = cursor.deserialize_current().map_err(|e| format!("反序列化趋势统计失败: {}", e))?;
+        if let Ok(key) = doc.get_str("_id") {
+            totals.insert(key.to_string(), doc);
+        }
+    }
+    Ok(totals)
+}
+
+// 计算用户增长和工具使用趋势，没有数据的区间补零
+pub async fn trends(mongo: &MongoManager, range: &DateRange) -> Result<(Vec<DailyGrowth>, Vec<DailyUsage>), String> {
+    let new_users = bucket_totals(
+        mongo.users(),
+        "createdAt",
+        doc! {},
+        range,
+        doc! {"count": {"$sum": 1}},
+    )
+    .await?;
+
+    // 会话、点击和使用时长读取每日统计中的用户合计
+    let daily = bucket_totals(
+        mongo.daily_stats(),
+        "date",
+        doc! {"toolId": daily_stats::USER_TOTAL},
+        range,
+        doc! {
+            "sessions": {"$sum": "$sessions"},
+            "clicks": {"$sum": "$clicks"},
+            "usageSecs": {"$sum": "$usageSecs"},
+            "users": {"$addToSet": "$userId"},
+            "clickUsers": {"$addToSet": {"$cond": [{"$gt": ["$clicks", 0]}, "$userId", null]}}
+        },
+    )
+    .await?;
+
+    let empty = Document::new();
+    let mut growth = Vec::new();
+    let mut usage_trend = Vec::new();
+    for bucket in range.buckets() {
+        let key = bucket_key(bucket);
+        let daily_doc = daily.get(&key).unwrap_or(&empty);
+
+        growth.push(DailyGrowth {
+            date: key.clone(),
+            new_users: new_users.get(&key).map(|doc| get_number(doc, "count")).unwrap_or(0),
+            active_users: get_user_ids(daily_doc, "users").len() as i64,
+            total_sessions: get_number(daily_doc, "sessions"),
+        });
+        usage_trend.push(DailyUsage {
+            date: key.clone(),
+            total_clicks: get_number(daily_doc, "clicks"),
+            total_usage_time: get_number(daily_doc, "usageSecs"),
+            unique_users: get_user_ids(daily_doc, "clickUsers").len() as i64,
+        });
+    }
+    Ok((growth, usage_trend))
+}
+
+// 时间段 [from, to) 内的活跃用户数，from 和 to 为业务日零点
+pub async fn active_users(mongo: &MongoManager, from: DateTime, to: DateTime) -> Result<i64, String> {
+    let users = mongo.daily_stats()
+        .distinct("userId", doc! {"toolId": daily_stats::USER_TOTAL, "date": {"$gte": from, "$lt": to}})
+        .await
+        .map_err(|e| format!("查询活跃用户失败: {}", e))?;
+    Ok(users.len() as i64)
+}
+
+// 日活、周活、月活（按业务时区的自然日，周活和月活包含今天在内的最近7天和30天）
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ActiveUserCounts {
+    // 业务时区的今天
+    pub date: String,
+    pub timezone: String,
+    pub dau: i64,
+    pub wau: i64,
+    pub mau: i64,
+}
+
+pub async fn active_user_counts(mongo: &MongoManager, tz: Tz) -> Result<ActiveUserCounts, String> {
+    let today = today(tz);
+    let to = day_start(tz, today + Duration::days(1));
+
+    let mut counts = [0i64; 3];
+    for (count, days) in counts.iter_mut().zip([1, WAU_DAYS, MAU_DAYS]) {
+        let from = day_start(tz, today - Duration::days(days - 1));
+        *count = active_users(mongo, from, to).await?;
+    }
+
+    Ok(ActiveUserCounts {
+        date: bucket_key(today),
+        timezone: tz.name().to_string(),
+        dau: counts[0],
+        wau: counts[1],
+        mau: counts[2],
+    })
+}
+
+// 启用状态的用户数
+pub async fn total_users(mongo: &MongoManager) -> Result<i64, String> {
+    Ok(mongo.users()
+        .count_documents(doc! {"isActive": true})
+        .await
+        .map_err(|e| format!("查询用户数失败: {}", e))? as i64)
+}
+
+pub async fn total_sessions(mongo: &MongoManager) -> Result<i64, String> {
+    Ok(mongo.user_sessions()
+        .count_documents(doc! {})
+        .await
+        .map_err(|e| format!("查询总会话数失败: {}", e))? as i64)
+}
+
+// 已结束会话的平均时长（秒），读取每日统计中的用户合计
+pub async fn average_session_duration(mongo: &MongoManager) -> Result<i64, String> {
+    let mut cursor = mongo.daily_stats()
+        .aggregate(vec![
+            doc! {"$match": {"toolId": daily_stats::USER_TOTAL, "endedSessions": {"$gt": 0}}},
+            doc! {"$group": {"_id": null, "sessions": {"$sum": "$endedSessions"}, "secs": {"$sum": "$sessionSecs"}}},
+        ])
+        .await
+        .map_err(|e| format!("会话时长聚合失败: {}", e))?;
+
+    if !cursor.advance().await.map_err(|e| format!("获取聚合结果失败: {}", e))? {
+        return Ok(0);
+    }
+    let doc = cursor.deserialize_current().map_err(|e| format!("反序列化聚合结果失败: {}", e))?;
+    let sessions = get_number(&doc, "sessions");
+    Ok(if sessions > 0 { get_number(&doc, "secs") / sessions } else { 0 })
+}
+
+// 时间段 [from, to) 内点击次数最多的工具（工具名称取工具目录中的规范名称）
+pub async fn popular_tools(
+    mongo: &MongoManager,
+    from: DateTime,
+    to: DateTime,
+    limit: i64,
+) -> Result<Vec<PopularTool>, String> {
+    let tool_pipeline = vec![
+        doc! {
+            "$match": {
+                "toolId": { "$ne": daily_stats::USER_TOTAL },
+                "date": { "$gte": from, "$lt": to }
+            }
+        },
+        doc! {
+            "$group": {
+                "_id": "$toolId",
+                "totalClicks": { "$sum": "$clicks" },
+                "totalUsageTime": { "$sum": "$usageSecs" },
+                "uniqueUsers": { "$addToSet": "$userId" }
+            }
+        },
+        doc! {
+            "$addFields": {
+                "uniqueUserCount": { "$size": "$uniqueUsers" }
+            }
+        },
+        doc! {
+            "$sort": { "totalClicks": -1, "totalUsageTime": -1 }
+        },
+        doc! {
+            "$limit": limit
+        },
+        doc! {
+            "$lookup": {
+                "from": "tools",
+                "localField": "_id",
+                "foreignField": "toolId",
+                "as": "catalog"
+            }
+        },
+        doc! {
+            "$addFields": {
+                "toolName": { "$arrayElemAt": ["$catalog.name", 0] }
+            }
+        }
+    ];
+
+    let mut cursor = mongo.daily_stats()
+        .aggregate(tool_pipeline)
+        .await
+        .map_err(|e| format!("工具统计聚合失败: {}", e))?;
+
+    let mut tools = Vec::new();
+    while cursor.advance().await.map_err(|e| format!("遍历工具统计失败: {}", e))? {
+        let doc = cursor.deserialize_current().map_err(|e| format!("反序列化工具统计失败: {}", e))?;
+        let tool_id = doc.get_i32("_id").unwrap_or(0);
+        tools.push(PopularTool {
+            tool_id,
+            // 已从目录中删除的工具
+            tool_name: doc.get_str("toolName").map(str::to_string).unwrap_or_else(|_| format!("工具{}", tool_id)),
+            total_clicks: get_number(&doc, "totalClicks"),
+            total_usage_time: get_number(&doc, "totalUsageTime"),
+            unique_users: get_number(&doc, "uniqueUserCount"),
+        });
+    }
+    Ok(tools)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::daily_stats::{DailyStat, USER_TOTAL};
+    use crate::test_support::TestDb;
+
+    const TZ: Tz = chrono_tz::Asia::Shanghai;
+
+    fn date(value: &str) -> NaiveDate {
+        NaiveDate::parse_from_str(value, "%Y-%m-%d").unwrap()
+    }
+
+    fn range(start: &str, end: &str, granularity: &str) -> DateRange {
+        DateRange::parse(Some(start.to_string()), Some(end.to_string()), Some(granularity.to_string()), TZ).unwrap()
+    }
+
+    // 业务时区某天的某个时刻
+    fn at(day: &str, hour: i64) -> DateTime {
+        DateTime::from_millis(day_start(TZ, date(day)).timestamp_millis() + hour * 3_600_000)
+    }
+
+    fn stat(day: &str, user_id: ObjectId, tool_id: i32, sessions: i64, clicks: i64, usage_secs: i64) -> DailyStat {
+        DailyStat {
+            id: None,
+            date: day_start(TZ, date(day)),
+            user_id,
+            tool_id,
+            sessions,
+            ended_sessions: 0,
+            session_secs: 0,
+            clicks,
+            usage_secs,
+        }
+    }
+
+    #[test]
+    fn buckets_start_on_monday_and_first_of_month() {
+        let keys = |range: DateRange| range.buckets().into_iter().map(bucket_key).collect::<Vec<_>>();
+        assert_eq!(keys(range("2024-01-03", "2024-01-05", "day")), ["2024-01-03", "2024-01-04", "2024-01-05"]);
+        assert_eq!(keys(range("2024-01-03", "2024-01-15", "week")), ["2024-01-01", "2024-01-08", "2024-01-15"]);
+        assert_eq!(keys(range("2023-12-15", "2024-02-10", "month")), ["2023-12-01", "2024-01-01", "2024-02-01"]);
+    }
+
+    #[test]
+    fn range_rejects_invalid_input() {
+        let parse = |start: &str, end: &str, granularity: &str| {
+            DateRange::parse(Some(start.to_string()), Some(end.to_string()), Some(granularity.to_string()), TZ)
+        };
+        assert!(parse("2024-01-05", "2024-01-01", "day").is_err());
+        assert!(parse("2024-01-01", "2024-01-05", "year").is_err());
+        assert!(parse("2020-01-01", "2024-01-01", "day").is_err());
+        assert!(parse("2020-01-01", "2024-01-01", "month").is_ok());
+    }
+
+    // 两个用户：alice 1月2日注册并使用，bob 1月5日注册只登录；1月5日 alice 再次打开工具
+    async fn seed(mongo: &MongoManager) {
+        let alice = ObjectId::new();
+        let bob = ObjectId::new();
+        mongo.users()
+            .clone_with_type::<Document>()
+            .insert_many([
+                doc! {"_id": alice, "username": "alice", "createdAt": at("2024-01-02", 10)},
+                doc! {"_id": bob, "username": "bob", "createdAt": at("2024-01-05", 23)},
+            ])
+            .await
+            .unwrap();
+        mongo.daily_stats()
+            .insert_many([
+                stat("2024-01-02", alice, USER_TOTAL, 1, 3, 60),
+                stat("2024-01-02", alice, 5, 0, 3, 60),
+                stat("2024-01-05", alice, USER_TOTAL, 0, 1, 0),
+                stat("2024-01-05", alice, 5, 0, 1, 0),
+                stat("2024-01-05", bob, USER_TOTAL, 2, 0, 30),
+            ])
+            .await
+            .unwrap();
+    }
+
+    fn growth_row(row: &DailyGrowth) -> (&str, i64, i64, i64) {
+        (row.date.as_str(), row.new_users, row.active_users, row.total_sessions)
+    }
+
+    fn usage_row(row: &DailyUsage) -> (&str, i64, i64, i64) {
+        (row.date.as_str(), row.total_clicks, row.total_usage_time, row.unique_users)
+    }
+
+    #[tokio::test]
+    #[ignore = "需要本机 mongod"]
+    async fn daily_trends_fill_empty_days_with_zero() {
+        let db = TestDb::new("metrics_day").await;
+        seed(&db.mongo).await;
+
+        let (growth, usage) = trends(&db.mongo, &range("2024-01-01", "2024-01-06", "day")).await.unwrap();
+        assert_eq!(growth.iter().map(growth_row).collect::<Vec<_>>(), [
+            ("2024-01-01", 0, 0, 0),
+            ("2024-01-02", 1, 1, 1),
+            ("2024-01-03", 0, 0, 0),
+            ("2024-01-04", 0, 0, 0),
+            ("2024-01-05", 1, 2, 2),
+            ("2024-01-06", 0, 0, 0),
+        ]);
+        assert_eq!(usage.iter().map(usage_row).collect::<Vec<_>>(), [
+            ("2024-01-01", 0, 0, 0),
+            ("2024-01-02", 3, 60, 1),
+            ("2024-01-03", 0, 0, 0),
+            ("2024-01-04", 0, 0, 0),
+            ("2024-01-05", 1, 30, 1),
+            ("2024-01-06", 0, 0, 0),
+        ]);
+        db.drop().await;
+    }
+
+    #[tokio::test]
+    #[ignore = "需要本机 mongod"]
+    async fn weekly_trends_fill_empty_weeks_with_zero() {
+        let db = TestDb::new("metrics_week").await;
+        seed(&db.mongo).await;
+
+        let (growth, usage) = trends(&db.mongo, &range("2024-01-01", "2024-01-20", "week")).await.unwrap();
+        assert_eq!(growth.iter().map(growth_row).collect::<Vec<_>>(), [
+            ("2024-01-01", 2, 2, 3),
+            ("2024-01-08", 0, 0, 0),
+            ("2024-01-15", 0, 0, 0),
+        ]);
+        assert_eq!(usage.iter().map(usage_row).collect::<Vec<_>>(), [
+            ("2024-01-01", 4, 90, 1),
+            ("2024-01-08", 0, 0, 0),
+            ("2024-01-15", 0, 0, 0),
+        ]);
+        db.drop().await;
+    }
+
+    #[tokio::test]
+    #[ignore = "需要本机 mongod"]
+    async fn monthly_trends_fill_empty_months_with_zero() {
+        let db = TestDb::new("metrics_month").await;
+        seed(&db.mongo).await;
+
+        let (growth, usage) = trends(&db.mongo, &range("2023-12-15", "2024-02-10", "month")).await.unwrap();
+        assert_eq!(growth.iter().map(growth_row).collect::<Vec<_>>(), [
+            ("2023-12-01", 0, 0, 0),
+            ("2024-01-01", 2, 2, 3),
+            ("2024-02-01", 0, 0, 0),
+        ]);
+        assert_eq!(usage.iter().map(usage_row).collect::<Vec<_>>(), [
+            ("2023-12-01", 0, 0, 0),
+            ("2024-01-01", 4, 90, 1),
+            ("2024-02-01", 0, 0, 0),
+        ]);
+        db.drop().await;
+    }
+
+    #[tokio::test]
+    #[ignore = "需要本机 mongod"]
+    async fn average_session_duration_reads_daily_totals() {
+        let db = TestDb::new("metrics_session_duration").await;
+        assert_eq!(average_session_duration(&db.mongo).await.unwrap(), 0);
+
+        let user_id = ObjectId::new();
+        let ended = |day: &str, sessions: i64, secs: i64| DailyStat {
+            ended_sessions: sessions,
+            session_secs: secs,
+            ..stat(day, user_id, USER_TOTAL, sessions, 0, 0)
+        };
+        db.mongo.daily_stats()
+            .insert_many([ended("2024-01-02", 1, 600), ended("2024-01-03", 2, 1800), stat("2024-01-04", user_id, USER_TOTAL, 1, 0, 0)])
+            .await
+            .unwrap();
+
+        // 未结束的会话不计入
+        assert_eq!(average_session_duration(&db.mongo).await.unwrap(), 800);
+        db.drop().await;
+    }
+}
@@ -0,0 +1,254 @@
+use argon2::{
+    password_hash::{PasswordHash, PasswordHasher, PasswordVerifier, SaltString},
+    Algorithm, Argon2, Params, Version,
+};
+use rand::RngCore;
+use sha2::{Digest, Sha256};
+
+// 密码哈希方案版本
+// - LegacySha256: 旧版单轮SHA-256 + 全局盐，仅用于兼容已有账号
+// - Argon2id: 当前方案，PHC格式字符串，每个用户独立随机盐
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PasswordScheme {
+    LegacySha256,
+    Argon2id,
+}
+
+// 旧版全局盐（仅用于校验历史哈希，新哈希不再使用）
+const LEGACY_SALT: &str = "chengshang2025";
+
+// Argon2id 成本参数（OWASP 推荐的最低配置）
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HashParams {
+    pub memory_kib: u32,
+    pub iterations: u32,
+    pub parallelism: u32,
+}
+
+impl Default for HashParams {
+    fn default() -> Self {
+        HashParams {
+            memory_kib: Params::DEFAULT_M_COST,
+            iterations: Params::DEFAULT_T_COST,
+            parallelism: Params::DEFAULT_P_COST,
+        }
+    }
+}
+
+impl HashParams {
+    fn argon2(&self) -> Result<Argon2<'static>, String> {
+        let params = Params::new(self.memory_kib, self.iterations, self.parallelism, None)
+            .map_err(|e| format!("无效的密码哈希参数: {}", e))?;
+        Ok(Argon2::new(Algorithm::Argon2id, Version::V0x13, params))
+    }
+}
+
+// 内置的常见弱密码（比较时忽略大小写）
+const COMMON_PASSWORDS: &[&str] = &[
+    "123456", "1234567", "12345678", "123456789", "1234567890", "111111", "000000",
+    "666666", "888888", "123123", "654321", "112233", "abc123", "abcd1234", "a123456",
+    "password", "password1", "password123", "passw0rd", "p@ssw0rd", "qwerty", "qwerty123",
+    "qwertyuiop", "1qaz2wsx", "1q2w3e4r", "iloveyou", "admin", "admin123", "admin888",
+    "root", "root123", "welcome", "welcome1", "letmein", "woaini1314", "5201314",
+];
+
+// 密码策略：长度、字符类别、弱密码名单和历史密码
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PasswordPolicy {
+    pub min_length: usize,
+    // 小写、大写、数字、符号中至少包含的类别数
+    pub min_char_classes: usize,
+    // 不允许与最近 N 次使用过的密码相同
+    pub history_size: usize,
+    // 额外的禁用密码
+    pub blocklist: Vec<String>,
+}
+
+impl PasswordPolicy {
+    // 校验新密码本身是否符合策略（不含历史密码检查）
+    pub fn validate(&self, password: &str, username: &str) -> Result<(), String> {
+        let length = password.chars().count();
+        if length < self.min_length {
+            return Err(format!("密码长度至少{}位", self.min_length));
+        }
+
+        let classes = [
+            password.chars().any(|c| c.is_lowercase()),
+            password.chars().any(|c| c.is_uppercase()),
+            password.chars().any(|c| c.is_ascii_digit()),
+            password.chars().any(|c| !c.is_alphanumeric()),
+        ]
+        .iter()
+        .filter(|present| **present)
+        .count();
+        if classes < self.min_char_classes {
+            return Err(format!(
+                "密码需包含小写字母、大写字母、数字、符号中的至少{}类",
+                self.min_char_classes
+            ));
+        }
+
+        let lowered = password.to_lowercase();
+        if !username.is_empty() && lowered.contains(&username.to_lowercase()) {
+            return Err("密码不能包含用户名".to_string());
+        }
+        if COMMON_PASSWORDS.contains(&lowered.as_str())
+            || self.blocklist.iter().any(|blocked| blocked.to_lowercase() == lowered)
+        {
+            return Err("密码过于常见，请更换".to_string());
+        }
+
+        Ok(())
+    }
+
+    // 新密码是否与当前密码或历史密码相同
+    pub fn is_reused<'a>(&self, password: &str, hashes: impl IntoIterator<Item = &'a str>) -> bool {
+        hashes
+            .into_iter()
+            .take(self.history_size + 1)
+            .any(|hash| verify_password(password, hash))
+    }
+}
+
+// 识别已存储哈希所使用的方案
+pub fn detect_scheme(hash: &str) -> Option<PasswordScheme> {
+    if hash.starts_with("$argon2id$") {
+        Some(PasswordScheme::Argon2id)
+    } else if hash.len() == 64 && hash.bytes().all(|b| b.is_ascii_hexdigit()) {
+        Some(PasswordScheme::LegacySha256)
+    } else {
+        None
+    }
+}
+
+// 使用当前方案（Argon2id）生成密码哈希
+pub fn hash_password_with(password: &str, params: &HashParams) -> Result<String, String> {
+    let mut salt_bytes = [0u8; 16];
+    rand::thread_rng().fill_bytes(&mut salt_bytes);
+    let salt = SaltString::encode_b64(&salt_bytes)
+        .map_err(|e| format!("生成密码盐失败: {}", e))?;
+
+    params
+        .argon2()?
+        .hash_password(password.as_bytes(), &salt)
+        .map(|hash| hash.to_string())
+        .map_err(|e| format!("密码哈希失败: {}", e))
+}
+
+// 验证密码，同时兼容旧版SHA-256哈希和Argon2id哈希
+pub fn verify_password(password: &str, hash: &str) -> bool {
+    match detect_scheme(hash) {
+        Some(PasswordScheme::Argon2id) => match PasswordHash::new(hash) {
+            // 校验时使用哈希字符串中记录的参数，而不是当前配置
+            Ok(parsed) => Argon2::default()
+                .verify_password(password.as_bytes(), &parsed)
+                .is_ok(),
+            Err(e) => {
+                log::warn!("⚠️ 无法解析的Argon2哈希: {}", e);
+                false
+            }
+        },
+        Some(PasswordScheme::LegacySha256) => {
+            constant_time_eq(legacy_hash(password).as_bytes(), hash.as_bytes())
+        }
+        None => false,
+    }
+}
+
+// 判断已存储的哈希是否需要在下次成功登录时升级
+pub fn needs_rehash_with(hash: &str, params: &HashParams) -> bool {
+    match detect_scheme(hash) {
+        Some(PasswordScheme::Argon2id) => match PasswordHash::new(hash) {
+            Ok(parsed) => match Params::try_from(&parsed) {
+                Ok(stored) => {
+                    stored.m_cost() != params.memory_kib
+                        || stored.t_cost() != params.iterations
+                        || stored.p_cost() != params.parallelism
+                }
+                Err(_) => true,
+            },
+            Err(_) => true,
+        },
+        Some(PasswordScheme::LegacySha256) | None => true,
+    }
+}
+
+// 旧版哈希算法：SHA-256(password + 全局盐)
+fn legacy_hash(password: &str) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(password.as_bytes());
+    hasher.update(LEGACY_SALT.as_bytes());
+    format!("{:x}", hasher.finalize())
+}
+
+pub(crate) fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 测试用的低成本参数
+    const FAST: HashParams = HashParams { memory_kib: 64, iterations: 1, parallelism: 1 };
+
+    fn policy() -> PasswordPolicy {
+        PasswordPolicy {
+            min_length: 10,
+            min_char_classes: 3,
+            history_size: 2,
+            blocklist: vec!["Chengshang@2025".to_string()],
+        }
+    }
+
+    #[test]
+    fn validate_enforces_length() {
+        assert_eq!(policy().validate("Ab1-xyz", "alice").unwrap_err(), "密码长度至少10位");
+        // 按字符数而不是字节数计算
+        assert!(policy().validate("密码Ab1-xyz", "alice").is_err());
+        assert!(policy().validate("密码Ab1-xyzw", "alice").is_ok());
+    }
+
+    #[test]
+    fn validate_counts_character_classes() {
+        assert!(policy().validate("lowercaseonly", "alice").is_err());
+        assert!(policy().validate("lowercase123", "alice").is_err());
+        assert!(policy().validate("Lowercase123", "alice").is_ok());
+        assert!(policy().validate("lowercase-123", "alice").is_ok());
+        assert!(policy().validate("LOWERCASE-ABC", "alice").is_err());
+    }
+
+    #[test]
+    fn validate_rejects_username_and_blocklisted_passwords() {
+        assert_eq!(policy().validate("Alice-2025-xyz", "alice").unwrap_err(), "密码不能包含用户名");
+        assert_eq!(policy().validate("P@ssw0rd", "alice").unwrap_err(), "密码长度至少10位");
+
+        let common = PasswordPolicy { min_length: 6, min_char_classes: 1, ..policy() };
+        assert_eq!(common.validate("P@SSW0RD", "alice").unwrap_err(), "密码过于常见，请更换");
+        assert_eq!(policy().validate("chengshang@2025", "alice").unwrap_err(), "密码过于常见，请更换");
+        // 用户名为空时不检查
+        assert!(policy().validate("Another-Battery-93", "").is_ok());
+    }
+
+    #[test]
+    fn is_reused_checks_current_and_last_n_passwords() {
+        // 依次为当前密码和由新到旧的历史密码
+        let passwords = ["Current-Pass-1", "History-Pass-1", "History-Pass-2", "History-Pass-3"];
+        let hashes: Vec<String> = passwords
+            .iter()
+            .map(|password| hash_password_with(password, &FAST).unwrap())
+            .collect();
+        let hashes = || hashes.iter().map(String::as_str);
+
+        let policy = policy();
+        assert!(policy.is_reused("Current-Pass-1", hashes()));
+        assert!(policy.is_reused("History-Pass-2", hashes()));
+        // 超出最近 history_size 次的密码可以再次使用
+        assert!(!policy.is_reused("History-Pass-3", hashes()));
+        assert!(!policy.is_reused("Brand-New-Pass-1", hashes()));
+
+        let no_history = PasswordPolicy { history_size: 0, ..policy };
+        assert!(no_history.is_reused("Current-Pass-1", hashes()));
+        assert!(!no_history.is_reused("History-Pass-1", hashes()));
+    }
+}
@@ -0,0 +1,513 @@
+use futures::TryStreamExt;
+use mongodb::{
+    bson::{self, doc, oid::ObjectId, DateTime},
+    options::IndexOptions,
+    IndexModel,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+
+use crate::auth::{AppState, MongoManager, User, UserResponse};
+use crate::guard::{require, require_any, require_target, AuthError, Permission};
+
+// 内置角色名称
+pub const ROLE_ADMIN: &str = "admin";
+pub const ROLE_USER: &str = "user";
+pub const ROLE_DEPARTMENT_LEAD: &str = "department_lead";
+
+// 角色：一组命名的权限集合
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Role {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    pub id: Option<ObjectId>,
+    pub name: String,
+    #[serde(rename = "displayName")]
+    pub display_name: String,
+    #[serde(default)]
+    pub description: String,
+    #[serde(default)]
+    pub permissions: Vec<Permission>,
+    #[serde(default)]
+    pub builtin: bool,
+    #[serde(rename = "createdAt")]
+    pub created_at: DateTime,
+    #[serde(rename = "updatedAt")]
+    pub updated_at: DateTime,
+}
+
+// 角色响应结构
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct RoleResponse {
+    pub id: String,
+    pub name: String,
+    #[serde(rename = "displayName")]
+    pub display_name: String,
+    pub description: String,
+    pub permissions: Vec<Permission>,
+    pub builtin: bool,
+}
+
+impl From<Role> for RoleResponse {
+    fn from(role: Role) -> Self {
+        RoleResponse {
+            id: role.id.map(|id| id.to_hex()).unwrap_or_default(),
+            name: role.name,
+            display_name: role.display_name,
+            description: role.description,
+            permissions: role.permissions,
+            builtin: role.builtin,
+        }
+    }
+}
+
+pub(crate) fn builtin_roles() -> Vec<(&'static str, &'static str, &'static str, Vec<Permission>)> {
+    vec![
+        (ROLE_ADMIN, "管理员", "拥有全部权限", Permission::ALL.to_vec()),
+        (ROLE_USER, "普通用户", "仅可使用工具", vec![]),
+        (
+            ROLE_DEPARTMENT_LEAD,
+            "部门负责人",
+            "管理本部门员工并查看本部门数据",
+            vec![Permission::ManageDepartmentUsers, Permission::ViewDepartmentAnalytics],
+        ),
+    ]
+}
+
+// 启动时执行：创建内置角色，并把旧的 role 字符串迁移为 roles 数组
+pub async fn migrate(mongo: &MongoManager) -> Result<(), String> {
+    mongo.roles()
+        .create_index(
+            IndexModel::builder()
+                .keys(doc! {"name": 1})
+                .options(IndexOptions::builder().unique(true).build())
+                .build(),
+        )
+        .await
+        .map_err(|e| format!("创建角色索引失败: {}", e))?;
+
+    let now = DateTime::now();
+    for (name, display_name, description, permissions) in builtin_roles() {
+        let permissions = bson::to_bson(&permissions).map_err(|e| format!("序列化权限失败: {}", e))?;
+        // 管理员角色始终拥有全部权限，其余内置角色只在首次创建时写入默认权限
+        let update = if name == ROLE_ADMIN {
+            doc! {
+                "$set": {"permissions": permissions, "builtin": true, "updatedAt": now},
+                "$setOnInsert": {"displayName": display_name, "description": description, "createdAt": now}
+            }
+        } else {
+            doc! {
+                "$setOnInsert": {
+                    "displayName": display_name,
+                    "description": description,
+                    "permissions": permissions,
+                    "builtin": true,
+                    "createdAt": now,
+                    "updatedAt": now
+                }
+            }
+        };
+        mongo.roles()
+            .update_one(doc! {"name": name}, update)
+            .upsert(true)
+            .await
+            .map_err(|e| format!("初始化内置角色 {} 失败: {}", name, e))?;
+    }
+
+    let result = mongo.users()
+        .update_many(
+            doc! {"roles": {"$exists": false}},
+            vec![doc! {"$set": {"roles": [{"$ifNull": ["$role", ROLE_USER]}]}}],
+        )
+        .await
+        .map_err(|e| format!("迁移用户角色失败: {}", e))?;
+
+    if result.modified_count > 0 {
+        log::info!("🔄 已为 {} 个用户迁移角色数据", result.modified_count);
+    }
+    Ok(())
+}
+
+// 用户持有的角色名（兼容尚未迁移的旧记录）
+pub fn role_names(user: &User) -> Vec<String> {
+    if user.roles.is_empty() {
+        vec![user.role.clone()]
+    } else {
+        user.roles.clone()
+    }
+}
+
+// 合并多个角色的权限
+pub async fn resolve_permissions(mongo: &MongoManager, roles: &[String]) -> Result<Vec<Permission>, String> {
+    let cursor = mongo.roles()
+        .find(doc! {"name": {"$in": roles}})
+        .await
+        .map_err(|e| format!("查询角色失败: {}", e))?;
+    let found: Vec<Role> = cursor.try_collect().await.map_err(|e| format!("读取角色失败: {}", e))?;
+
+    let granted: HashSet<Permission> = found.into_iter().flat_map(|role| role.permissions).collect();
+    Ok(Permission::ALL.iter().copied().filter(|p| granted.contains(p)).collect())
+}
+
+// 将用户转换为包含角色和有效权限的响应结构
+pub async fn resolve_user(mongo: &MongoManager, user: User) -> Result<UserResponse, String> {
+    let roles = role_names(&user);
+    let permissions = resolve_permissions(mongo, &roles).await?;
+
+    let mut response = UserResponse::from(user);
+    response.roles = roles;
+    response.permissions = permissions;
+    Ok(response)
+}
+
+// 校验角色是否存在，并确保调用者不能授予自己没有的权限
+pub async fn validate_assignment(
+    mongo: &MongoManager,
+    caller: &UserResponse,
+    roles: &[String],
+) -> Result<(), String> {
+    if roles.is_empty() {
+        return Err("至少需要指定一个角色".to_string());
+    }
+
+    let cursor = mongo.roles()
+        .find(doc! {"name": {"$in": roles}})
+        .await
+        .map_err(|e| format!("查询角色失败: {}", e))?;
+    let found: Vec<Role> = cursor.try_collect().await.map_err(|e| format!("读取角色失败: {}", e))?;
+
+    for name in roles {
+        let role = found.iter().find(|r| &r.name == name)
+            .ok_or_else(|| format!("角色不存在: {}", name))?;
+        ensure_grantable(caller, &role.permissions, name)?;
+    }
+    Ok(())
+}
+
+// 调用者只能授予自己拥有的权限，分配角色、创建和编辑角色都要校验
+pub fn ensure_grantable(caller: &UserResponse, permissions: &[Permission], role: &str) -> Result<(), AuthError> {
+    if let Some(missing) = permissions.iter().find(|p| !caller.has_permission(**p)) {
+        log::warn!("❌ 权限拒绝 - 用户 {} 尝试授予超出自身的权限: {}", caller.username, role);
+        return Err(AuthError::Forbidden { permission: *missing });
+    }
+    Ok(())
+}
+
+fn validate_role_name(name: &str) -> Result<(), String> {
+    if name.is_empty() || name.len() > 32 {
+        return Err("角色标识长度必须在1到32个字符之间".to_string());
+    }
+    if !name.chars().all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_') {
+        return Err("角色标识只能包含小写字母、数字和下划线".to_string());
+    }
+    Ok(())
+}
+
+// 列出所有角色
+#[tauri::command]
+pub async fn list_roles(
+    state: tauri::State<'_, AppState>,
+) -> Result<Vec<RoleResponse>, String> {
+    require_any(&state, &[
+        Permission::ManageRoles,
+        Permission::ManageUsers,
+        Permission::ManageDepartmentUsers,
+    ]).await?;
+
+    let mongo = state.mongo.read().await;
+    let cursor = mongo.roles()
+        .find(doc! {})
+        .sort(doc! {"builtin": -1, "name": 1})
+        .await
+        .map_err(|e| format!("查询角色失败: {}", e))?;
+    let roles: Vec<Role> = cursor.try_collect().await.map_err(|e| format!("读取角色失败: {}", e))?;
+
+    Ok(roles.into_iter().map(RoleResponse::from).collect())
+}
+
+// 创建自定义角色
+#[tauri::command]
+pub async fn create_role(
+    name: String,
+    display_name: String,
+    description: Option<String>,
+    permissions: Vec<Permission>,
+    state: tauri::State<'_, AppState>,
+) -> Result<RoleResponse, String> {
+    let current_user = require(&state, Permission::ManageRoles).await?;
+    log::info!("📝 角色管理操作 - 创建角色: {} (操作员: {})", name, current_user.username);
+
+    let name = name.trim().to_string();
+    validate_role_name(&name)?;
+    if display_name.trim().is_empty() {
+        return Err("角色名称不能为空".to_string());
+    }
+    ensure_grantable(&current_user, &permissions, &name)?;
+
+    let mongo = state.mongo.read().await;
+    if mongo.roles().find_one(doc! {"name": &name}).await
+        .map_err(|e| format!("检查角色失败: {}", e))?
+        .is_some()
+    {
+        return Err("角色标识已存在".to_string());
+    }
+
+    let now = DateTime::now();
+    let mut role = Role {
+        id: None,
+        name,
+        display_name: display_name.trim().to_string(),
+        description: description.unwrap_or_default(),
+        permissions,
+        builtin: false,
+        created_at: now,
+        updated_at: now,
+    };
+
+    let result = mongo.roles()
+        .insert_one(role.clone())
+        .await
+        .map_err(|e| format!("创建角色失败: {}", e))?;
+    role.id = result.inserted_id.as_object_id();
+
+    log::info!("✅ 角色创建成功: {}", role.name);
+    Ok(RoleResponse::from(role))
+}
+
+// 编辑角色（内置管理员角色的权限不可修改）
+#[tauri::command]
+pub async fn edit_role(
+    role_id: String,
+    display_name: Option<String>,
+    description: Option<String>,
+    permissions: Option<Vec<Permission>>,
+    state: tauri::State<'_, AppState>,
+) -> Result<RoleResponse, String> {
+    let current_user = require(&state, Permission::ManageRoles).await?;
+    log::info!("📝 角色管理操作 - 编辑角色: {} (操作员: {})", role_id, current_user.username);
+
+    let role_object_id = ObjectId::parse_str(&role_id)
+        .map_err(|e| format!("无效的角色ID: {}", e))?;
+
+    let mongo = state.mongo.read().await;
+    let role = mongo.roles()
+        .find_one(doc! {"_id": role_object_id})
+        .await
+        .map_err(|e| format!("查询角色失败: {}", e))?
+        .ok_or("角色不存在")?;
+
+    let mut update_doc = doc! {"updatedAt": DateTime::now()};
+    if let Some(display_name) = display_name {
+        if display_name.trim().is_empty() {
+            return Err("角色名称不能为空".to_string());
+        }
+        update_doc.insert("displayName", display_name.trim());
+    }
+    if let Some(description) = description {
+        update_doc.insert("description", description);
+    }
+    if let Some(permissions) = permissions {
+        if role.name == ROLE_ADMIN {
+            return Err("不能修改管理员角色的权限".to_string());
+        }
+        ensure_grantable(&current_user, &permissions, &role.name)?;
+        update_doc.insert(
+            "permissions",
+            bson::to_bson(&permissions).map_err(|e| format!("序列化权限失败: {}", e))?,
+        );
+    }
+
+    mongo.roles()
+        .update_one(doc! {"_id": role_object_id}, doc! {"$set": update_doc})
+        .await
+        .map_err(|e| format!("更新角色失败: {}", e))?;
+
+    let updated = mongo.roles()
+        .find_one(doc! {"_id": role_object_id})
+        .await
+        .map_err(|e| format!("获取更新后角色失败: {}", e))?
+        .ok_or("角色不存在")?;
+
+    log::info!("✅ 角色编辑成功: {}", updated.name);
+    Ok(RoleResponse::from(updated))
+}
+
+// 删除自定义角色（内置角色和仍被使用的角色不可删除）
+#[tauri::command]
+pub async fn delete_role(
+    role_id: String,
+    state: tauri::State<'_, AppState>,
+) -> Result<(), String> {
+    let current_user = require(&state, Permission::ManageRoles).await?;
+    log::info!("📝 角色管理操作 - 删除角色: {} (操作员: {})", role_id, current_user.username);
+
+    let role_object_id = ObjectId::parse_str(&role_id)
+        .map_err(|e| format!("无效的角色ID: {}", e))?;
+
+    let mongo = state.mongo.read().await;
+    let role = mongo.roles()
+        .find_one(doc! {"_id": role_object_id})
+        .await
+        .map_err(|e| format!("查询角色失败: {}", e))?
+        .ok_or("角色不存在")?;
+
+    if role.builtin {
+        return Err("内置角色不能删除".to_string());
+    }
+
+    let holders = mongo.users()
+        .count_documents(doc! {"roles": &role.name})
+        .await
+        .map_err(|e| format!("检查角色使用情况失败: {}", e))?;
+    if holders > 0 {
+        return Err(format!("仍有 {} 个用户持有该角色，无法删除", holders));
+    }
+
+    mongo.roles()
+        .delete_one(doc! {"_id": role_object_id})
+        .await
+        .map_err(|e| format!("删除角色失败: {}", e))?;
+
+    log::info!("✅ 角色删除成功: {}", role.name);
+    Ok(())
+}
+
+// 为用户分配角色（替换原有角色集合）
+#[tauri::command]
+pub async fn assign_user_roles(
+    user_id: String,
+    roles: Vec<String>,
+    state: tauri::State<'_, AppState>,
+) -> Result<UserResponse, String> {
+    let current_user = require_any(&state, &[
+        Permission::ManageUsers,
+        Permission::ManageDepartmentUsers,
+    ]).await?;
+    log::info!("📝 用户管理操作 - 分配角色: 用户={}, 角色={:?} (操作员: {})", user_id, roles, current_user.username);
+
+    if current_user.id == user_id {
+        return Err("不能修改自己的角色".to_string());
+    }
+
+    let user_object_id = ObjectId::parse_str(&user_id)
+        .map_err(|e| format!("无效的用户ID: {}", e))?;
+
+    let mongo = state.mongo.read().await;
+    let target = mongo.users()
+        .find_one(doc! {"_id": user_object_id})
+        .await
+        .map_err(|e| format!("查询用户失败: {}", e))?
+        .ok_or("用户不存在")?;
+
+    require_target(&mongo, &current_user, &target).await?;
+
+    let mut seen = HashSet::new();
+    let roles: Vec<String> = roles.into_iter()
+        .map(|r| r.trim().to_string())
+        .filter(|r| seen.insert(r.clone()))
+        .collect();
+    validate_assignment(&mongo, &current_user, &roles).await?;
+
+    mongo.users()
+        .update_one(
+            doc! {"_id": user_object_id},
+            doc! {"$set": {"roles": &roles, "role": &roles[0]}}
+        )
+        .await
+        .map_err(|e| format!("分配角色失败: {}", e))?;
+
+    let updated = mongo.users()
+        .find_one(doc! {"_id": user_object_id})
+        .await
+        .map_err(|e| format!("获取更新后用户信息失败: {}", e))?
+        .ok_or("用户不存在")?;
+
+    log::info!("✅ 角色分配成功: {} -> {:?}", updated.username, roles);
+    resolve_user(&mongo, updated).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{app_state, sign_in, TestDb};
+    use tauri::Manager;
+
+    fn caller(permissions: Vec<Permission>) -> UserResponse {
+        UserResponse {
+            id: ObjectId::new().to_hex(),
+            username: "role-manager".to_string(),
+            role: "role_manager".to_string(),
+            roles: vec!["role_manager".to_string()],
+            department: None,
+            permissions,
+            is_active: true,
+            created_at: String::new(),
+            last_login_at: None,
+            total_usage_time: 0,
+            login_count: 0,
+        }
+    }
+
+    #[test]
+    fn role_manager_cannot_grant_permissions_it_lacks() {
+        let manager = caller(vec![Permission::ManageRoles]);
+        assert_eq!(
+            ensure_grantable(&manager, &[Permission::ManageRoles, Permission::ManageUsers], "escalated").unwrap_err(),
+            AuthError::Forbidden { permission: Permission::ManageUsers }
+        );
+        assert_eq!(
+            ensure_grantable(&manager, &[Permission::ViewAnalytics], "escalated").unwrap_err(),
+            AuthError::Forbidden { permission: Permission::ViewAnalytics }
+        );
+        assert!(ensure_grantable(&manager, &[Permission::ManageRoles], "peer").is_ok());
+        assert!(ensure_grantable(&manager, &[], "empty").is_ok());
+    }
+
+    #[test]
+    fn admin_can_grant_every_permission() {
+        let admin = caller(Permission::ALL.to_vec());
+        assert!(ensure_grantable(&admin, &Permission::ALL, "everything").is_ok());
+    }
+
+    #[tokio::test]
+    #[ignore = "需要本机 mongod"]
+    async fn role_commands_require_permission() {
+        let db = TestDb::new("role_commands").await;
+        let dir = std::env::temp_dir().join(format!("chengshang-test-{}", uuid::Uuid::new_v4().simple()));
+        let app = tauri::test::mock_app();
+        app.manage(app_state(&db, &dir).await);
+        let state = app.state::<AppState>();
+        let target = ObjectId::new().to_hex();
+
+        let cases = [
+            (AuthError::Unauthenticated, AuthError::Unauthenticated),
+            (
+                AuthError::Forbidden { permission: Permission::ManageRoles },
+                AuthError::Forbidden { permission: Permission::ManageUsers },
+            ),
+        ];
+        for (roles_error, users_error) in cases {
+            let expected = roles_error.to_string();
+            assert_eq!(list_roles(state.clone()).await.unwrap_err(), expected);
+            assert_eq!(
+                create_role("escalated".to_string(), "提权".to_string(), None, Permission::ALL.to_vec(), state.clone())
+                    .await
+                    .unwrap_err(),
+                expected
+            );
+            assert_eq!(
+                edit_role(target.clone(), None, None, Some(Permission::ALL.to_vec()), state.clone()).await.unwrap_err(),
+                expected
+            );
+            assert_eq!(delete_role(target.clone(), state.clone()).await.unwrap_err(), expected);
+            assert_eq!(
+                assign_user_roles(target.clone(), vec![ROLE_ADMIN.to_string()], state.clone()).await.unwrap_err(),
+                users_error.to_string()
+            );
+
+            sign_in(&state, "plain", ROLE_USER).await;
+        }
+
+        db.drop().await;
+        let _ = std::fs::remove_dir_all(dir);
+    }
+}
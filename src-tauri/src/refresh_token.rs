@@ -0,0 +1,396 @@
+use mongodb::{
+    bson::{doc, oid::ObjectId, DateTime},
+    options::IndexOptions,
+    IndexModel,
+};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::HashSet;
+use std::time::Duration;
+
+use crate::auth::{self, AppState, LoginResponse, MongoManager};
+use crate::config::AuthConfig;
+use crate::secret::Secret;
+
+// 刷新Token的用途，与旧版 tokenType 取值一致
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RefreshKind {
+    RememberMe,
+    AutoLogin,
+}
+
+impl RefreshKind {
+    pub fn parse(token_type: &str) -> Result<Self, String> {
+        match token_type {
+            "remember_me" => Ok(RefreshKind::RememberMe),
+            "auto_login" => Ok(RefreshKind::AutoLogin),
+            other => Err(format!("未知的Token类型: {}", other)),
+        }
+    }
+
+    // 整个Token家族的有效期（轮换不会延长）
+    pub fn lifetime_days(&self, auth: &AuthConfig) -> i64 {
+        match self {
+            RefreshKind::RememberMe => auth.remember_me_days,
+            RefreshKind::AutoLogin => auth.auto_login_days,
+        }
+    }
+}
+
+// 刷新Token（集合 refresh_tokens）：只保存哈希，每次使用后轮换，
+// 同一次登录派生出的Token属于同一家族
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct RefreshToken {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    pub id: Option<ObjectId>,
+    #[serde(rename = "tokenHash")]
+    pub token_hash: Secret<String>,
+    #[serde(rename = "familyId")]
+    pub family_id: String,
+    #[serde(rename = "userId")]
+    pub user_id: ObjectId,
+    // 签发Token的设备，旧记录为空
+    #[serde(rename = "deviceId", default, skip_serializing_if = "Option::is_none")]
+    pub device_id: Option<String>,
+    pub kind: RefreshKind,
+    #[serde(rename = "createdAt")]
+    pub created_at: DateTime,
+    #[serde(rename = "expiresAt")]
+    pub expires_at: DateTime,
+    // 已轮换的时间，再次出现即视为被盗用
+    #[serde(rename = "usedAt", default, skip_serializing_if = "Option::is_none")]
+    pub used_at: Option<DateTime>,
+    #[serde(rename = "revokedAt", default, skip_serializing_if = "Option::is_none")]
+    pub revoked_at: Option<DateTime>,
+}
+
+pub async fn ensure_indexes(mongo: &MongoManager) -> Result<(), String> {
+    let indexes = [
+        IndexModel::builder()
+            .keys(doc! {"tokenHash": 1})
+            .options(IndexOptions::builder().unique(true).build())
+            .build(),
+        IndexModel::builder()
+            .keys(doc! {"familyId": 1})
+            .build(),
+        IndexModel::builder()
+            .keys(doc! {"userId": 1})
+            .build(),
+        // 过期的Token由MongoDB自动清理
+        IndexModel::builder()
+            .keys(doc! {"expiresAt": 1})
+            .options(IndexOptions::builder().expire_after(Duration::from_secs(0)).build())
+            .build(),
+    ];
+
+    mongo.refresh_tokens()
+        .create_indexes(indexes)
+        .await
+        .map_err(|e| format!("创建刷新Token索引失败: {}", e))?;
+    Ok(())
+}
+
+fn hash_token(token: &str) -> String {
+    format!("{:x}", Sha256::digest(token.as_bytes()))
+}
+
+async fn insert_token(
+    mongo: &MongoManager,
+    user_id: ObjectId,
+    device_id: Option<String>,
+    kind: RefreshKind,
+    family_id: String,
+    expires_at: DateTime,
+) -> Result<Secret<String>, String> {
+    let token = auth::generate_token_id();
+    let record = RefreshToken {
+        id: None,
+        token_hash: Secret::new(hash_token(&token)),
+        family_id,
+        user_id,
+        device_id,
+        kind,
+        created_at: DateTime::now(),
+        expires_at,
+        used_at: None,
+        revoked_at: None,
+    };
+
+    mongo.refresh_tokens()
+        .insert_one(record)
+        .await
+        .map_err(|e| format!("保存刷新Token失败: {}", e))?;
+    Ok(Secret::new(token))
+}
+
+// 登录时为当前设备签发新的Token家族
+pub async fn issue(
+    mongo: &MongoManager,
+    auth: &AuthConfig,
+    user_id: ObjectId,
+    device_id: &str,
+    kind: RefreshKind,
+) -> Result<Secret<String>, String> {
+    let expires_at = DateTime::from_millis(
+        DateTime::now().timestamp_millis() + kind.lifetime_days(auth) * 24 * 60 * 60 * 1000,
+    );
+    let family_id = uuid::Uuid::new_v4().to_string();
+    insert_token(mongo, user_id, Some(device_id.to_string()), kind, family_id, expires_at).await
+}
+
+async fn revoke_family(mongo: &MongoManager, family_id: &str) -> Result<(), String> {
+    mongo.refresh_tokens()
+        .update_many(
+            doc! {"familyId": family_id, "revokedAt": {"$exists": false}},
+            doc! {"$set": {"revokedAt": DateTime::now()}},
+        )
+        .await
+        .map_err(|e| format!("撤销Token家族失败: {}", e))?;
+    Ok(())
+}
+
+// 撤销用户在指定设备上的刷新Token
+pub async fn revoke_device_tokens(
+    mongo: &MongoManager,
+    user_id: ObjectId,
+    device_id: &str,
+) -> Result<u64, String> {
+    let result = mongo.refresh_tokens()
+        .update_many(
+            doc! {"userId": user_id, "deviceId": device_id, "revokedAt": {"$exists": false}},
+            doc! {"$set": {"revokedAt": DateTime::now()}},
+        )
+        .await
+        .map_err(|e| format!("撤销设备Token失败: {}", e))?;
+    Ok(result.modified_count)
+}
+
+// 持有可用刷新Token的设备
+pub async fn active_device_ids(mongo: &MongoManager, user_id: ObjectId) -> Result<HashSet<String>, String> {
+    let ids = mongo.refresh_tokens()
+        .distinct(
+            "deviceId",
+            doc! {
+                "userId": user_id,
+                "usedAt": {"$exists": false},
+                "revokedAt": {"$exists": false},
+                "expiresAt": {"$gt": DateTime::now()}
+            },
+        )
+        .await
+        .map_err(|e| format!("查询设备Token失败: {}", e))?;
+
+    Ok(ids
+        .into_iter()
+        .filter_map(|id| id.as_str().map(|s| s.to_string()))
+        .collect())
+}
+
+// 撤销用户的全部Token（含旧版JWT），keep_device 为修改密码的本机时保留本机签发的刷新Token
+pub async fn revoke_user_tokens(
+    mongo: &MongoManager,
+    user_id: ObjectId,
+    keep_device: Option<&str>,
+) -> Result<u64, String> {
+    let legacy_filter = doc! {"userId": user_id};
+    let mut refresh_filter = doc! {"userId": user_id, "revokedAt": {"$exists": false}};
+    if let Some(device_id) = keep_device {
+        refresh_filter.insert("deviceId", doc! {"$ne": device_id});
+    }
+
+    let legacy = mongo.user_tokens()
+        .delete_many(legacy_filter)
+        .await
+        .map_err(|e| format!("清除旧Token失败: {}", e))?;
+    let refresh = mongo.refresh_tokens()
+        .update_many(refresh_filter, doc! {"$set": {"revokedAt": DateTime::now()}})
+        .await
+        .map_err(|e| format!("撤销刷新Token失败: {}", e))?;
+
+    Ok(legacy.deleted_count + refresh.modified_count)
+}
+
+// 使用刷新Token：作废旧Token并签发同一家族的新Token；
+// 已使用过的Token再次出现说明可能被盗用，整个家族立即作废
+pub async fn rotate(
+    mongo: &MongoManager,
+    token: &str,
+    kind: RefreshKind,
+    device_id: &str,
+) -> Result<(ObjectId, Secret<String>), String> {
+    let record = mongo.refresh_tokens()
+        .find_one(doc! {"tokenHash": hash_token(token)})
+        .await
+        .map_err(|e| format!("查询刷新Token失败: {}", e))?
+        .ok_or("Token无效或已过期")?;
+
+    if record.kind != kind {
+        return Err("Token类型不匹配".to_string());
+    }
+    if record.revoked_at.is_some() {
+        return Err("Token已被撤销，请重新登录".to_string());
+    }
+    if record.expires_at <= DateTime::now() {
+        return Err("Token无效或已过期".to_string());
+    }
+    // Token只能在签发它的设备上使用
+    if record.device_id.as_deref().is_some_and(|d| d != device_id) {
+        log::warn!("🚨 刷新Token在其他设备上使用，撤销Token家族: 用户ID={}", record.user_id.to_hex());
+        revoke_family(mongo, &record.family_id).await?;
+        return Err("Token已失效，请重新登录".to_string());
+    }
+
+    // 原子地标记为已使用，并发重放时只有一个请求能成功
+    let claimed = mongo.refresh_tokens()
+        .update_one(
+            doc! {"_id": record.id, "usedAt": {"$exists": false}, "revokedAt": {"$exists": false}},
+            doc! {"$set": {"usedAt": DateTime::now()}},
+        )
+        .await
+        .map_err(|e| format!("更新刷新Token失败: {}", e))?;
+
+    if claimed.modified_count == 0 {
+        log::warn!("🚨 检测到刷新Token重复使用，撤销Token家族: 用户ID={}", record.user_id.to_hex());
+        revoke_family(mongo, &record.family_id).await?;
+        return Err("Token已失效，请重新登录".to_string());
+    }
+
+    let next = insert_token(
+        mongo,
+        record.user_id,
+        record.device_id,
+        kind,
+        record.family_id,
+        record.expires_at,
+    )
+    .await?;
+    Ok((record.user_id, next))
+}
+
+// 使用刷新Token恢复登录，返回轮换后的新Token
+#[tauri::command]
+pub async fn refresh_session(
+    refresh_token: Secret<String>,
+    token_type: String,
+    state: tauri::State<'_, AppState>,
+) -> Result<LoginResponse, String> {
+    let kind = RefreshKind::parse(&token_type)?;
+
+    let mongo = state.mongo.read().await;
+    let (user_id, next_token) = rotate(&mongo, refresh_token.expose(), kind, &state.device.device_id).await?;
+
+    let user = mongo.users()
+        .find_one(doc! {"_id": user_id})
+        .await
+        .map_err(|e| format!("用户查询失败: {}", e))?
+        .ok_or("用户不存在")?;
+
+    if !user.is_active {
+        revoke_user_tokens(&mongo, user_id, None).await?;
+        return Err("账号已被禁用".to_string());
+    }
+
+    let (user_response, session_id) = auth::resume_session(&state, &mongo, user).await?;
+
+    let (remember_me_token, auto_login_token) = match kind {
+        RefreshKind::RememberMe => (Some(next_token), None),
+        RefreshKind::AutoLogin => (None, Some(next_token)),
+    };
+
+    Ok(LoginResponse {
+        user: Some(user_response),
+        second_factor_required: false,
+        challenge_id: None,
+        remember_me_token,
+        auto_login_token,
+        session_id: Some(session_id.to_hex()),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::TryStreamExt;
+
+    use crate::test_support::TestDb;
+
+    const DEVICE: &str = "device-1";
+
+    async fn family(mongo: &MongoManager, user_id: ObjectId) -> Vec<RefreshToken> {
+        mongo.refresh_tokens()
+            .find(doc! {"userId": user_id})
+            .await
+            .unwrap()
+            .try_collect()
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    #[ignore = "需要本机 mongod"]
+    async fn rotation_issues_next_token_in_the_same_family() {
+        let db = TestDb::new("refresh_rotate").await;
+        let user_id = ObjectId::new();
+        let first = issue(&db.mongo, &AuthConfig::default(), user_id, DEVICE, RefreshKind::RememberMe).await.unwrap();
+
+        let (rotated_user, second) = rotate(&db.mongo, first.expose(), RefreshKind::RememberMe, DEVICE).await.unwrap();
+        assert_eq!(rotated_user, user_id);
+        assert_ne!(second.expose(), first.expose());
+        assert!(rotate(&db.mongo, second.expose(), RefreshKind::AutoLogin, DEVICE).await.is_err());
+        rotate(&db.mongo, second.expose(), RefreshKind::RememberMe, DEVICE).await.unwrap();
+
+        let tokens = family(&db.mongo, user_id).await;
+        assert_eq!(tokens.len(), 3);
+        assert!(tokens.iter().all(|token| token.family_id == tokens[0].family_id && token.revoked_at.is_none()));
+        assert_eq!(tokens.iter().filter(|token| token.used_at.is_none()).count(), 1);
+        db.drop().await;
+    }
+
+    #[tokio::test]
+    #[ignore = "需要本机 mongod"]
+    async fn reused_token_revokes_the_whole_family() {
+        let db = TestDb::new("refresh_reuse").await;
+        let user_id = ObjectId::new();
+        let auth = AuthConfig::default();
+        let first = issue(&db.mongo, &auth, user_id, DEVICE, RefreshKind::RememberMe).await.unwrap();
+        // 同一用户的其他Token家族不受影响
+        let other = issue(&db.mongo, &auth, user_id, DEVICE, RefreshKind::AutoLogin).await.unwrap();
+
+        let (_, second) = rotate(&db.mongo, first.expose(), RefreshKind::RememberMe, DEVICE).await.unwrap();
+        assert_eq!(
+            rotate(&db.mongo, first.expose(), RefreshKind::RememberMe, DEVICE).await.unwrap_err(),
+            "Token已失效，请重新登录"
+        );
+        assert_eq!(
+            rotate(&db.mongo, second.expose(), RefreshKind::RememberMe, DEVICE).await.unwrap_err(),
+            "Token已被撤销，请重新登录"
+        );
+
+        let tokens = family(&db.mongo, user_id).await;
+        let revoked = tokens.iter().filter(|token| token.revoked_at.is_some()).count();
+        assert_eq!(revoked, 2);
+        rotate(&db.mongo, other.expose(), RefreshKind::AutoLogin, DEVICE).await.unwrap();
+        db.drop().await;
+    }
+
+    #[tokio::test]
+    #[ignore = "需要本机 mongod"]
+    async fn token_used_on_another_device_revokes_the_family() {
+        let db = TestDb::new("refresh_device").await;
+        let user_id = ObjectId::new();
+        let token = issue(&db.mongo, &AuthConfig::default(), user_id, DEVICE, RefreshKind::AutoLogin).await.unwrap();
+
+        assert_eq!(
+            rotate(&db.mongo, token.expose(), RefreshKind::AutoLogin, "device-2").await.unwrap_err(),
+            "Token已失效，请重新登录"
+        );
+        // 之后在原设备上也不能再使用
+        assert_eq!(
+            rotate(&db.mongo, token.expose(), RefreshKind::AutoLogin, DEVICE).await.unwrap_err(),
+            "Token已被撤销，请重新登录"
+        );
+        assert!(family(&db.mongo, user_id).await.iter().all(|token| token.revoked_at.is_some()));
+        db.drop().await;
+    }
+}
@@ -0,0 +1,645 @@
+use chrono::{Datelike, Duration, NaiveDate, Timelike, Utc};
+use chrono_tz::Tz;
+use lettre::{
+    message::{header::ContentType, Mailbox},
+    transport::smtp::authentication::Credentials,
+    AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor,
+};
+use mongodb::{
+    bson::{doc, DateTime},
+    error::{ErrorKind, WriteFailure},
+};
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+
+use crate::auth::{AppState, DailyGrowth, DailyUsage, MongoManager, PopularTool};
+use crate::config::{AppConfig, ReportConfig, SmtpConfig, SmtpSecurity};
+use crate::guard::{require, Permission};
+use crate::metrics::{self, DateRange, Granularity};
+use crate::retention::{self, ChurnedUser};
+
+// MongoDB 唯一索引冲突错误码
+const DUPLICATE_KEY_CODE: i32 = 11000;
+
+// 内置周报模板，{{name}} 形式的占位符在生成时替换
+const DEFAULT_TEMPLATE: &str = include_str!("../templates/weekly_report.html");
+// 每隔多少秒检查一次是否需要生成周报
+const CHECK_EVERY_SECS: u64 = 15 * 60;
+// 生成中的记录超过该时间仍未完成（程序中途退出），允许其他电脑接手
+const STALE_RUN_MINUTES: i64 = 60;
+
+// 一周的统计数据
+pub struct WeeklyReport {
+    pub week_start: NaiveDate,
+    pub week_end: NaiveDate,
+    pub tz: Tz,
+    pub active_users: i64,
+    pub new_users: i64,
+    pub sessions: i64,
+    pub clicks: i64,
+    pub usage_secs: i64,
+    pub daily: Vec<(DailyGrowth, DailyUsage)>,
+    pub top_tools: Vec<PopularTool>,
+    pub inactive_days: i64,
+    pub inactive_users: Vec<ChurnedUser>,
+}
+
+// 手动生成周报的响应结构
+#[derive(Debug, Serialize, Deserialize)]
+pub struct WeeklyReportResult {
+    #[serde(rename = "weekStart")]
+    pub week_start: String,
+    #[serde(rename = "weekEnd")]
+    pub week_end: String,
+    pub html: String,
+    // 保存的文件路径，未配置保存目录或未要求投递时为空
+    pub path: Option<String>,
+    pub emailed: bool,
+}
+
+// 截至 now 应该生成报告的那一周（周一）：每周 weekday 的 hour 点之后生成上一周的报告
+pub fn due_week(tz: Tz, weekday: chrono::Weekday, hour: u32, now: chrono::DateTime<Utc>) -> NaiveDate {
+    let local = now.with_timezone(&tz);
+    let today = local.date_naive();
+    let days_since = (today.weekday().num_days_from_monday() + 7 - weekday.num_days_from_monday()) % 7;
+    let mut run_day = today - Duration::days(days_since as i64);
+    if days_since == 0 && local.hour() < hour {
+        run_day -= Duration::days(7);
+    }
+    Granularity::Week.truncate(run_day) - Duration::days(7)
+}
+
+// 汇总 week_start 开始的一周（周一至周日）的统计数据
+pub async fn collect(
+    mongo: &MongoManager,
+    tz: Tz,
+    config: &ReportConfig,
+    week_start: NaiveDate,
+) -> Result<WeeklyReport, String> {
+    let range = DateRange {
+        start: week_start,
+        end: week_start + Duration::days(6),
+        granularity: Granularity::Day,
+        tz,
+    };
+
+    let (growth, usage) = metrics::trends(mongo, &range).await?;
+    let active_users = metrics::active_users(mongo, range.lower_bound(), range.upper_bound()).await?;
+    let top_tools = metrics::popular_tools(mongo, range.lower_bound(), range.upper_bound(), config.top_tools).await?;
+    let inactive_users = retention::churned_users(
+        mongo,
+        doc! {"isActive": true},
+        config.inactive_days,
+        config.inactive_limit,
+    )
+    .await?;
+
+    Ok(WeeklyReport {
+        week_start: range.start,
+        week_end: range.end,
+        tz,
+        active_users,
+        new_users: growth.iter().map(|day| day.new_users).sum(),
+        sessions: growth.iter().map(|day| day.total_sessions).sum(),
+        clicks: usage.iter().map(|day| day.total_clicks).sum(),
+        usage_secs: usage.iter().map(|day| day.total_usage_time).sum(),
+        daily: growth.into_iter().zip(usage).collect(),
+        top_tools,
+        inactive_days: config.inactive_days,
+        inactive_users,
+    })
+}
+
+fn escape_html(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+fn format_duration(secs: i64) -> String {
+    if secs >= 3600 {
+        format!("{}小时{}分钟", secs / 3600, secs % 3600 / 60)
+    } else {
+        format!("{}分钟", secs / 60)
+    }
+}
+
+// 生成表格，单元格内容需已转义
+fn html_table(headers: &[&str], rows: Vec<Vec<String>>) -> String {
+    if rows.is_empty() {
+        return "<p style=\"color:#8f959e;font-size:13px;\">暂无数据</p>".to_string();
+    }
+    let cell = "padding:6px 10px;border-bottom:1px solid #e5e6eb;text-align:left;font-size:13px;";
+    let mut html = String::from("<table style=\"width:100%;border-collapse:collapse;\"><tr>");
+    for header in headers {
+        html.push_str(&format!("<th style=\"{}color:#646a73;\">{}</th>", cell, header));
+    }
+    html.push_str("</tr>");
+    for row in rows {
+        html.push_str("<tr>");
+        for value in row {
+            html.push_str(&format!("<td style=\"{}\">{}</td>", cell, value));
+        }
+        html.push_str("</tr>");
+    }
+    html.push_str("</table>");
+    html
+}
+
+// 按模板生成 HTML 报告
+pub fn render(report: &WeeklyReport, template: &str) -> String {
+    let summary = html_table(
+        &["活跃用户", "新增用户", "会话数", "工具点击", "使用时长"],
+        vec![vec![
+            report.active_users.to_string(),
+            report.new_users.to_string(),
+            report.sessions.to_string(),
+            report.clicks.to_string(),
+            format_duration(report.usage_secs),
+        ]],
+    );
+
+    // 每日活跃用户附带按本周最大值缩放的条形
+    let max_dau = report.daily.iter().map(|(growth, _)| growth.active_users).max().unwrap_or(0).max(1);
+    let dau_trend = html_table(
+        &["日期", "活跃用户", "", "工具点击", "使用时长"],
+        report.daily
+            .iter()
+            .map(|(growth, usage)| {
+                let width = growth.active_users * 200 / max_dau;
+                vec![
+                    growth.date.clone(),
+                    growth.active_users.to_string(),
+                    format!("<div style=\"width:{}px;height:10px;background:#3370ff;border-radius:2px;\"></div>", width),
+                    usage.total_clicks.to_string(),
+                    format_duration(usage.total_usage_time),
+                ]
+            })
+            .collect(),
+    );
+
+    let top_tools = html_table(
+        &["工具", "点击次数", "使用时长", "使用人数"],
+        report.top_tools
+            .iter()
+            .map(|tool| vec![
+                escape_html(&tool.tool_name),
+                tool.total_clicks.to_string(),
+                format_duration(tool.total_usage_time),
+                tool.unique_users.to_string(),
+            ])
+            .collect(),
+    );
+
+    let inactive_users = html_table(
+        &["用户名", "部门", "最后活跃", "未活跃天数"],
+        report.inactive_users
+            .iter()
+            .map(|user| vec![
+                escape_html(&user.username),
+                escape_html(user.department.as_deref().unwrap_or("-")),
+                user.last_active_at
+                    .as_deref()
+                    .and_then(|at| chrono::DateTime::parse_from_rfc3339(at).ok())
+                    .map(|at| at.with_timezone(&report.tz).format("%Y-%m-%d").to_string())
+                    .unwrap_or_else(|| "从未使用".to_string()),
+                user.inactive_days.to_string(),
+            ])
+            .collect(),
+    );
+
+    let period = format!(
+        "{} 至 {}",
+        report.week_start.format("%Y-%m-%d"),
+        report.week_end.format("%Y-%m-%d")
+    );
+    let generated_at = Utc::now().with_timezone(&report.tz).format("%Y-%m-%d %H:%M").to_string();
+
+    [
+        ("{{title}}", format!("工具使用周报 {}", period)),
+        ("{{period}}", period),
+        ("{{timezone}}", report.tz.name().to_string()),
+        ("{{generated_at}}", generated_at),
+        ("{{summary}}", summary),
+        ("{{dau_trend}}", dau_trend),
+        ("{{top_tools}}", top_tools),
+        ("{{inactive_days}}", report.inactive_days.to_string()),
+        ("{{inactive_users}}", inactive_users),
+    ]
+    .iter()
+    .fold(template.to_string(), |html, (placeholder, value)| html.replace(placeholder, value))
+}
+
+fn load_template(config: &ReportConfig) -> Result<String, String> {
+    match &config.template {
+        Some(path) => std::fs::read_to_string(path)
+            .map_err(|e| format!("读取周报模板 {} 失败: {}", path.display(), e)),
+        None => Ok(DEFAULT_TEMPLATE.to_string()),
+    }
+}
+
+// 通过 SMTP 中继发送 HTML 邮件
+pub async fn send_mail(smtp: &SmtpConfig, subject: &str, html: String) -> Result<(), String> {
+    let from: Mailbox = smtp.from.parse().map_err(|e| format!("无效的发件人 {}: {}", smtp.from, e))?;
+    let mut builder = Message::builder().from(from).subject(subject).header(ContentType::TEXT_HTML);
+    for to in &smtp.to {
+        builder = builder.to(to.parse().map_err(|e| format!("无效的收件人 {}: {}", to, e))?);
+    }
+    let message = builder.body(html).map_err(|e| format!("生成邮件失败: {}", e))?;
+
+    let transport = match smtp.security {
+        SmtpSecurity::Starttls => AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(&smtp.host)
+            .map_err(|e| format!("连接 SMTP 服务器失败: {}", e))?,
+        SmtpSecurity::Tls => AsyncSmtpTransport::<Tokio1Executor>::relay(&smtp.host)
+            .map_err(|e| format!("连接 SMTP 服务器失败: {}", e))?,
+        SmtpSecurity::None => AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous(&smtp.host),
+    };
+    let mut transport = transport
+        .port(smtp.port)
+        .timeout(Some(std::time::Duration::from_secs(smtp.timeout_secs)));
+    if let Some(username) = smtp.username.as_deref().filter(|username| !username.is_empty()) {
+        let password = smtp.password.as_ref().map(|password| password.expose().clone()).unwrap_or_default();
+        transport = transport.credentials(Credentials::new(username.to_string(), password));
+    }
+
+    transport
+        .build()
+        .send(message)
+        .await
+        .map_err(|e| format!("发送周报邮件失败: {}", e))?;
+    Ok(())
+}
+
+// 保存到报告目录和/或发送邮件，返回保存的文件路径和是否已发送
+async fn deliver(config: &ReportConfig, report: &WeeklyReport, html: &str) -> Result<(Option<PathBuf>, bool), String> {
+    let mut path = None;
+    if let Some(dir) = &config.output_dir {
+        std::fs::create_dir_all(dir).map_err(|e| format!("创建报告目录 {} 失败: {}", dir.display(), e))?;
+        let file = dir.join(format!("weekly_report_{}.html", report.week_start.format("%Y-%m-%d")));
+        std::fs::write(&file, html).map_err(|e| format!("保存周报 {} 失败: {}", file.display(), e))?;
+        path = Some(file);
+    }
+
+    let mut emailed = false;
+    if let Some(smtp) = &config.smtp {
+        let subject = format!(
+            "工具使用周报 {} 至 {}",
+            report.week_start.format("%Y-%m-%d"),
+            report.week_end.format("%Y-%m-%d")
+        );
+        send_mail(smtp, &subject, html.to_string()).await?;
+        emailed = true;
+    }
+    Ok((path, emailed))
+}
+
+// 在 report_runs 中登记本周报告，多台管理员电脑同时运行时只有一台能登记成功；
+// 上次生成失败或中途退出的记录可以重新登记
+async fn claim(mongo: &MongoManager, run_id: &str, device_id: &str) -> Result<bool, String> {
+    let stale = DateTime::from_millis(Utc::now().timestamp_millis() - STALE_RUN_MINUTES * 60_000);
+    let result = mongo.report_runs()
+        .update_one(
+            doc! {
+                "_id": run_id,
+                "$or": [
+                    {"status": "failed"},
+                    {"status": "running", "claimedAt": {"$lt": stale}}
+                ]
+            },
+            doc! {"$set": {"status": "running", "deviceId": device_id, "claimedAt": DateTime::now()}},
+        )
+        .upsert(true)
+        .await;
+
+    match result {
+        Ok(_) => Ok(true),
+        Err(e) => match *e.kind {
+            // 记录已存在且不可接手时 upsert 会因 _id 重复失败
+            ErrorKind::Write(WriteFailure::WriteError(ref err)) if err.code == DUPLICATE_KEY_CODE => Ok(false),
+            _ => Err(format!("登记周报生成记录失败: {}", e)),
+        },
+    }
+}
+
+// 生成并投递一周的报告，结果记录在 report_runs 中
+async fn run_scheduled(
+    app_config: &AppConfig,
+    mongo: &MongoManager,
+    device_id: &str,
+    week_start: NaiveDate,
+) -> Result<bool, String> {
+    let config = &app_config.report;
+    let run_id = format!("weekly:{}", week_start.format("%Y-%m-%d"));
+    if !claim(mongo, &run_id, device_id).await? {
+        return Ok(false);
+    }
+
+    let result = async {
+        let report = collect(mongo, app_config.analytics.tz(), config, week_start).await?;
+        let html = render(&report, &load_template(config)?);
+        deliver(config, &report, &html).await
+    }
+    .await;
+
+    let update = match &result {
+        Ok((path, emailed)) => doc! {"$set": {
+            "status": "done",
+            "finishedAt": DateTime::now(),
+            "path": path.as_ref().map(|path| path.to_string_lossy().to_string()),
+            "emailed": emailed
+        }},
+        Err(e) => doc! {"$set": {"status": "failed", "finishedAt": DateTime::now(), "error": e}},
+    };
+    if let Err(e) = mongo.report_runs().update_one(doc! {"_id": &run_id}, update).await {
+        log::warn!("⚠️ 更新周报生成记录失败: {}", e);
+    }
+
+    let (path, emailed) = result?;
+    log::info!(
+        "📰 已生成 {} 开始的周报: 文件={}, 邮件={}",
+        week_start,
+        path.map(|path| path.display().to_string()).unwrap_or_else(|| "-".to_string()),
+        if emailed { "已发送" } else { "-" }
+    );
+    Ok(true)
+}
+
+// 后台任务：定期检查是否到了生成周报的时间；只有当前登录账号可以查看全部统计数据时才生成
+pub fn spawn_scheduler(state: &AppState) {
+    if !state.config.report.enabled {
+        return;
+    }
+    let config = state.config.clone();
+    let mongo = state.mongo.clone();
+    let current_user = state.current_user.clone();
+    let device_id = state.device.device_id.clone();
+
+    tauri::async_runtime::spawn(async move {
+        let tz = config.analytics.tz();
+        let weekday = config.report.weekday();
+        let hour = config.report.hour;
+        let mut last_done = None;
+        let mut interval = tokio::time::interval(std::time::Duration::from_secs(CHECK_EVERY_SECS));
+        loop {
+            interval.tick().await;
+
+            let week_start = due_week(tz, weekday, hour, Utc::now());
+            if last_done == Some(week_start) {
+                continue;
+            }
+            let is_admin = current_user
+                .read()
+                .await
+                .as_ref()
+                .is_some_and(|user| user.has_permission(Permission::ViewAnalytics));
+            if !is_admin {
+                continue;
+            }
+
+            match run_scheduled(&config, &*mongo.read().await, &device_id, week_start).await {
+                Ok(_) => last_done = Some(week_start),
+                Err(e) => log::warn!("⚠️ 生成周报失败，{} 分钟后重试: {}", CHECK_EVERY_SECS / 60, e),
+            }
+        }
+    });
+}
+
+// 立即生成周报，用于预览和检查投递配置。week_start 为该周任意一天（YYYY-MM-DD），
+// 默认上一周；deliver 为 true 时按配置保存文件和发送邮件，不影响定时生成
+#[tauri::command]
+pub async fn generate_weekly_report(
+    week_start: Option<String>,
+    deliver: Option<bool>,
+    state: tauri::State<'_, AppState>,
+) -> Result<WeeklyReportResult, String> {
+    let current_user = require(&state, Permission::ViewAnalytics).await?;
+    let tz = state.config.analytics.tz();
+    let config = &state.config.report;
+
+    let week_start = match week_start.as_deref() {
+        Some(value) => Granularity::Week.truncate(
+            NaiveDate::parse_from_str(value.trim(), "%Y-%m-%d")
+                .map_err(|e| format!("无效的日期 {}: {}", value, e))?,
+        ),
+        None => Granularity::Week.truncate(metrics::today(tz)) - Duration::days(7),
+    };
+
+    let mongo = state.mongo.read().await;
+    let report = collect(&mongo, tz, config, week_start).await?;
+    let html = render(&report, &load_template(config)?);
+    let (path, emailed) = if deliver.unwrap_or(false) {
+        self::deliver(config, &report, &html).await?
+    } else {
+        (None, false)
+    };
+
+    log::info!("📰 {} 手动生成了 {} 开始的周报", current_user.username, week_start);
+    Ok(WeeklyReportResult {
+        week_start: report.week_start.format("%Y-%m-%d").to_string(),
+        week_end: report.week_end.format("%Y-%m-%d").to_string(),
+        html,
+        path: path.map(|path| path.to_string_lossy().to_string()),
+        emailed,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+    use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+    use tokio::net::TcpListener;
+
+    // 只应答一封邮件的 SMTP 服务，返回收到的信封收件人和 DATA 内容
+    async fn mock_smtp(listener: TcpListener) -> (Vec<String>, String) {
+        let (socket, _) = listener.accept().await.unwrap();
+        let (reader, mut writer) = socket.into_split();
+        let mut lines = BufReader::new(reader).lines();
+        let mut recipients = Vec::new();
+        let mut data = String::new();
+
+        writer.write_all(b"220 mock ESMTP\r\n").await.unwrap();
+        while let Some(line) = lines.next_line().await.unwrap() {
+            let command = line.to_ascii_uppercase();
+            if command.starts_with("EHLO") || command.starts_with("HELO") {
+                writer.write_all(b"250-mock\r\n250 8BITMIME\r\n").await.unwrap();
+            } else if command.starts_with("RCPT TO:") {
+                recipients.push(line["RCPT TO:".len()..].to_string());
+                writer.write_all(b"250 OK\r\n").await.unwrap();
+            } else if command == "DATA" {
+                writer.write_all(b"354 go ahead\r\n").await.unwrap();
+                while let Some(line) = lines.next_line().await.unwrap() {
+                    if line == "." {
+                        break;
+                    }
+                    data.push_str(&line);
+                    data.push('\n');
+                }
+                writer.write_all(b"250 queued\r\n").await.unwrap();
+            } else if command == "QUIT" {
+                writer.write_all(b"221 bye\r\n").await.unwrap();
+                break;
+            } else {
+                writer.write_all(b"250 OK\r\n").await.unwrap();
+            }
+        }
+        (recipients, data)
+    }
+
+    #[tokio::test]
+    async fn send_mail_delivers_html_over_plain_smtp() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let server = tokio::spawn(mock_smtp(listener));
+
+        let smtp = SmtpConfig {
+            host: "127.0.0.1".to_string(),
+            port,
+            security: SmtpSecurity::None,
+            from: "report@example.com".to_string(),
+            to: vec!["admin@example.com".to_string(), "boss@example.com".to_string()],
+            timeout_secs: 5,
+            ..Default::default()
+        };
+        let html = "<html><body><h1>Weekly report</h1><p>clicks: 42</p></body></html>".to_string();
+        send_mail(&smtp, "Weekly report", html.clone()).await.unwrap();
+
+        let (recipients, data) = server.await.unwrap();
+        assert_eq!(recipients, ["<admin@example.com>", "<boss@example.com>"]);
+        assert!(data.contains("Subject: Weekly report"), "{}", data);
+        assert!(data.contains("Content-Type: text/html"), "{}", data);
+        // 头部和正文以空行分隔
+        let (_, body) = data.split_once("\n\n").unwrap();
+        assert_eq!(body.trim_end(), html);
+    }
+
+    #[test]
+    fn escape_html_escapes_markup_and_quotes() {
+        assert_eq!(
+            escape_html("<a href=\"x\">Tom & 'Jerry'</a>"),
+            "&lt;a href=&quot;x&quot;&gt;Tom &amp; &#39;Jerry&#39;&lt;/a&gt;"
+        );
+        assert_eq!(escape_html("工具箱"), "工具箱");
+    }
+
+    #[test]
+    fn format_duration_switches_to_hours_after_one_hour() {
+        assert_eq!(format_duration(59), "0分钟");
+        assert_eq!(format_duration(3599), "59分钟");
+        assert_eq!(format_duration(3600), "1小时0分钟");
+        assert_eq!(format_duration(5 * 3600 + 7 * 60 + 30), "5小时7分钟");
+    }
+
+    fn at(tz: Tz, value: &str) -> chrono::DateTime<Utc> {
+        let local = chrono::NaiveDateTime::parse_from_str(value, "%Y-%m-%d %H:%M").unwrap();
+        local.and_local_timezone(tz).unwrap().with_timezone(&Utc)
+    }
+
+    #[test]
+    fn due_week_waits_for_the_run_hour() {
+        let tz = chrono_tz::Asia::Shanghai;
+        let monday = chrono::Weekday::Mon;
+        let date = |value| NaiveDate::parse_from_str(value, "%Y-%m-%d").unwrap();
+
+        // 2026-10-12 是周一：8 点前上一次生成的是 10-05 那次，即 09-28 这一周
+        assert_eq!(due_week(tz, monday, 8, at(tz, "2026-10-12 07:59")), date("2026-09-28"));
+        assert_eq!(due_week(tz, monday, 8, at(tz, "2026-10-12 08:00")), date("2026-10-05"));
+        // 周中和周日都对应本周一生成的那次
+        assert_eq!(due_week(tz, monday, 8, at(tz, "2026-10-14 03:00")), date("2026-10-05"));
+        assert_eq!(due_week(tz, monday, 8, at(tz, "2026-10-18 23:59")), date("2026-10-05"));
+        // 按本地时区判断：UTC 周日 23 点已是上海的周一 7 点
+        assert_eq!(
+            due_week(tz, monday, 8, Utc.with_ymd_and_hms(2026, 10, 11, 23, 0, 0).unwrap()),
+            date("2026-09-28")
+        );
+        // 生成日不是周一时同样返回上一周的周一
+        assert_eq!(due_week(tz, chrono::Weekday::Wed, 9, at(tz, "2026-10-14 09:30")), date("2026-10-05"));
+        assert_eq!(due_week(tz, chrono::Weekday::Wed, 9, at(tz, "2026-10-14 08:30")), date("2026-09-28"));
+    }
+
+    fn report() -> WeeklyReport {
+        let day = |date: &str, active_users, clicks| {
+            (
+                DailyGrowth {
+                    date: date.to_string(),
+                    new_users: 1,
+                    active_users,
+                    total_sessions: active_users,
+                },
+                DailyUsage {
+                    date: date.to_string(),
+                    total_clicks: clicks,
+                    total_usage_time: clicks * 60,
+                    unique_users: active_users,
+                },
+            )
+        };
+        WeeklyReport {
+            week_start: NaiveDate::from_ymd_opt(2026, 10, 5).unwrap(),
+            week_end: NaiveDate::from_ymd_opt(2026, 10, 11).unwrap(),
+            tz: chrono_tz::Asia::Shanghai,
+            active_users: 4,
+            new_users: 2,
+            sessions: 6,
+            clicks: 30,
+            usage_secs: 7200,
+            daily: vec![day("2026-10-05", 4, 20), day("2026-10-06", 2, 10)],
+            top_tools: vec![PopularTool {
+                tool_id: 1,
+                tool_name: "<script>alert(1)</script>".to_string(),
+                total_clicks: 30,
+                total_usage_time: 1800,
+                unique_users: 4,
+            }],
+            inactive_days: 30,
+            inactive_users: vec![ChurnedUser {
+                id: "1".to_string(),
+                username: "tom&jerry".to_string(),
+                role: "user".to_string(),
+                department: None,
+                created_at: "2026-01-01T00:00:00Z".to_string(),
+                last_active_at: Some("2026-09-01T20:00:00Z".to_string()),
+                inactive_days: 47,
+            }],
+        }
+    }
+
+    #[test]
+    fn render_fills_every_placeholder_and_escapes_names() {
+        let template = "{{title}}|{{period}}|{{timezone}}|{{generated_at}}|{{summary}}|\
+                        {{dau_trend}}|{{top_tools}}|{{inactive_days}}|{{inactive_users}}";
+        let html = render(&report(), template);
+
+        assert!(!html.contains("{{"), "{}", html);
+        assert!(html.starts_with("工具使用周报 2026-10-05 至 2026-10-11|2026-10-05 至 2026-10-11|Asia/Shanghai|"), "{}", html);
+        assert!(html.contains("&lt;script&gt;alert(1)&lt;/script&gt;"), "{}", html);
+        assert!(!html.contains("<script>"), "{}", html);
+        assert!(html.contains("tom&amp;jerry"), "{}", html);
+        // 最后活跃时间按报告时区显示日期
+        assert!(html.contains(">2026-09-02<"), "{}", html);
+        assert!(html.contains("2小时0分钟"), "{}", html);
+        // 条形宽度按本周最大活跃用户数缩放
+        assert!(html.contains("width:200px"), "{}", html);
+        assert!(html.contains("width:100px"), "{}", html);
+        assert_eq!(render(&report(), "{{inactive_days}}"), "30");
+    }
+
+    #[test]
+    fn render_shows_placeholder_text_for_empty_tables() {
+        let mut report = report();
+        report.top_tools.clear();
+        report.inactive_users.clear();
+        assert!(render(&report, "{{top_tools}}").contains("暂无数据"));
+        assert!(render(&report, "{{inactive_users}}").contains("暂无数据"));
+        assert!(!render(&report, "{{summary}}").contains("暂无数据"));
+    }
+}
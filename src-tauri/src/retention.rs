@@ -0,0 +1,462 @@
+use chrono::{Duration, NaiveDate, Utc};
+use mongodb::bson::{doc, oid::ObjectId, Bson, DateTime, Document};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+
+use crate::auth::{AppState, MongoManager};
+use crate::daily_stats;
+use crate::guard::{require_any, Permission};
+use crate::metrics::{self, Granularity};
+
+// 未指定范围时按周统计最近12批、按月统计最近6批新用户
+const DEFAULT_WEEK_COHORTS: i64 = 12;
+const DEFAULT_MONTH_COHORTS: u32 = 6;
+const DEFAULT_WEEK_PERIODS: u32 = 8;
+const DEFAULT_MONTH_PERIODS: u32 = 6;
+const MAX_PERIODS: u32 = 52;
+const MAX_COHORTS: usize = 104;
+
+const DEFAULT_INACTIVE_DAYS: i64 = 30;
+const MAX_INACTIVE_DAYS: i64 = 365;
+const DEFAULT_CHURN_LIMIT: i64 = 100;
+const MAX_CHURN_LIMIT: i64 = 1000;
+
+// 留存报表响应结构
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RetentionReport {
+    pub granularity: Granularity,
+    pub timezone: String,
+    pub department: Option<String>,
+    pub cohorts: Vec<RetentionCohort>,
+}
+
+// 同一周（月）创建的一批用户
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RetentionCohort {
+    // 批次所在周（月）的第一天
+    pub cohort: String,
+    pub size: i64,
+    // 第0期为创建当周（月），之后依次为后续各期；尚未到来的期不返回
+    pub periods: Vec<RetentionPeriod>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RetentionPeriod {
+    pub period: u32,
+    pub start: String,
+    #[serde(rename = "activeUsers")]
+    pub active_users: i64,
+    // 活跃用户占该批用户的比例（0-1）
+    pub rate: f64,
+    // 当前期尚未结束
+    pub partial: bool,
+}
+
+// 流失用户响应结构
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ChurnedUser {
+    pub id: String,
+    pub username: String,
+    pub role: String,
+    pub department: Option<String>,
+    #[serde(rename = "createdAt")]
+    pub created_at: String,
+    // 最后一次登录或使用工具的时间，从未使用过时为空
+    #[serde(rename = "lastActiveAt")]
+    pub last_active_at: Option<String>,
+    #[serde(rename = "inactiveDays")]
+    pub inactive_days: i64,
+}
+
+fn parse_date(value: Option<&str>) -> Result<Option<NaiveDate>, String> {
+    value
+        .map(|value| {
+            NaiveDate::parse_from_str(value.trim(), "%Y-%m-%d")
+                .map_err(|e| format!("无效的日期 {}: {}", value, e))
+        })
+        .transpose()
+}
+
+// 按用户所属批次和有活动的周（月）计算各批次每期的留存，current_period 之后的期不返回
+fn cohort_table(
+    granularity: Granularity,
+    first_cohort: NaiveDate,
+    end: NaiveDate,
+    current_period: NaiveDate,
+    periods: u32,
+    cohort_of: &HashMap<ObjectId, NaiveDate>,
+    active_periods: &HashMap<ObjectId, HashSet<NaiveDate>>,
+) -> Vec<RetentionCohort> {
+    let mut members: HashMap<NaiveDate, Vec<ObjectId>> = HashMap::new();
+    for (user_id, cohort) in cohort_of {
+        members.entry(*cohort).or_default().push(*user_id);
+    }
+
+    let mut cohorts = Vec::new();
+    let mut cohort = first_cohort;
+    while cohort <= end {
+        let users = members.get(&cohort).map(Vec::as_slice).unwrap_or_default();
+        let size = users.len() as i64;
+
+        let mut cohort_periods = Vec::new();
+        let mut start = cohort;
+        for period in 0..periods {
+            if start > current_period {
+                break;
+            }
+            let active_users = users
+                .iter()
+                .filter(|user_id| active_periods.get(user_id).is_some_and(|periods| periods.contains(&start)))
+                .count() as i64;
+            cohort_periods.push(RetentionPeriod {
+                period,
+                start: metrics::bucket_key(start),
+                active_users,
+                rate: if size > 0 { active_users as f64 / size as f64 } else { 0.0 },
+                partial: start == current_period,
+            });
+            start = granularity.next(start);
+        }
+
+        cohorts.push(RetentionCohort {
+            cohort: metrics::bucket_key(cohort),
+            size,
+            periods: cohort_periods,
+        });
+        cohort = granularity.next(cohort);
+    }
+    cohorts
+}
+
+// 新用户留存：按创建时间分批，统计每批用户在之后各周（月）中有登录或使用记录的比例
+#[tauri::command]
+pub async fn get_retention_cohorts(
+    granularity: Option<String>,
+    start_date: Option<String>,
+    end_date: Option<String>,
+    periods: Option<u32>,
+    department: Option<String>,
+    state: tauri::State<'_, AppState>,
+) -> Result<RetentionReport, String> {
+    let current_user = require_any(&state, &[
+        Permission::ViewAnalytics,
+        Permission::ViewDepartmentAnalytics,
+    ]).await?;
+    let tz = state.config.analytics.tz();
+
+    let granularity = match granularity.as_deref() {
+        Some(value) => Granularity::parse(value)?,
+        None => Granularity::Week,
+    };
+    if granularity == Granularity::Day {
+        return Err("留存分析只支持按周或按月统计".to_string());
+    }
+    let periods = periods.unwrap_or(match granularity {
+        Granularity::Month => DEFAULT_MONTH_PERIODS,
+        _ => DEFAULT_WEEK_PERIODS,
+    });
+    if !(1..=MAX_PERIODS).contains(&periods) {
+        return Err(format!("统计期数必须在 1 到 {} 之间", MAX_PERIODS));
+    }
+
+    let today = metrics::today(tz);
+    let end = parse_date(end_date.as_deref())?.unwrap_or(today);
+    let first_cohort = match parse_date(start_date.as_deref())? {
+        Some(start) => granularity.truncate(start),
+        None => match granularity {
+            Granularity::Month => granularity.truncate(end) - chrono::Months::new(DEFAULT_MONTH_COHORTS - 1),
+            _ => granularity.truncate(end) - Duration::weeks(DEFAULT_WEEK_COHORTS - 1),
+        },
+    };
+    if first_cohort > end {
+        return Err("开始日期不能晚于结束日期".to_string());
+    }
+    let cohort_count = std::iter::successors(Some(first_cohort), |cohort| Some(granularity.next(*cohort)))
+        .take_while(|cohort| *cohort <= end)
+        .take(MAX_COHORTS + 1)
+        .count();
+    if cohort_count > MAX_COHORTS {
+        return Err(format!("时间范围过大，最多统计 {} 批用户", MAX_COHORTS));
+    }
+
+    let Some(mut user_filter) = current_user.analytics_department_filter(department.clone())? else {
+        return Ok(RetentionReport {
+            granularity,
+            timezone: tz.name().to_string(),
+            department,
+            cohorts: Vec::new(),
+        });
+    };
+    user_filter.insert(
+        "createdAt",
+        doc! {
+            "$gte": metrics::day_start(tz, first_cohort),
+            "$lt": metrics::day_start(tz, end + Duration::days(1))
+        },
+    );
+
+    let mongo = state.mongo.read().await;
+
+    // 每个用户所属的批次
+    let mut cursor = mongo.users()
+        .clone_with_type::<Document>()
+        .find(user_filter)
+        .projection(doc! {"_id": 1, "createdAt": 1})
+        .await
+        .map_err(|e| format!("查询用户失败: {}", e))?;
+    let mut cohort_of: HashMap<ObjectId, NaiveDate> = HashMap::new();
+    while cursor.advance().await.map_err(|e| format!("遍历用户失败: {}", e))? {
+        let user = cursor.deserialize_current().map_err(|e| format!("反序列化用户失败: {}", e))?;
+        if let (Ok(id), Ok(created_at)) = (user.get_object_id("_id"), user.get_datetime("createdAt")) {
+            cohort_of.insert(id, granularity.truncate(metrics::business_date(tz, *created_at)));
+        }
+    }
+
+    // 每个用户有活动的周（月）
+    let user_ids: Vec<ObjectId> = cohort_of.keys().copied().collect();
+    let mut active_periods: HashMap<ObjectId, HashSet<NaiveDate>> = HashMap::new();
+    if !user_ids.is_empty() {
+        let pipeline = vec![
+            doc! {
+                "$match": {
+                    "userId": {"$in": &user_ids},
+                    "toolId": daily_stats::USER_TOTAL,
+                    "date": {"$gte": metrics::day_start(tz, first_cohort)}
+                }
+            },
+            doc! {
+                "$group": {
+                    "_id": "$userId",
+                    "periods": {
+                        "$addToSet": {
+                            "$dateTrunc": {
+                                "date": "$date",
+                                "unit": granularity.unit(),
+                                "timezone": tz.name(),
+                                "startOfWeek": "monday"
+                            }
+                        }
+                    }
+                }
+            },
+        ];
+        let mut cursor = mongo.daily_stats()
+            .aggregate(pipeline)
+            .await
+            .map_err(|e| format!("留存统计聚合失败: {}", e))?;
+        while cursor.advance().await.map_err(|e| format!("遍历留存统计失败: {}", e))? {
+            let doc = cursor.deserialize_current().map_err(|e| format!("反序列化留存统计失败: {}", e))?;
+            let Ok(user_id) = doc.get_object_id("_id") else { continue };
+            let periods = doc.get_array("periods")
+                .map(|values| {
+                    values.iter()
+                        .filter_map(Bson::as_datetime)
+                        .map(|at| metrics::business_date(tz, *at))
+                        .collect()
+                })
+                .unwrap_or_default();
+            active_periods.insert(user_id, periods);
+        }
+    }
+
+    let cohorts = cohort_table(
+        granularity,
+        first_cohort,
+        end,
+        granularity.truncate(today),
+        periods,
+        &cohort_of,
+        &active_periods,
+    );
+
+    log::info!("📈 {} 查询留存分析: {} 批用户", current_user.username, cohorts.len());
+    Ok(RetentionReport {
+        granularity,
+        timezone: tz.name().to_string(),
+        department,
+        cohorts,
+    })
+}
+
+// 查询 user_filter 范围内超过 inactive_days 天未活跃的用户，流失用户查询和周报共用
+pub async fn churned_users(
+    mongo: &MongoManager,
+    user_filter: Document,
+    inactive_days: i64,
+    limit: i64,
+) -> Result<Vec<ChurnedUser>, String> {
+    let now = Utc::now().timestamp_millis();
+    let cutoff = DateTime::from_millis(now - inactive_days * 86_400_000);
+
+    let pipeline = vec![
+        doc! {"$match": user_filter},
+        // 每日统计中最近一天的活动
+        doc! {
+            "$lookup": {
+                "from": "daily_stats",
+                "localField": "_id",
+                "foreignField": "userId",
+                "pipeline": [
+                    {"$match": {"toolId": daily_stats::USER_TOTAL}},
+                    {"$sort": {"date": -1}},
+                    {"$limit": 1},
+                    {"$project": {"_id": 0, "date": 1}}
+                ],
+                "as": "lastStat"
+            }
+        },
+        doc! {
+            "$addFields": {
+                "lastActiveAt": {
+                    "$max": ["$lastLoginAt", {"$arrayElemAt": ["$lastStat.date", 0]}]
+                }
+            }
+        },
+        doc! {
+            "$addFields": {
+                "inactiveSince": {"$ifNull": ["$lastActiveAt", "$createdAt"]}
+            }
+        },
+        doc! {"$match": {"inactiveSince": {"$lt": cutoff}}},
+        doc! {"$sort": {"inactiveSince": 1, "_id": 1}},
+        doc! {"$limit": limit},
+    ];
+
+    let mut cursor = mongo.users()
+        .aggregate(pipeline)
+        .await
+        .map_err(|e| format!("流失用户查询失败: {}", e))?;
+
+    let mut results = Vec::new();
+    while cursor.advance().await.map_err(|e| format!("遍历流失用户失败: {}", e))? {
+        let doc = cursor.deserialize_current().map_err(|e| format!("反序列化流失用户失败: {}", e))?;
+        let to_rfc3339 = |dt: &DateTime| dt.try_to_rfc3339_string().unwrap_or_default();
+        let inactive_since = doc.get_datetime("inactiveSince").map(|dt| dt.timestamp_millis()).unwrap_or(now);
+
+        results.push(ChurnedUser {
+            id: doc.get_object_id("_id").map(|id| id.to_hex()).unwrap_or_default(),
+            username: doc.get_str("username").unwrap_or("").to_string(),
+            role: doc.get_str("role").unwrap_or("user").to_string(),
+            department: doc.get_str("department").ok().map(str::to_string),
+            created_at: doc.get_datetime("createdAt").map(to_rfc3339).unwrap_or_default(),
+            last_active_at: doc.get_datetime("lastActiveAt").ok().map(to_rfc3339),
+            inactive_days: (now - inactive_since) / 86_400_000,
+        });
+    }
+    Ok(results)
+}
+
+// 流失用户：超过 inactive_days 天没有登录或使用记录的启用账号（新建账号从创建时间起算），
+// 按不活跃时间从长到短排列
+#[tauri::command]
+pub async fn get_churned_users(
+    inactive_days: Option<i64>,
+    department: Option<String>,
+    limit: Option<i64>,
+    state: tauri::State<'_, AppState>,
+) -> Result<Vec<ChurnedUser>, String> {
+    let current_user = require_any(&state, &[
+        Permission::ViewAnalytics,
+        Permission::ViewDepartmentAnalytics,
+    ]).await?;
+
+    let inactive_days = inactive_days.unwrap_or(DEFAULT_INACTIVE_DAYS);
+    if !(1..=MAX_INACTIVE_DAYS).contains(&inactive_days) {
+        return Err(format!("不活跃天数必须在 1 到 {} 之间", MAX_INACTIVE_DAYS));
+    }
+    let limit = limit.unwrap_or(DEFAULT_CHURN_LIMIT).clamp(1, MAX_CHURN_LIMIT);
+
+    let Some(mut user_filter) = current_user.analytics_department_filter(department)? else {
+        return Ok(Vec::new());
+    };
+    user_filter.insert("isActive", true);
+
+    let mongo = state.mongo.read().await;
+    let results = churned_users(&mongo, user_filter, inactive_days, limit).await?;
+
+    log::info!("📉 {} 查询流失用户: 超过 {} 天未活跃，共 {} 人", current_user.username, inactive_days, results.len());
+    Ok(results)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(value: &str) -> NaiveDate {
+        NaiveDate::parse_from_str(value, "%Y-%m-%d").unwrap()
+    }
+
+    #[test]
+    fn weekly_cohorts_count_activity_per_period() {
+        let (a, b, c, d) = (ObjectId::new(), ObjectId::new(), ObjectId::new(), ObjectId::new());
+        let cohort_of = HashMap::from([
+            (a, date("2026-09-28")),
+            (b, date("2026-09-28")),
+            (c, date("2026-09-28")),
+            (d, date("2026-10-05")),
+        ]);
+        let active_periods = HashMap::from([
+            (a, HashSet::from([date("2026-09-28"), date("2026-10-05"), date("2026-10-12")])),
+            (b, HashSet::from([date("2026-09-28"), date("2026-10-12")])),
+            // 批次之外的周不计入
+            (d, HashSet::from([date("2026-09-28")])),
+        ]);
+
+        let cohorts = cohort_table(
+            Granularity::Week,
+            date("2026-09-28"),
+            date("2026-10-18"),
+            date("2026-10-12"),
+            8,
+            &cohort_of,
+            &active_periods,
+        );
+
+        let summary: Vec<_> = cohorts
+            .iter()
+            .map(|cohort| {
+                let periods: Vec<_> = cohort.periods
+                    .iter()
+                    .map(|period| (period.period, period.start.as_str(), period.active_users, period.partial))
+                    .collect();
+                (cohort.cohort.as_str(), cohort.size, periods)
+            })
+            .collect();
+        assert_eq!(summary, [
+            ("2026-09-28", 3, vec![
+                (0, "2026-09-28", 2, false),
+                (1, "2026-10-05", 1, false),
+                (2, "2026-10-12", 2, true),
+            ]),
+            ("2026-10-05", 1, vec![(0, "2026-10-05", 0, false), (1, "2026-10-12", 0, true)]),
+            ("2026-10-12", 0, vec![(0, "2026-10-12", 0, true)]),
+        ]);
+
+        let rates: Vec<f64> = cohorts[0].periods.iter().map(|period| period.rate).collect();
+        assert_eq!(rates, [2.0 / 3.0, 1.0 / 3.0, 2.0 / 3.0]);
+        // 空批次的留存率为 0 而不是 NaN
+        assert_eq!(cohorts[2].periods[0].rate, 0.0);
+    }
+
+    #[test]
+    fn period_count_limits_each_cohort() {
+        let user = ObjectId::new();
+        let cohort_of = HashMap::from([(user, date("2026-01-01"))]);
+        let active_periods = HashMap::from([(user, HashSet::from([date("2026-01-01"), date("2026-03-01")]))]);
+
+        let cohorts = cohort_table(
+            Granularity::Month,
+            date("2026-01-01"),
+            date("2026-01-31"),
+            date("2026-10-01"),
+            3,
+            &cohort_of,
+            &active_periods,
+        );
+
+        assert_eq!(cohorts.len(), 1);
+        let periods: Vec<_> = cohorts[0].periods.iter().map(|period| (period.start.as_str(), period.rate)).collect();
+        assert_eq!(periods, [("2026-01-01", 1.0), ("2026-02-01", 0.0), ("2026-03-01", 1.0)]);
+        assert!(cohorts[0].periods.iter().all(|period| !period.partial));
+    }
+}
@@ -0,0 +1,224 @@
+use mongodb::{
+    bson::{doc, oid::ObjectId, DateTime, Document},
+    options::ReturnDocument,
+    IndexModel,
+};
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+use chrono_tz::Tz;
+
+use crate::auth::{AppState, MongoManager, UserSession};
+use crate::daily_stats;
+
+// 会话结束原因
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SessionEndReason {
+    // 用户主动退出
+    Logout,
+    // 同一客户端上开始了新的会话
+    Superseded,
+    // 长时间没有心跳（崩溃、强制退出或断网），按最后一次心跳时间关闭
+    Stale,
+}
+
+impl SessionEndReason {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SessionEndReason::Logout => "logout",
+            SessionEndReason::Superseded => "superseded",
+            SessionEndReason::Stale => "stale",
+        }
+    }
+}
+
+pub async fn ensure_indexes(mongo: &MongoManager) -> Result<(), String> {
+    let indexes = [
+        IndexModel::builder()
+            .keys(doc! {"logoutAt": 1, "lastHeartbeatAt": 1})
+            .build(),
+        IndexModel::builder()
+            .keys(doc! {"userId": 1, "loginAt": -1})
+            .build(),
+    ];
+
+    mongo.user_sessions()
+        .create_indexes(indexes)
+        .await
+        .map_err(|e| format!("创建会话索引失败: {}", e))?;
+    Ok(())
+}
+
+// 以毫秒差计算会话时长（秒）的聚合表达式
+fn duration_secs(end: impl Into<mongodb::bson::Bson>) -> Document {
+    doc! {"$toLong": {"$divide": [{"$subtract": [end.into(), "$loginAt"]}, 1000]}}
+}
+
+// 开始新会话并设为当前会话；本客户端上一个未结束的会话随之关闭
+pub async fn start(state: &AppState, mongo: &MongoManager, user_id: ObjectId) -> Result<ObjectId, String> {
+    let previous = state.current_session.write().await.take();
+    if let Some(previous) = previous {
+        close(mongo, state.config.analytics.tz(), previous, None, SessionEndReason::Superseded).await?;
+    }
+
+    let session_id = insert(mongo, state.config.analytics.tz(), user_id, &state.device.device_id).await?;
+    *state.current_session.write().await = Some(session_id);
+    Ok(session_id)
+}
+
+async fn insert(mongo: &MongoManager, tz: Tz, user_id: ObjectId, device_id: &str) -> Result<ObjectId, String> {
+    let now = DateTime::now();
+    let session = UserSession {
+        id: None,
+        user_id,
+        login_at: now,
+        logout_at: None,
+        session_duration: None,
+        device_id: Some(device_id.to_string()),
+        last_heartbeat_at: Some(now),
+        end_reason: None,
+    };
+
+    let result = mongo.user_sessions()
+        .insert_one(session)
+        .await
+        .map_err(|e| format!("创建会话失败: {}", e))?;
+
+    if let Err(e) = daily_stats::add(mongo, tz, user_id, None, now, doc! {"sessions": 1}).await {
+        log::warn!("⚠️ {}", e);
+    }
+    result.inserted_id.as_object_id().ok_or_else(|| "会话ID无效".to_string())
+}
+
+// 关闭指定会话并计算时长；会话已结束时返回 false
+pub async fn close(
+    mongo: &MongoManager,
+    tz: Tz,
+    session_id: ObjectId,
+    user_id: Option<ObjectId>,
+    reason: SessionEndReason,
+) -> Result<bool, String> {
+    let now = DateTime::now();
+    let mut filter = doc! {"_id": session_id, "logoutAt": null};
+    if let Some(user_id) = user_id {
+        filter.insert("userId", user_id);
+    }
+    end(mongo, tz, filter, doc! {"logoutAt": now, "lastHeartbeatAt": now, "sessionDuration": duration_secs(now)}, reason).await
+}
+
+// 结束一个会话，并把时长计入登录当天的每日统计（平均会话时长只读汇总）
+async fn end(
+    mongo: &MongoManager,
+    tz: Tz,
+    filter: Document,
+    mut set: Document,
+    reason: SessionEndReason,
+) -> Result<bool, String> {
+    set.insert("endReason", reason.as_str());
+    let session = mongo.user_sessions()
+        .find_one_and_update(filter, vec![doc! {"$set": set}])
+        .return_document(ReturnDocument::After)
+        .await
+        .map_err(|e| format!("关闭会话失败: {}", e))?;
+    let Some(session) = session else {
+        return Ok(false);
+    };
+
+    if let Some(secs) = session.session_duration.filter(|secs| *secs > 0) {
+        let inc = doc! {"endedSessions": 1, "sessionSecs": secs};
+        if let Err(e) = daily_stats::add(mongo, tz, session.user_id, None, session.login_at, inc).await {
+            log::warn!("⚠️ {}", e);
+        }
+    }
+    Ok(true)
+}
+
+// 刷新会话心跳；会话已被关闭时返回 false
+pub async fn heartbeat(mongo: &MongoManager, session_id: ObjectId) -> Result<bool, String> {
+    let result = mongo.user_sessions()
+        .update_one(
+            doc! {"_id": session_id, "logoutAt": null},
+            doc! {"$set": {"lastHeartbeatAt": DateTime::now()}},
+        )
+        .await
+        .map_err(|e| format!("更新会话心跳失败: {}", e))?;
+    Ok(result.matched_count > 0)
+}
+
+// 关闭超时没有心跳的会话，结束时间取最后一次心跳（旧记录没有心跳时取登录时间）
+pub async fn reap_stale(mongo: &MongoManager, tz: Tz, stale_after_secs: u64) -> Result<u64, String> {
+    let cutoff = DateTime::from_millis(DateTime::now().timestamp_millis() - stale_after_secs as i64 * 1000);
+    let last_seen = doc! {"$ifNull": ["$lastHeartbeatAt", "$loginAt"]};
+
+    let stale = mongo.user_sessions()
+        .distinct(
+            "_id",
+            doc! {
+                "logoutAt": null,
+                "$or": [
+                    {"lastHeartbeatAt": {"$lt": cutoff}},
+                    {"lastHeartbeatAt": null, "loginAt": {"$lt": cutoff}}
+                ]
+            },
+        )
+        .await
+        .map_err(|e| format!("查询超时会话失败: {}", e))?;
+
+    // 逐个关闭，以便把每个会话的时长计入每日统计；期间被其他客户端关闭的会话跳过
+    let mut closed = 0;
+    for session_id in stale.iter().filter_map(|id| id.as_object_id()) {
+        let set = doc! {"logoutAt": last_seen.clone(), "sessionDuration": duration_secs(last_seen.clone())};
+        if end(mongo, tz, doc! {"_id": session_id, "logoutAt": null}, set, SessionEndReason::Stale).await? {
+            closed += 1;
+        }
+    }
+    Ok(closed)
+}
+
+// 后台任务：定期刷新当前会话心跳，并关闭所有客户端遗留的超时会话
+pub fn spawn_heartbeat(state: &AppState) {
+    let config = state.config.clone();
+    let mongo = state.mongo.clone();
+    let current_session = state.current_session.clone();
+    let current_user = state.current_user.clone();
+    let device_id = state.device.device_id.clone();
+
+    tauri::async_runtime::spawn(async move {
+        let mut interval = tokio::time::interval(Duration::from_secs(config.session.heartbeat_secs));
+        loop {
+            interval.tick().await;
+            let mongo = mongo.read().await;
+
+            let session_id = *current_session.read().await;
+            if let Some(session_id) = session_id {
+                match heartbeat(&mongo, session_id).await {
+                    // 休眠或断网过久时会话已被关闭，为仍在线的用户重新开始会话
+                    Ok(false) => {
+                        let user_id = current_user
+                            .read()
+                            .await
+                            .as_ref()
+                            .and_then(|user| ObjectId::parse_str(&user.id).ok());
+                        let restarted = match user_id {
+                            Some(user_id) => insert(&mongo, config.analytics.tz(), user_id, &device_id).await.ok(),
+                            None => None,
+                        };
+                        if restarted.is_some() {
+                            log::info!("🔄 会话已超时关闭，已重新开始会话");
+                        }
+                        *current_session.write().await = restarted;
+                    }
+                    Ok(true) => {}
+                    Err(e) => log::warn!("⚠️ {}", e),
+                }
+            }
+
+            match reap_stale(&mongo, config.analytics.tz(), config.session.stale_after_secs).await {
+                Ok(0) => {}
+                Ok(count) => log::info!("🧹 已关闭 {} 个超时会话", count),
+                Err(e) => log::warn!("⚠️ {}", e),
+            }
+        }
+    });
+}
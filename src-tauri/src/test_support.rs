@@ -0,0 +1,122 @@
+use std::cell::RefCell;
+use std::path::Path;
+use std::sync::Once;
+
+use mongodb::bson::DateTime;
+
+use crate::auth::{AppState, MongoManager, User, UserResponse};
+use crate::config::AppConfig;
+use crate::device::DeviceInfo;
+use crate::event_queue::EventQueue;
+use crate::rbac;
+use crate::secret::Secret;
+
+// 集成测试连接的 mongod，默认使用本机
+const ENV_TEST_MONGO_URI: &str = "CHENGSHANG_TEST_MONGO_URI";
+const DEFAULT_TEST_MONGO_URI: &str = "mongodb://localhost:27017/?directConnection=true";
+
+// 需要 mongod 的测试标记为 #[ignore]，运行方式：
+// CHENGSHANG_TEST_MONGO_URI=mongodb://localhost:27017 cargo test -- --ignored
+// 每个测试使用独立的临时数据库，结束后调用 drop 删除
+pub struct TestDb {
+    pub uri: String,
+    pub database: String,
+    pub mongo: MongoManager,
+}
+
+impl TestDb {
+    pub async fn new(name: &str) -> Self {
+        let uri = std::env::var(ENV_TEST_MONGO_URI).unwrap_or_else(|_| DEFAULT_TEST_MONGO_URI.to_string());
+        let suffix = uuid::Uuid::new_v4().simple().to_string();
+        let database = format!("chengshang_test_{}_{}", name, &suffix[..8]);
+        let mongo = MongoManager::new(&uri, &database)
+            .await
+            .unwrap_or_else(|e| panic!("无法连接测试数据库 {}: {}", uri, e));
+        TestDb { uri, database, mongo }
+    }
+
+    pub async fn drop(self) {
+        self.mongo.drop_database().await.expect("删除测试数据库失败");
+    }
+}
+
+// 连接测试数据库的应用状态，签名密钥和离线事件队列写入 dir
+pub async fn app_state(db: &TestDb, dir: &Path) -> AppState {
+    let mut config = AppConfig::default();
+    config.mongo.uri = db.uri.clone();
+    config.mongo.database = db.database.clone();
+    config.auth.signing_keys_dir = dir.join("signing_keys");
+    let device = DeviceInfo {
+        device_id: "test-device".to_string(),
+        hostname: "test-host".to_string(),
+        os: "test-os".to_string(),
+        app_version: "0.0.0".to_string(),
+    };
+    let events = EventQueue::open(dir).unwrap();
+    AppState::new(config, device, events).await.unwrap()
+}
+
+// 创建指定角色的用户（不可用于密码登录）并设为当前登录用户
+pub async fn sign_in(state: &AppState, username: &str, role: &str) -> UserResponse {
+    let mongo = state.mongo.read().await;
+    let mut user = User {
+        id: None,
+        username: username.to_string(),
+        password: Secret::new(String::new()),
+        password_history: Vec::new(),
+        role: role.to_string(),
+        roles: vec![role.to_string()],
+        department: None,
+        is_active: true,
+        created_at: DateTime::now(),
+        last_login_at: None,
+        total_usage_time: 0,
+        login_count: 0,
+    };
+    let result = mongo.users().insert_one(&user).await.unwrap();
+    user.id = result.inserted_id.as_object_id();
+
+    let response = rbac::resolve_user(&mongo, user).await.unwrap();
+    *state.current_user.write().await = Some(response.clone());
+    response
+}
+
+thread_local! {
+    static CAPTURED: RefCell<Option<Vec<String>>> = const { RefCell::new(None) };
+}
+
+// 把当前线程的日志记录下来，用于检查日志中是否出现敏感信息
+struct CaptureLogger;
+
+impl log::Log for CaptureLogger {
+    fn enabled(&self, _: &log::Metadata) -> bool {
+        true
+    }
+
+    fn log(&self, record: &log::Record) {
+        CAPTURED.with(|captured| {
+            if let Some(lines) = captured.borrow_mut().as_mut() {
+                lines.push(record.args().to_string());
+            }
+        });
+    }
+
+    fn flush(&self) {}
+}
+
+static LOGGER: CaptureLogger = CaptureLogger;
+static INIT_LOGGER: Once = Once::new();
+
+// 开始记录当前线程的日志，配合单线程的 #[tokio::test] 使用
+pub fn capture_logs() {
+    INIT_LOGGER.call_once(|| {
+        log::set_logger(&LOGGER).expect("测试日志已被其他 logger 占用");
+        log::set_max_level(log::LevelFilter::Trace);
+    });
+    CAPTURED.with(|captured| *captured.borrow_mut() = Some(Vec::new()));
+}
+
+// 结束记录并返回期间的日志
+pub fn captured_logs() -> Vec<String> {
+    CAPTURED.with(|captured| captured.borrow_mut().take().unwrap_or_default())
+}
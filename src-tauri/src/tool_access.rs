@@ -0,0 +1,342 @@
+use futures::TryStreamExt;
+use mongodb::{
+    bson::{doc, oid::ObjectId, DateTime, Document},
+    options::IndexOptions,
+    IndexModel,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+use crate::auth::{AppState, MongoManager, User, UserResponse};
+use crate::guard::{current_caller, require, AuthError, Permission};
+use crate::rbac;
+use crate::tools;
+
+// 工具授权：没有授权记录的工具对所有登录用户开放，
+// 一旦存在授权记录，只有命中角色、部门或用户名单的用户可以使用
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ToolGrant {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    pub id: Option<ObjectId>,
+    #[serde(rename = "toolId")]
+    pub tool_id: i32,
+    #[serde(rename = "toolUrl", default, skip_serializing_if = "Option::is_none")]
+    pub tool_url: Option<String>,
+    #[serde(default)]
+    pub roles: Vec<String>,
+    #[serde(default)]
+    pub departments: Vec<String>,
+    #[serde(rename = "userIds", default)]
+    pub user_ids: Vec<ObjectId>,
+    #[serde(rename = "updatedAt")]
+    pub updated_at: DateTime,
+    #[serde(rename = "updatedBy")]
+    pub updated_by: String,
+}
+
+// 工具授权响应结构
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ToolGrantResponse {
+    #[serde(rename = "toolId")]
+    pub tool_id: i32,
+    #[serde(rename = "toolUrl")]
+    pub tool_url: Option<String>,
+    pub roles: Vec<String>,
+    pub departments: Vec<String>,
+    #[serde(rename = "userIds")]
+    pub user_ids: Vec<String>,
+    #[serde(rename = "updatedAt")]
+    pub updated_at: String,
+    #[serde(rename = "updatedBy")]
+    pub updated_by: String,
+}
+
+impl From<ToolGrant> for ToolGrantResponse {
+    fn from(grant: ToolGrant) -> Self {
+        ToolGrantResponse {
+            tool_id: grant.tool_id,
+            tool_url: grant.tool_url,
+            roles: grant.roles,
+            departments: grant.departments,
+            user_ids: grant.user_ids.iter().map(|id| id.to_hex()).collect(),
+            updated_at: grant.updated_at.try_to_rfc3339_string().unwrap_or_default(),
+            updated_by: grant.updated_by,
+        }
+    }
+}
+
+impl ToolGrant {
+    // 判断授权记录是否允许指定用户使用该工具
+    pub fn allows(&self, user: &UserResponse) -> bool {
+        if user.has_permission(Permission::ManageTools) {
+            return true;
+        }
+        if self.user_ids.iter().any(|id| id.to_hex() == user.id) {
+            return true;
+        }
+        if self.roles.iter().any(|role| user.roles.contains(role)) {
+            return true;
+        }
+        match &user.department {
+            Some(department) => self.departments.contains(department),
+            None => false,
+        }
+    }
+
+    pub fn matches_url(&self, url: &str) -> bool {
+        match &self.tool_url {
+            Some(tool_url) => url_matches(tool_url, url),
+            None => false,
+        }
+    }
+}
+
+// 判断URL是否属于工具地址（忽略末尾斜杠，允许子路径）
+fn url_matches(tool_url: &str, url: &str) -> bool {
+    let tool_url = tool_url.trim_end_matches('/');
+    let url = url.trim_end_matches('/');
+    !tool_url.is_empty()
+        && (url == tool_url
+            || url.strip_prefix(tool_url)
+                .map(|rest| rest.starts_with(['/', '?', '#']))
+                .unwrap_or(false))
+}
+
+pub async fn ensure_indexes(mongo: &MongoManager) -> Result<(), String> {
+    mongo.tool_grants()
+        .create_index(
+            IndexModel::builder()
+                .keys(doc! {"toolId": 1})
+                .options(IndexOptions::builder().unique(true).build())
+                .build(),
+        )
+        .await
+        .map_err(|e| format!("创建工具授权索引失败: {}", e))?;
+    Ok(())
+}
+
+// 校验用户是否可以使用指定工具
+pub async fn ensure_tool_allowed(
+    mongo: &MongoManager,
+    user: &UserResponse,
+    tool_id: i32,
+) -> Result<(), String> {
+    if tool_allowed(mongo, user, tool_id).await? {
+        Ok(())
+    } else {
+        log::warn!("❌ 工具访问拒绝 - 用户 {} 无权使用工具 {}", user.username, tool_id);
+        Err(AuthError::ToolDenied { tool_id }.into())
+    }
+}
+
+// 用户是否可以使用指定工具（没有授权记录的工具所有人可用）
+pub async fn tool_allowed(mongo: &MongoManager, user: &UserResponse, tool_id: i32) -> Result<bool, String> {
+    let grant = mongo.tool_grants()
+        .find_one(doc! {"toolId": tool_id})
+        .await
+        .map_err(|e| format!("查询工具授权失败: {}", e))?;
+    Ok(grant.map_or(true, |grant| grant.allows(user)))
+}
+
+// 计入统计的打开工具事件的筛选条件，与上传事件时的校验一致：
+// 用户仍然存在，且工具没有授权记录或授权允许该用户使用（按当前的授权记录判断）
+pub async fn counted_opens_filter(mongo: &MongoManager) -> Result<Document, String> {
+    let grants: Vec<ToolGrant> = mongo.tool_grants()
+        .find(doc! {})
+        .await
+        .map_err(|e| format!("查询工具授权失败: {}", e))?
+        .try_collect()
+        .await
+        .map_err(|e| format!("读取工具授权失败: {}", e))?;
+    let users: Vec<User> = mongo.users()
+        .find(doc! {})
+        .await
+        .map_err(|e| format!("查询用户失败: {}", e))?
+        .try_collect()
+        .await
+        .map_err(|e| format!("读取用户失败: {}", e))?;
+
+    let mut resolved = Vec::new();
+    for user in users {
+        if let Some(id) = user.id {
+            resolved.push((id, rbac::resolve_user(mongo, user).await?));
+        }
+    }
+
+    let restricted: Vec<i32> = grants.iter().map(|grant| grant.tool_id).collect();
+    let mut allowed = vec![doc! {"toolId": {"$nin": restricted}}];
+    for grant in &grants {
+        let user_ids: Vec<ObjectId> = resolved
+            .iter()
+            .filter(|(_, user)| grant.allows(user))
+            .map(|(id, _)| *id)
+            .collect();
+        allowed.push(doc! {"toolId": grant.tool_id, "userId": {"$in": user_ids}});
+    }
+    let user_ids: Vec<ObjectId> = resolved.iter().map(|(id, _)| *id).collect();
+    Ok(doc! {"userId": {"$in": user_ids}, "$or": allowed})
+}
+
+// 校验用户是否可以打开指定URL（URL属于受限工具时才拦截）
+pub async fn ensure_url_allowed(
+    mongo: &MongoManager,
+    user: &UserResponse,
+    url: &str,
+) -> Result<(), String> {
+    let cursor = mongo.tool_grants()
+        .find(doc! {})
+        .await
+        .map_err(|e| format!("查询工具授权失败: {}", e))?;
+    let grants: Vec<ToolGrant> = cursor.try_collect().await.map_err(|e| format!("读取工具授权失败: {}", e))?;
+
+    // 授权记录未填写地址时，使用工具目录中的地址匹配
+    let catalog_urls: HashMap<i32, String> = tools::load_tools(mongo, false)
+        .await?
+        .into_iter()
+        .map(|tool| (tool.tool_id, tool.url))
+        .collect();
+
+    let denied = grants.iter().find(|grant| {
+        let matches = grant.matches_url(url)
+            || catalog_urls
+                .get(&grant.tool_id)
+                .map(|tool_url| url_matches(tool_url, url))
+                .unwrap_or(false);
+        matches && !grant.allows(user)
+    });
+
+    if let Some(grant) = denied {
+        log::warn!("❌ 工具访问拒绝 - 用户 {} 无权打开 {}", user.username, url);
+        return Err(AuthError::ToolDenied { tool_id: grant.tool_id }.into());
+    }
+    Ok(())
+}
+
+// 过滤出用户可以使用的工具ID
+pub async fn filter_accessible(
+    mongo: &MongoManager,
+    user: &UserResponse,
+    tool_ids: Vec<i32>,
+) -> Result<Vec<i32>, String> {
+    let cursor = mongo.tool_grants()
+        .find(doc! {"toolId": {"$in": &tool_ids}})
+        .await
+        .map_err(|e| format!("查询工具授权失败: {}", e))?;
+    let grants: Vec<ToolGrant> = cursor.try_collect().await.map_err(|e| format!("读取工具授权失败: {}", e))?;
+
+    Ok(tool_ids
+        .into_iter()
+        .filter(|tool_id| {
+            grants
+                .iter()
+                .find(|g| g.tool_id == *tool_id)
+                .map(|g| g.allows(user))
+                .unwrap_or(true)
+        })
+        .collect())
+}
+
+// 获取当前用户可以使用的工具ID，未指定时使用工具目录中所有已启用的工具
+#[tauri::command]
+pub async fn get_accessible_tools(
+    tool_ids: Option<Vec<i32>>,
+    state: tauri::State<'_, AppState>,
+) -> Result<Vec<i32>, String> {
+    let current_user = current_caller(&state).await?;
+
+    let mongo = state.mongo.read().await;
+    let tool_ids = match tool_ids {
+        Some(ids) => ids,
+        None => tools::load_tools(&mongo, true)
+            .await?
+            .into_iter()
+            .map(|tool| tool.tool_id)
+            .collect(),
+    };
+
+    filter_accessible(&mongo, &current_user, tool_ids).await
+}
+
+// 列出所有工具授权 - 管理员功能
+#[tauri::command]
+pub async fn list_tool_grants(
+    state: tauri::State<'_, AppState>,
+) -> Result<Vec<ToolGrantResponse>, String> {
+    require(&state, Permission::ManageTools).await?;
+
+    let mongo = state.mongo.read().await;
+    let cursor = mongo.tool_grants()
+        .find(doc! {})
+        .sort(doc! {"toolId": 1})
+        .await
+        .map_err(|e| format!("查询工具授权失败: {}", e))?;
+    let grants: Vec<ToolGrant> = cursor.try_collect().await.map_err(|e| format!("读取工具授权失败: {}", e))?;
+
+    Ok(grants.into_iter().map(ToolGrantResponse::from).collect())
+}
+
+// 设置工具授权（覆盖原有授权）- 管理员功能
+#[tauri::command]
+pub async fn set_tool_grant(
+    tool_id: i32,
+    tool_url: Option<String>,
+    roles: Vec<String>,
+    departments: Vec<String>,
+    user_ids: Vec<String>,
+    state: tauri::State<'_, AppState>,
+) -> Result<ToolGrantResponse, String> {
+    let current_user = require(&state, Permission::ManageTools).await?;
+    log::info!("📝 工具授权操作 - 设置工具 {} 的授权 (操作员: {})", tool_id, current_user.username);
+
+    let user_object_ids = user_ids
+        .iter()
+        .map(|id| ObjectId::parse_str(id).map_err(|e| format!("无效的用户ID {}: {}", id, e)))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let tool_url = tool_url
+        .map(|u| u.trim().to_string())
+        .filter(|u| !u.is_empty());
+
+    let grant = ToolGrant {
+        id: None,
+        tool_id,
+        tool_url,
+        roles: roles.into_iter().map(|r| r.trim().to_string()).filter(|r| !r.is_empty()).collect(),
+        departments: departments.into_iter().map(|d| d.trim().to_string()).filter(|d| !d.is_empty()).collect(),
+        user_ids: user_object_ids,
+        updated_at: DateTime::now(),
+        updated_by: current_user.username.clone(),
+    };
+
+    let mongo = state.mongo.read().await;
+    mongo.tool_grants()
+        .replace_one(doc! {"toolId": tool_id}, grant.clone())
+        .upsert(true)
+        .await
+        .map_err(|e| format!("保存工具授权失败: {}", e))?;
+
+    log::info!("✅ 工具 {} 授权已更新: 角色={:?}, 部门={:?}, 用户数={}",
+               tool_id, grant.roles, grant.departments, grant.user_ids.len());
+    Ok(ToolGrantResponse::from(grant))
+}
+
+// 删除工具授权，工具恢复为对所有用户开放 - 管理员功能
+#[tauri::command]
+pub async fn remove_tool_grant(
+    tool_id: i32,
+    state: tauri::State<'_, AppState>,
+) -> Result<(), String> {
+    let current_user = require(&state, Permission::ManageTools).await?;
+    log::info!("📝 工具授权操作 - 移除工具 {} 的授权 (操作员: {})", tool_id, current_user.username);
+
+    let mongo = state.mongo.read().await;
+    let result = mongo.tool_grants()
+        .delete_one(doc! {"toolId": tool_id})
+        .await
+        .map_err(|e| format!("删除工具授权失败: {}", e))?;
+
+    if result.deleted_count == 0 {
+        return Err("该工具没有授权记录".to_string());
+    }
+    Ok(())
+}
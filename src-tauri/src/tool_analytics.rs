@@ -0,0 +1,360 @@
+use mongodb::bson::{doc, Bson, Document};
+use serde::{Deserialize, Serialize};
+
+use crate::auth::{AppState, MongoManager};
+use crate::guard::{require_any, Permission};
+use crate::metrics::{self, DateRange, Granularity};
+use crate::tools;
+
+const TOP_USERS: i64 = 10;
+
+// 单个工具的使用分析
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ToolAnalytics {
+    #[serde(rename = "toolId")]
+    pub tool_id: i32,
+    #[serde(rename = "toolName")]
+    pub tool_name: String,
+    pub granularity: Granularity,
+    #[serde(rename = "startDate")]
+    pub start_date: String,
+    #[serde(rename = "endDate")]
+    pub end_date: String,
+    pub department: Option<String>,
+    // 统计范围内的合计
+    #[serde(rename = "totalClicks")]
+    pub total_clicks: i64,
+    #[serde(rename = "totalUsageTime")]
+    pub total_usage_time: i64,
+    #[serde(rename = "uniqueUsers")]
+    pub unique_users: i64,
+    pub trend: Vec<ToolTrendPoint>,
+    #[serde(rename = "topUsers")]
+    pub top_users: Vec<ToolUserUsage>,
+    pub departments: Vec<ToolDepartmentUsage>,
+    #[serde(rename = "openDuration")]
+    pub open_duration: OpenDurationStats,
+    // 最早和最近一次打开该工具的时间（不限统计范围），从未打开过时为空
+    #[serde(rename = "firstUsedAt")]
+    pub first_used_at: Option<String>,
+    #[serde(rename = "lastUsedAt")]
+    pub last_used_at: Option<String>,
+}
+
+// 每个统计区间的使用数据（date 为区间第一天）
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ToolTrendPoint {
+    pub date: String,
+    pub clicks: i64,
+    #[serde(rename = "usageTime")]
+    pub usage_time: i64,
+    #[serde(rename = "uniqueUsers")]
+    pub unique_users: i64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ToolUserUsage {
+    #[serde(rename = "userId")]
+    pub user_id: String,
+    pub username: String,
+    pub department: Option<String>,
+    pub clicks: i64,
+    #[serde(rename = "usageTime")]
+    pub usage_time: i64,
+}
+
+// 按用户所在部门汇总，未分配部门的用户 department 为空
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ToolDepartmentUsage {
+    pub department: Option<String>,
+    pub clicks: i64,
+    #[serde(rename = "usageTime")]
+    pub usage_time: i64,
+    pub users: i64,
+}
+
+// 每次打开的时长（秒），来自关闭工具事件中前端记录的打开时长。
+// 大多数工具在主窗口的弹窗中打开，Rust 端只能看到主窗口的焦点，无法得到每次打开的时长，
+// 因此这里的数据由前端上报，未扣除切换到其他窗口或空闲的时间，不能与前台使用时长直接比较
+#[derive(Debug, Serialize, Deserialize)]
+pub struct OpenDurationStats {
+    // 固定为 true，提示界面这些数据来自前端上报
+    #[serde(rename = "clientReported")]
+    pub client_reported: bool,
+    pub samples: u64,
+    #[serde(rename = "medianSecs")]
+    pub median_secs: Option<i64>,
+    #[serde(rename = "p90Secs")]
+    pub p90_secs: Option<i64>,
+}
+
+// 最近排名法：第 percentile 百分位是升序排列后的第 ceil(samples * percentile / 100) 个样本（从1开始）
+fn nearest_rank(samples: u64, percentile: u64) -> u64 {
+    (samples * percentile).div_ceil(100).max(1)
+}
+
+// 按最近排名法取第 percentile 百分位的打开时长
+async fn duration_percentile(
+    mongo: &MongoManager,
+    filter: &Document,
+    samples: u64,
+    percentile: u64,
+) -> Result<Option<i64>, String> {
+    if samples == 0 {
+        return Ok(None);
+    }
+    let rank = nearest_rank(samples, percentile);
+    let event = mongo.activity_events()
+        .clone_with_type::<Document>()
+        .find_one(filter.clone())
+        .sort(doc! {"durationSecs": 1})
+        .skip(rank - 1)
+        .projection(doc! {"durationSecs": 1})
+        .await
+        .map_err(|e| format!("查询打开时长失败: {}", e))?;
+    Ok(event.map(|event| metrics::get_number(&event, "durationSecs")))
+}
+
+// 最早或最近一次打开工具的时间
+async fn opened_at(mongo: &MongoManager, filter: &Document, direction: i32) -> Result<Option<String>, String> {
+    let event = mongo.activity_events()
+        .clone_with_type::<Document>()
+        .find_one(filter.clone())
+        .sort(doc! {"timestamp": direction})
+        .projection(doc! {"timestamp": 1})
+        .await
+        .map_err(|e| format!("查询工具使用时间失败: {}", e))?;
+    Ok(event
+        .and_then(|event| event.get_datetime("timestamp").ok().copied())
+        .map(|at| at.try_to_rfc3339_string().unwrap_or_default()))
+}
+
+// 单个工具的使用分析：按区间的点击、时长和使用人数，使用最多的用户，
+// 部门分布，每次打开时长（前端上报）的中位数和90分位，以及最早和最近一次使用时间。
+// 部门负责人只能查看本部门用户的使用数据
+#[tauri::command]
+pub async fn get_tool_analytics(
+    tool_id: i32,
+    start_date: Option<String>,
+    end_date: Option<String>,
+    granularity: Option<String>,
+    department: Option<String>,
+    state: tauri::State<'_, AppState>,
+) -> Result<ToolAnalytics, String> {
+    let current_user = require_any(&state, &[
+        Permission::ViewAnalytics,
+        Permission::ViewDepartmentAnalytics,
+    ]).await?;
+    if tool_id <= 0 {
+        return Err(format!("无效的工具ID: {}", tool_id));
+    }
+    let tz = state.config.analytics.tz();
+    let range = DateRange::parse(start_date, end_date, granularity, tz)?;
+    let user_filter = current_user.analytics_department_filter(department.clone())?;
+
+    let mongo = state.mongo.read().await;
+    let tool_name = tools::tool_names(&mongo)
+        .await?
+        .remove(&tool_id)
+        .unwrap_or_else(|| format!("工具{}", tool_id));
+
+    // 按部门查看时只统计该部门的用户
+    let mut stat_filter = doc! {"toolId": tool_id};
+    let mut event_filter = doc! {"toolId": tool_id};
+    let user_ids = match user_filter {
+        Some(filter) if filter.is_empty() => None,
+        Some(filter) => Some(
+            mongo.users()
+                .distinct("_id", filter)
+                .await
+                .map_err(|e| format!("查询部门用户失败: {}", e))?,
+        ),
+        None => Some(Vec::new()),
+    };
+    if let Some(user_ids) = user_ids {
+        stat_filter.insert("userId", doc! {"$in": user_ids.clone()});
+        event_filter.insert("userId", doc! {"$in": user_ids});
+    }
+    let mut range_filter = stat_filter.clone();
+    range_filter.insert("date", doc! {"$gte": range.lower_bound(), "$lt": range.upper_bound()});
+
+    // 各区间的点击、时长和使用人数
+    let buckets = metrics::bucket_totals(
+        mongo.daily_stats(),
+        "date",
+        stat_filter,
+        &range,
+        doc! {
+            "clicks": {"$sum": "$clicks"},
+            "usageSecs": {"$sum": "$usageSecs"},
+            "users": {"$addToSet": "$userId"}
+        },
+    )
+    .await?;
+    let empty = Document::new();
+    let trend: Vec<ToolTrendPoint> = range
+        .buckets()
+        .into_iter()
+        .map(|bucket| {
+            let key = metrics::bucket_key(bucket);
+            let doc = buckets.get(&key).unwrap_or(&empty);
+            ToolTrendPoint {
+                clicks: metrics::get_number(doc, "clicks"),
+                usage_time: metrics::get_number(doc, "usageSecs"),
+                unique_users: doc.get_array("users").map(|users| users.len() as i64).unwrap_or(0),
+                date: key,
+            }
+        })
+        .collect();
+
+    // 先按用户汇总，再分别取使用最多的用户和按部门汇总
+    let pipeline = vec![
+        doc! {"$match": range_filter},
+        doc! {
+            "$group": {
+                "_id": "$userId",
+                "clicks": {"$sum": "$clicks"},
+                "usageSecs": {"$sum": "$usageSecs"}
+            }
+        },
+        doc! {
+            "$lookup": {
+                "from": "users",
+                "localField": "_id",
+                "foreignField": "_id",
+                "pipeline": [{"$project": {"_id": 0, "username": 1, "department": 1}}],
+                "as": "user"
+            }
+        },
+        doc! {"$set": {"user": {"$arrayElemAt": ["$user", 0]}}},
+        doc! {
+            "$facet": {
+                "topUsers": [
+                    {"$sort": {"clicks": -1, "usageSecs": -1, "_id": 1}},
+                    {"$limit": TOP_USERS}
+                ],
+                "departments": [
+                    {
+                        "$group": {
+                            "_id": {"$ifNull": ["$user.department", null]},
+                            "clicks": {"$sum": "$clicks"},
+                            "usageSecs": {"$sum": "$usageSecs"},
+                            "users": {"$sum": 1}
+                        }
+                    },
+                    {"$sort": {"clicks": -1, "usageSecs": -1}}
+                ],
+                "totals": [
+                    {
+                        "$group": {
+                            "_id": null,
+                            "clicks": {"$sum": "$clicks"},
+                            "usageSecs": {"$sum": "$usageSecs"},
+                            "users": {"$sum": 1}
+                        }
+                    }
+                ]
+            }
+        },
+    ];
+    let mut cursor = mongo.daily_stats()
+        .aggregate(pipeline)
+        .await
+        .map_err(|e| format!("工具使用分析聚合失败: {}", e))?;
+    let facets = if cursor.advance().await.map_err(|e| format!("获取聚合结果失败: {}", e))? {
+        cursor.deserialize_current().map_err(|e| format!("反序列化聚合结果失败: {}", e))?
+    } else {
+        Document::new()
+    };
+    let facet = |key: &str| -> Vec<Document> {
+        facets
+            .get_array(key)
+            .map(|values| values.iter().filter_map(Bson::as_document).cloned().collect())
+            .unwrap_or_default()
+    };
+
+    let top_users = facet("topUsers")
+        .iter()
+        .map(|doc| {
+            let user = doc.get_document("user").ok();
+            ToolUserUsage {
+                user_id: doc.get_object_id("_id").map(|id| id.to_hex()).unwrap_or_default(),
+                username: user.and_then(|user| user.get_str("username").ok()).unwrap_or("已删除用户").to_string(),
+                department: user.and_then(|user| user.get_str("department").ok()).map(str::to_string),
+                clicks: metrics::get_number(doc, "clicks"),
+                usage_time: metrics::get_number(doc, "usageSecs"),
+            }
+        })
+        .collect();
+    let departments = facet("departments")
+        .iter()
+        .map(|doc| ToolDepartmentUsage {
+            department: doc.get_str("_id").ok().map(str::to_string),
+            clicks: metrics::get_number(doc, "clicks"),
+            usage_time: metrics::get_number(doc, "usageSecs"),
+            users: metrics::get_number(doc, "users"),
+        })
+        .collect();
+    let totals = facet("totals").into_iter().next().unwrap_or_default();
+
+    // 每次打开的时长：统计范围内带有打开时长的关闭事件
+    let mut duration_filter = event_filter.clone();
+    duration_filter.insert("type", "tool_closed");
+    duration_filter.insert("timestamp", doc! {"$gte": range.lower_bound(), "$lt": range.upper_bound()});
+    duration_filter.insert("durationSecs", doc! {"$gt": 0});
+    let samples = mongo.activity_events()
+        .count_documents(duration_filter.clone())
+        .await
+        .map_err(|e| format!("统计打开时长失败: {}", e))?;
+    let open_duration = OpenDurationStats {
+        client_reported: true,
+        samples,
+        median_secs: duration_percentile(&mongo, &duration_filter, samples, 50).await?,
+        p90_secs: duration_percentile(&mongo, &duration_filter, samples, 90).await?,
+    };
+
+    let mut opened_filter = event_filter;
+    opened_filter.insert("type", "tool_opened");
+    let first_used_at = opened_at(&mongo, &opened_filter, 1).await?;
+    let last_used_at = opened_at(&mongo, &opened_filter, -1).await?;
+
+    log::info!(
+        "🔧 {} 查询工具 {} 的使用分析: {} ~ {}",
+        current_user.username,
+        tool_name,
+        range.start,
+        range.end
+    );
+    Ok(ToolAnalytics {
+        tool_id,
+        tool_name,
+        granularity: range.granularity,
+        start_date: range.start.format("%Y-%m-%d").to_string(),
+        end_date: range.end.format("%Y-%m-%d").to_string(),
+        department,
+        total_clicks: metrics::get_number(&totals, "clicks"),
+        total_usage_time: metrics::get_number(&totals, "usageSecs"),
+        unique_users: metrics::get_number(&totals, "users"),
+        trend,
+        top_users,
+        departments,
+        open_duration,
+        first_used_at,
+        last_used_at,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn nearest_rank_picks_the_expected_sample() {
+        let ranks = |samples| (nearest_rank(samples, 50), nearest_rank(samples, 90));
+        assert_eq!(ranks(1), (1, 1));
+        assert_eq!(ranks(2), (1, 2));
+        assert_eq!(ranks(10), (5, 9));
+        assert_eq!(ranks(11), (6, 10));
+        assert_eq!(nearest_rank(10, 100), 10);
+    }
+}
@@ -0,0 +1,560 @@
+use futures::TryStreamExt;
+use mongodb::{
+    bson::{self, doc, oid::ObjectId, DateTime, Document},
+    options::IndexOptions,
+    IndexModel,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+use crate::auth::{AppState, MongoManager};
+use crate::guard::{current_caller, require, Permission};
+
+// 初始工具目录，首次连接到空数据库时导入
+const SEED_TOOLS: &str = include_str!("../seed/tools.json");
+
+// 工具类型
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ToolType {
+    Web,
+    Desktop,
+    Integrated,
+}
+
+// 工具目录中的工具
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Tool {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    pub id: Option<ObjectId>,
+    #[serde(rename = "toolId")]
+    pub tool_id: i32,
+    pub name: String,
+    #[serde(default)]
+    pub description: String,
+    pub category: String,
+    pub url: String,
+    #[serde(rename = "toolType")]
+    pub tool_type: ToolType,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    #[serde(default)]
+    pub icon: Option<String>,
+    #[serde(default)]
+    pub color: Option<String>,
+    #[serde(default)]
+    pub featured: bool,
+    pub enabled: bool,
+    #[serde(rename = "sortOrder")]
+    pub sort_order: i32,
+    // 所有用户在工具窗口前台的累计使用时长（秒）
+    #[serde(rename = "totalUsageTime", default)]
+    pub total_usage_time: i64,
+    #[serde(rename = "createdAt")]
+    pub created_at: DateTime,
+    #[serde(rename = "updatedAt")]
+    pub updated_at: DateTime,
+}
+
+// 工具响应结构
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ToolResponse {
+    pub id: i32,
+    pub name: String,
+    pub description: String,
+    pub category: String,
+    pub url: String,
+    #[serde(rename = "toolType")]
+    pub tool_type: ToolType,
+    pub tags: Vec<String>,
+    pub icon: Option<String>,
+    pub color: Option<String>,
+    pub featured: bool,
+    pub enabled: bool,
+    #[serde(rename = "sortOrder")]
+    pub sort_order: i32,
+    #[serde(rename = "totalUsageTime")]
+    pub total_usage_time: i64,
+    #[serde(rename = "updatedAt")]
+    pub updated_at: String,
+}
+
+impl From<Tool> for ToolResponse {
+    fn from(tool: Tool) -> Self {
+        ToolResponse {
+            id: tool.tool_id,
+            name: tool.name,
+            description: tool.description,
+            category: tool.category,
+            url: tool.url,
+            tool_type: tool.tool_type,
+            tags: tool.tags,
+            icon: tool.icon,
+            color: tool.color,
+            featured: tool.featured,
+            enabled: tool.enabled,
+            sort_order: tool.sort_order,
+            total_usage_time: tool.total_usage_time,
+            updated_at: tool.updated_at.try_to_rfc3339_string().unwrap_or_default(),
+        }
+    }
+}
+
+// 创建/导入工具的输入
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ToolInput {
+    pub id: Option<i32>,
+    pub name: String,
+    #[serde(default)]
+    pub description: String,
+    pub category: String,
+    pub url: String,
+    #[serde(rename = "toolType")]
+    pub tool_type: ToolType,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    pub icon: Option<String>,
+    pub color: Option<String>,
+    #[serde(default)]
+    pub featured: bool,
+    pub enabled: Option<bool>,
+}
+
+// 更新工具的输入（仅更新提供的字段）
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct ToolUpdate {
+    pub name: Option<String>,
+    pub description: Option<String>,
+    pub category: Option<String>,
+    pub url: Option<String>,
+    #[serde(rename = "toolType")]
+    pub tool_type: Option<ToolType>,
+    pub tags: Option<Vec<String>>,
+    pub icon: Option<String>,
+    pub color: Option<String>,
+    pub featured: Option<bool>,
+}
+
+pub async fn ensure_indexes(mongo: &MongoManager) -> Result<(), String> {
+    mongo.tools()
+        .create_index(
+            IndexModel::builder()
+                .keys(doc! {"toolId": 1})
+                .options(IndexOptions::builder().unique(true).build())
+                .build(),
+        )
+        .await
+        .map_err(|e| format!("创建工具索引失败: {}", e))?;
+    Ok(())
+}
+
+// 按工具ID覆盖写入工具目录，返回新增和更新的数量
+async fn import(mongo: &MongoManager, tools: Vec<ToolInput>) -> Result<(u32, u32), String> {
+    let now = DateTime::now();
+    let mut inserted = 0;
+    let mut updated = 0;
+
+    for (index, tool) in tools.into_iter().enumerate() {
+        let tool_id = tool.id.unwrap_or_default();
+        let result = mongo.tools()
+            .update_one(
+                doc! {"toolId": tool_id},
+                doc! {
+                    "$set": {
+                        "name": tool.name.trim(),
+                        "description": tool.description,
+                        "category": tool.category.trim(),
+                        "url": tool.url.trim(),
+                        "toolType": bson::to_bson(&tool.tool_type).map_err(|e| format!("序列化工具类型失败: {}", e))?,
+                        "tags": tool.tags,
+                        "icon": tool.icon,
+                        "color": tool.color,
+                        "featured": tool.featured,
+                        "updatedAt": now
+                    },
+                    "$setOnInsert": {
+                        "enabled": tool.enabled.unwrap_or(true),
+                        "sortOrder": index as i32,
+                        "createdAt": now
+                    }
+                },
+            )
+            .upsert(true)
+            .await
+            .map_err(|e| format!("导入工具 {} 失败: {}", tool_id, e))?;
+
+        if result.upserted_id.is_some() {
+            inserted += 1;
+        } else {
+            updated += 1;
+        }
+    }
+    Ok((inserted, updated))
+}
+
+// 工具目录为空时（新数据库或升级前的安装）导入内置的初始工具目录
+pub async fn seed(mongo: &MongoManager) -> Result<(), String> {
+    let catalog_size = mongo.tools()
+        .count_documents(doc! {})
+        .await
+        .map_err(|e| format!("查询工具目录失败: {}", e))?;
+    if catalog_size > 0 {
+        return Ok(());
+    }
+
+    let tools: Vec<ToolInput> = serde_json::from_str(SEED_TOOLS).map_err(|e| format!("解析初始工具目录失败: {}", e))?;
+    let (inserted, _) = import(mongo, tools).await?;
+    log::info!("📦 工具目录为空，已导入 {} 个初始工具", inserted);
+    Ok(())
+}
+
+fn validate_input(name: &str, category: &str, url: &str) -> Result<(), String> {
+    if name.trim().is_empty() {
+        return Err("工具名称不能为空".to_string());
+    }
+    if category.trim().is_empty() {
+        return Err("工具分类不能为空".to_string());
+    }
+    validate_url(url)
+}
+
+fn validate_url(url: &str) -> Result<(), String> {
+    let url = url.trim();
+    if !(url.starts_with("https://") || url.starts_with("http://")) {
+        return Err(format!("无效的工具地址: {}", url));
+    }
+    Ok(())
+}
+
+// 按排序返回工具目录
+pub async fn load_tools(mongo: &MongoManager, enabled_only: bool) -> Result<Vec<Tool>, String> {
+    let filter = if enabled_only { doc! {"enabled": true} } else { doc! {} };
+    let cursor = mongo.tools()
+        .find(filter)
+        .sort(doc! {"sortOrder": 1, "toolId": 1})
+        .await
+        .map_err(|e| format!("查询工具目录失败: {}", e))?;
+    cursor.try_collect().await.map_err(|e| format!("读取工具目录失败: {}", e))
+}
+
+// 工具ID到规范名称的映射，用于分析数据展示
+pub async fn tool_names(mongo: &MongoManager) -> Result<HashMap<i32, String>, String> {
+    Ok(load_tools(mongo, false)
+        .await?
+        .into_iter()
+        .map(|tool| (tool.tool_id, tool.name))
+        .collect())
+}
+
+// 获取工具的规范名称：目录尚未导入时沿用客户端传入的名称，
+// 目录存在时拒绝未知或已停用的工具
+pub async fn canonical_tool_name(
+    mongo: &MongoManager,
+    tool_id: i32,
+    fallback: Option<String>,
+) -> Result<String, String> {
+    let tool = mongo.tools()
+        .find_one(doc! {"toolId": tool_id})
+        .await
+        .map_err(|e| format!("查询工具失败: {}", e))?;
+
+    match tool {
+        Some(tool) if tool.enabled => Ok(tool.name),
+        Some(tool) => Err(format!("工具已停用: {}", tool.name)),
+        None => {
+            let catalog_size = mongo.tools()
+                .count_documents(doc! {})
+                .await
+                .map_err(|e| format!("查询工具目录失败: {}", e))?;
+            if catalog_size > 0 {
+                Err(format!("工具不存在: {}", tool_id))
+            } else {
+                Ok(fallback.unwrap_or_else(|| format!("工具{}", tool_id)))
+            }
+        }
+    }
+}
+
+// 加载工具目录 - 前端启动时调用，只返回当前用户可用的已启用工具
+#[tauri::command]
+pub async fn list_tools(
+    state: tauri::State<'_, AppState>,
+) -> Result<Vec<ToolResponse>, String> {
+    let current_user = current_caller(&state).await?;
+
+    let mongo = state.mongo.read().await;
+    let tools = load_tools(&mongo, true).await?;
+    let tool_ids: Vec<i32> = tools.iter().map(|t| t.tool_id).collect();
+    let accessible = crate::tool_access::filter_accessible(&mongo, &current_user, tool_ids).await?;
+
+    Ok(tools
+        .into_iter()
+        .filter(|tool| accessible.contains(&tool.tool_id))
+        .map(ToolResponse::from)
+        .collect())
+}
+
+// 列出全部工具（包括已停用的）- 管理员功能
+#[tauri::command]
+pub async fn list_all_tools(
+    state: tauri::State<'_, AppState>,
+) -> Result<Vec<ToolResponse>, String> {
+    require(&state, Permission::ManageTools).await?;
+
+    let mongo = state.mongo.read().await;
+    Ok(load_tools(&mongo, false)
+        .await?
+        .into_iter()
+        .map(ToolResponse::from)
+        .collect())
+}
+
+// 创建工具 - 管理员功能
+#[tauri::command]
+pub async fn create_tool(
+    tool: ToolInput,
+    state: tauri::State<'_, AppState>,
+) -> Result<ToolResponse, String> {
+    let current_user = require(&state, Permission::ManageTools).await?;
+    log::info!("📝 工具管理操作 - 创建工具: {} (操作员: {})", tool.name, current_user.username);
+    validate_input(&tool.name, &tool.category, &tool.url)?;
+
+    let mongo = state.mongo.read().await;
+
+    // 获取当前最大的工具ID和排序值
+    let last = mongo.tools()
+        .find_one(doc! {})
+        .sort(doc! {"toolId": -1})
+        .await
+        .map_err(|e| format!("查询工具目录失败: {}", e))?;
+    let max_sort = mongo.tools()
+        .find_one(doc! {})
+        .sort(doc! {"sortOrder": -1})
+        .await
+        .map_err(|e| format!("查询工具目录失败: {}", e))?
+        .map(|t| t.sort_order)
+        .unwrap_or(-1);
+
+    let tool_id = match tool.id {
+        Some(id) => {
+            if mongo.tools().find_one(doc! {"toolId": id}).await
+                .map_err(|e| format!("检查工具ID失败: {}", e))?
+                .is_some()
+            {
+                return Err(format!("工具ID已存在: {}", id));
+            }
+            id
+        }
+        None => last.map(|t| t.tool_id + 1).unwrap_or(1),
+    };
+
+    let now = DateTime::now();
+    let mut new_tool = Tool {
+        id: None,
+        tool_id,
+        name: tool.name.trim().to_string(),
+        description: tool.description,
+        category: tool.category.trim().to_string(),
+        url: tool.url.trim().to_string(),
+        tool_type: tool.tool_type,
+        tags: tool.tags,
+        icon: tool.icon,
+        color: tool.color,
+        featured: tool.featured,
+        enabled: tool.enabled.unwrap_or(true),
+        sort_order: max_sort + 1,
+        total_usage_time: 0,
+        created_at: now,
+        updated_at: now,
+    };
+
+    let result = mongo.tools()
+        .insert_one(new_tool.clone())
+        .await
+        .map_err(|e| format!("创建工具失败: {}", e))?;
+    new_tool.id = result.inserted_id.as_object_id();
+
+    log::info!("✅ 工具创建成功: {} (ID: {})", new_tool.name, new_tool.tool_id);
+    Ok(ToolResponse::from(new_tool))
+}
+
+// 批量导入工具（按工具ID覆盖，保留原有排序和启用状态）- 管理员功能
+#[tauri::command]
+pub async fn import_tools(
+    tools: Vec<ToolInput>,
+    state: tauri::State<'_, AppState>,
+) -> Result<String, String> {
+    let current_user = require(&state, Permission::ManageTools).await?;
+    log::info!("📝 工具管理操作 - 导入 {} 个工具 (操作员: {})", tools.len(), current_user.username);
+
+    for tool in &tools {
+        if tool.id.is_none() {
+            return Err(format!("导入的工具缺少ID: {}", tool.name));
+        }
+        validate_input(&tool.name, &tool.category, &tool.url)?;
+    }
+
+    let mongo = state.mongo.read().await;
+    let (inserted, updated) = import(&mongo, tools).await?;
+
+    log::info!("✅ 工具导入完成: 新增 {} 个, 更新 {} 个", inserted, updated);
+    Ok(format!("✅ 工具导入完成！\n新增: {} 个\n更新: {} 个", inserted, updated))
+}
+
+// 更新工具信息 - 管理员功能
+#[tauri::command]
+pub async fn update_tool(
+    tool_id: i32,
+    update: ToolUpdate,
+    state: tauri::State<'_, AppState>,
+) -> Result<ToolResponse, String> {
+    let current_user = require(&state, Permission::ManageTools).await?;
+    log::info!("📝 工具管理操作 - 更新工具: {} (操作员: {})", tool_id, current_user.username);
+
+    let mut update_doc = Document::new();
+    if let Some(name) = update.name {
+        if name.trim().is_empty() {
+            return Err("工具名称不能为空".to_string());
+        }
+        update_doc.insert("name", name.trim());
+    }
+    if let Some(description) = update.description {
+        update_doc.insert("description", description);
+    }
+    if let Some(category) = update.category {
+        if category.trim().is_empty() {
+            return Err("工具分类不能为空".to_string());
+        }
+        update_doc.insert("category", category.trim());
+    }
+    if let Some(url) = update.url {
+        validate_url(&url)?;
+        update_doc.insert("url", url.trim());
+    }
+    if let Some(tool_type) = update.tool_type {
+        update_doc.insert(
+            "toolType",
+            bson::to_bson(&tool_type).map_err(|e| format!("序列化工具类型失败: {}", e))?,
+        );
+    }
+    if let Some(tags) = update.tags {
+        update_doc.insert("tags", tags);
+    }
+    if let Some(icon) = update.icon {
+        update_doc.insert("icon", icon);
+    }
+    if let Some(color) = update.color {
+        update_doc.insert("color", color);
+    }
+    if let Some(featured) = update.featured {
+        update_doc.insert("featured", featured);
+    }
+
+    if update_doc.is_empty() {
+        return Err("没有提供任何更新字段".to_string());
+    }
+    update_doc.insert("updatedAt", DateTime::now());
+
+    let mongo = state.mongo.read().await;
+    let result = mongo.tools()
+        .update_one(doc! {"toolId": tool_id}, doc! {"$set": update_doc})
+        .await
+        .map_err(|e| format!("更新工具失败: {}", e))?;
+
+    if result.matched_count == 0 {
+        return Err("工具不存在".to_string());
+    }
+
+    let tool = mongo.tools()
+        .find_one(doc! {"toolId": tool_id})
+        .await
+        .map_err(|e| format!("获取更新后工具信息失败: {}", e))?
+        .ok_or("工具不存在")?;
+
+    log::info!("✅ 工具更新成功: {}", tool.name);
+    Ok(ToolResponse::from(tool))
+}
+
+// 启用或停用工具 - 管理员功能
+#[tauri::command]
+pub async fn set_tool_enabled(
+    tool_id: i32,
+    enabled: bool,
+    state: tauri::State<'_, AppState>,
+) -> Result<ToolResponse, String> {
+    let current_user = require(&state, Permission::ManageTools).await?;
+    log::info!("📝 工具管理操作 - {}工具: {} (操作员: {})",
+               if enabled { "启用" } else { "停用" }, tool_id, current_user.username);
+
+    let mongo = state.mongo.read().await;
+    let result = mongo.tools()
+        .update_one(
+            doc! {"toolId": tool_id},
+            doc! {"$set": {"enabled": enabled, "updatedAt": DateTime::now()}}
+        )
+        .await
+        .map_err(|e| format!("更新工具状态失败: {}", e))?;
+
+    if result.matched_count == 0 {
+        return Err("工具不存在".to_string());
+    }
+
+    let tool = mongo.tools()
+        .find_one(doc! {"toolId": tool_id})
+        .await
+        .map_err(|e| format!("获取工具信息失败: {}", e))?
+        .ok_or("工具不存在")?;
+
+    Ok(ToolResponse::from(tool))
+}
+
+// 调整工具顺序：按传入的工具ID顺序重新编号 - 管理员功能
+#[tauri::command]
+pub async fn reorder_tools(
+    tool_ids: Vec<i32>,
+    state: tauri::State<'_, AppState>,
+) -> Result<(), String> {
+    let current_user = require(&state, Permission::ManageTools).await?;
+    log::info!("📝 工具管理操作 - 调整 {} 个工具的顺序 (操作员: {})", tool_ids.len(), current_user.username);
+
+    let mut seen = std::collections::HashSet::new();
+    if let Some(duplicate) = tool_ids.iter().find(|id| !seen.insert(**id)) {
+        return Err(format!("工具ID重复: {}", duplicate));
+    }
+
+    let mongo = state.mongo.read().await;
+    let now = DateTime::now();
+    for (index, tool_id) in tool_ids.iter().enumerate() {
+        let result = mongo.tools()
+            .update_one(
+                doc! {"toolId": tool_id},
+                doc! {"$set": {"sortOrder": index as i32, "updatedAt": now}}
+            )
+            .await
+            .map_err(|e| format!("更新工具顺序失败: {}", e))?;
+        if result.matched_count == 0 {
+            return Err(format!("工具不存在: {}", tool_id));
+        }
+    }
+
+    log::info!("✅ 工具顺序已更新");
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn seed_catalog_is_valid() {
+        let tools: Vec<ToolInput> = serde_json::from_str(SEED_TOOLS).unwrap();
+        assert!(!tools.is_empty());
+
+        let mut ids = std::collections::HashSet::new();
+        for tool in &tools {
+            let id = tool.id.unwrap();
+            assert!(id > 0 && ids.insert(id), "工具ID {} 无效或重复", id);
+            validate_input(&tool.name, &tool.category, &tool.url).unwrap();
+        }
+    }
+}
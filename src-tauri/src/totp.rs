@@ -0,0 +1,628 @@
+use mongodb::{
+    bson::{doc, oid::ObjectId, DateTime},
+    options::IndexOptions,
+    IndexModel,
+};
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::time::Duration;
+use totp_rs::{Algorithm, TOTP};
+
+use crate::auth::{self, AppState, LoginResponse, MongoManager, User, UserResponse};
+use crate::guard::{current_caller, require, AuthError, Permission};
+use crate::activity::{self, ActivityEvent, LoginFailureReason};
+use crate::lockout;
+use crate::password::constant_time_eq;
+use crate::secret::Secret;
+
+// 验证器应用中显示的发行方名称
+const ISSUER: &str = "呈尚策划工具中心";
+const DIGITS: usize = 6;
+const STEP_SECS: u64 = 30;
+// 允许前后各一个时间步的时钟偏差
+const SKEW: u8 = 1;
+const SECRET_BYTES: usize = 20;
+const RECOVERY_CODE_COUNT: usize = 10;
+const RECOVERY_CODE_LEN: usize = 10;
+// 去掉易混淆的 0/o/1/l
+const RECOVERY_CODE_ALPHABET: &[u8] = b"abcdefghijkmnpqrstuvwxyz23456789";
+// 密码验证通过后提交动态验证码的有效期
+const CHALLENGE_TTL_SECS: u64 = 5 * 60;
+
+// 用户的两步验证配置（集合 user_totp，每个用户一条）
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct UserTotp {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    pub id: Option<ObjectId>,
+    #[serde(rename = "userId")]
+    pub user_id: ObjectId,
+    // Base32 编码的共享密钥
+    pub secret: Secret<String>,
+    // 首次验证码确认前为 false
+    pub enabled: bool,
+    // 恢复码的 SHA-256 哈希，使用后删除
+    #[serde(rename = "recoveryCodes", default)]
+    pub recovery_codes: Vec<Secret<String>>,
+    // 最近一次使用的时间步，防止验证码重放
+    #[serde(rename = "lastUsedStep", default, skip_serializing_if = "Option::is_none")]
+    pub last_used_step: Option<i64>,
+    #[serde(rename = "createdAt")]
+    pub created_at: DateTime,
+    #[serde(rename = "confirmedAt", default, skip_serializing_if = "Option::is_none")]
+    pub confirmed_at: Option<DateTime>,
+}
+
+// 密码验证通过、等待动态验证码的登录（集合 login_challenges）
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct LoginChallenge {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    pub id: Option<ObjectId>,
+    #[serde(rename = "challengeId")]
+    pub challenge_id: Secret<String>,
+    #[serde(rename = "userId")]
+    pub user_id: ObjectId,
+    #[serde(rename = "rememberMe")]
+    pub remember_me: bool,
+    #[serde(rename = "autoLogin")]
+    pub auto_login: bool,
+    #[serde(rename = "createdAt")]
+    pub created_at: DateTime,
+    #[serde(rename = "expiresAt")]
+    pub expires_at: DateTime,
+}
+
+// 开始绑定时返回给前端的信息
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct TotpEnrollment {
+    pub secret: Secret<String>,
+    #[serde(rename = "otpauthUri")]
+    pub otpauth_uri: Secret<String>,
+}
+
+// 两步验证状态
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct TotpStatus {
+    pub enabled: bool,
+    // 策略要求当前用户启用两步验证
+    pub required: bool,
+    #[serde(rename = "recoveryCodesRemaining")]
+    pub recovery_codes_remaining: usize,
+}
+
+pub async fn ensure_indexes(mongo: &MongoManager) -> Result<(), String> {
+    mongo.user_totp()
+        .create_index(
+            IndexModel::builder()
+                .keys(doc! {"userId": 1})
+                .options(IndexOptions::builder().unique(true).build())
+                .build(),
+        )
+        .await
+        .map_err(|e| format!("创建两步验证索引失败: {}", e))?;
+
+    // 过期的登录挑战由MongoDB自动清理
+    mongo.login_challenges()
+        .create_index(
+            IndexModel::builder()
+                .keys(doc! {"expiresAt": 1})
+                .options(IndexOptions::builder().expire_after(Duration::from_secs(0)).build())
+                .build(),
+        )
+        .await
+        .map_err(|e| format!("创建登录挑战索引失败: {}", e))?;
+    Ok(())
+}
+
+// 拥有高危权限的账号，受 require_admin_totp 策略约束
+pub fn is_privileged(user: &UserResponse) -> bool {
+    [
+        Permission::ManageUsers,
+        Permission::ManageRoles,
+        Permission::ManageSigningKeys,
+        Permission::ManageTestData,
+    ]
+    .iter()
+    .any(|p| user.has_permission(*p))
+}
+
+fn build_totp(secret: &[u8], username: &str) -> Result<TOTP, String> {
+    // 账号名中不允许出现冒号
+    TOTP::new(
+        Algorithm::SHA1,
+        DIGITS,
+        SKEW,
+        STEP_SECS,
+        secret.to_vec(),
+        Some(ISSUER.to_string()),
+        username.replace(':', "_"),
+    )
+    .map_err(|e| format!("生成两步验证配置失败: {}", e))
+}
+
+fn decode_secret(secret: &Secret<String>) -> Result<Vec<u8>, String> {
+    totp_rs::Secret::Encoded(secret.expose().clone())
+        .to_bytes()
+        .map_err(|e| format!("两步验证密钥无效: {:?}", e))
+}
+
+fn now_secs() -> u64 {
+    (DateTime::now().timestamp_millis() / 1000).max(0) as u64
+}
+
+// 在允许的时钟偏差内查找验证码对应的时间步
+fn matching_step(totp: &TOTP, code: &str, now: u64) -> Option<u64> {
+    let current = now / STEP_SECS;
+    let skew = SKEW as u64;
+    (current.saturating_sub(skew)..=current + skew)
+        .find(|step| constant_time_eq(totp.generate(step * STEP_SECS).as_bytes(), code.as_bytes()))
+}
+
+// 恢复码统一去掉分隔符并转为小写后再比较
+fn normalize_code(code: &str) -> String {
+    code.chars()
+        .filter(|c| !c.is_whitespace() && *c != '-')
+        .flat_map(|c| c.to_lowercase())
+        .collect()
+}
+
+fn hash_recovery_code(code: &str) -> String {
+    format!("{:x}", Sha256::digest(normalize_code(code).as_bytes()))
+}
+
+fn generate_recovery_codes() -> Vec<String> {
+    let mut rng = rand::thread_rng();
+    (0..RECOVERY_CODE_COUNT)
+        .map(|_| {
+            let code: String = (0..RECOVERY_CODE_LEN)
+                .map(|_| RECOVERY_CODE_ALPHABET[rng.gen_range(0..RECOVERY_CODE_ALPHABET.len())] as char)
+                .collect();
+            format!("{}-{}", &code[..RECOVERY_CODE_LEN / 2], &code[RECOVERY_CODE_LEN / 2..])
+        })
+        .collect()
+}
+
+async fn find_totp(mongo: &MongoManager, user_id: ObjectId) -> Result<Option<UserTotp>, String> {
+    mongo.user_totp()
+        .find_one(doc! {"userId": user_id})
+        .await
+        .map_err(|e| format!("查询两步验证配置失败: {}", e))
+}
+
+pub async fn is_enabled(mongo: &MongoManager, user_id: ObjectId) -> Result<bool, String> {
+    Ok(find_totp(mongo, user_id).await?.map(|t| t.enabled).unwrap_or(false))
+}
+
+// 校验动态验证码（同一时间步只能使用一次）
+async fn verify_code(
+    mongo: &MongoManager,
+    record: &UserTotp,
+    username: &str,
+    code: &str,
+) -> Result<bool, String> {
+    let totp = build_totp(&decode_secret(&record.secret)?, username)?;
+    let step = match matching_step(&totp, code, now_secs()) {
+        Some(step) => step as i64,
+        None => return Ok(false),
+    };
+
+    // 原子更新时间步，已用过的验证码不会匹配
+    let result = mongo.user_totp()
+        .update_one(
+            doc! {
+                "_id": record.id,
+                "$or": [
+                    {"lastUsedStep": {"$exists": false}},
+                    {"lastUsedStep": {"$lt": step}}
+                ]
+            },
+            doc! {"$set": {"lastUsedStep": step}},
+        )
+        .await
+        .map_err(|e| format!("更新两步验证状态失败: {}", e))?;
+    Ok(result.modified_count == 1)
+}
+
+// 校验第二因素：6位动态验证码或一次性恢复码
+pub async fn verify_second_factor(
+    mongo: &MongoManager,
+    user: &User,
+    code: &str,
+) -> Result<bool, String> {
+    let user_id = user.id.ok_or("用户ID缺失")?;
+    let record = match find_totp(mongo, user_id).await? {
+        Some(record) if record.enabled => record,
+        _ => return Ok(false),
+    };
+
+    let code = code.trim();
+    if code.len() == DIGITS && code.chars().all(|c| c.is_ascii_digit()) {
+        return verify_code(mongo, &record, &user.username, code).await;
+    }
+
+    // 恢复码使用后立即作废
+    let hash = hash_recovery_code(code);
+    let result = mongo.user_totp()
+        .update_one(
+            doc! {"_id": record.id, "recoveryCodes": &hash},
+            doc! {"$pull": {"recoveryCodes": &hash}},
+        )
+        .await
+        .map_err(|e| format!("更新恢复码失败: {}", e))?;
+    if result.modified_count == 1 {
+        log::warn!("🔑 用户 {} 使用了恢复码登录，剩余 {} 个", user.username, record.recovery_codes.len().saturating_sub(1));
+        return Ok(true);
+    }
+    Ok(false)
+}
+
+// 密码验证通过后创建登录挑战，返回挑战ID
+pub async fn create_challenge(
+    mongo: &MongoManager,
+    user: &User,
+    remember_me: bool,
+    auto_login: bool,
+) -> Result<String, String> {
+    let challenge_id = uuid::Uuid::new_v4().to_string();
+    let now = DateTime::now();
+    let challenge = LoginChallenge {
+        id: None,
+        challenge_id: Secret::new(challenge_id.clone()),
+        user_id: user.id.ok_or("用户ID缺失")?,
+        remember_me,
+        auto_login,
+        created_at: now,
+        expires_at: DateTime::from_millis(now.timestamp_millis() + (CHALLENGE_TTL_SECS * 1000) as i64),
+    };
+
+    mongo.login_challenges()
+        .insert_one(challenge)
+        .await
+        .map_err(|e| format!("创建登录挑战失败: {}", e))?;
+    Ok(challenge_id)
+}
+
+// 登录第二步：提交动态验证码或恢复码完成登录
+#[tauri::command]
+pub async fn verify_totp(
+    challenge_id: Secret<String>,
+    code: Secret<String>,
+    state: tauri::State<'_, AppState>,
+) -> Result<LoginResponse, String> {
+    let mongo = state.mongo.read().await;
+
+    let challenge = mongo.login_challenges()
+        .find_one(doc! {
+            "challengeId": challenge_id.expose(),
+            "expiresAt": {"$gt": DateTime::now()}
+        })
+        .await
+        .map_err(|e| format!("查询登录挑战失败: {}", e))?
+        .ok_or("验证已过期，请重新登录")?;
+
+    let user = mongo.users()
+        .find_one(doc! {"_id": challenge.user_id})
+        .await
+        .map_err(|e| format!("查询用户失败: {}", e))?
+        .ok_or("用户不存在")?;
+
+    if !user.is_active {
+        return Err("账号已被禁用，请联系管理员".to_string());
+    }
+
+    // 验证码错误同样计入登录失败次数
+    let lockout_config = &state.config.lockout;
+    lockout::ensure_can_attempt(&mongo, lockout_config, &user.username, &state.device.device_id).await?;
+
+    if !verify_second_factor(&mongo, &user, code.expose()).await? {
+        log::warn!("❌ 两步验证失败: 用户={}", user.username);
+        activity::record(&state, user.id, ActivityEvent::LoginFailed {
+            username: user.username.clone(),
+            reason: LoginFailureReason::InvalidSecondFactor,
+        }).await;
+        lockout::record_failure(&mongo, lockout_config, &user.username, &state.device.device_id).await?;
+        return Err("验证码错误".to_string());
+    }
+
+    mongo.login_challenges()
+        .delete_one(doc! {"_id": challenge.id})
+        .await
+        .map_err(|e| format!("删除登录挑战失败: {}", e))?;
+
+    log::info!("✅ 两步验证通过: 用户={}", user.username);
+    auth::complete_login(&state, &mongo, user, challenge.remember_me, challenge.auto_login).await
+}
+
+// 查询当前用户的两步验证状态
+#[tauri::command]
+pub async fn get_totp_status(
+    state: tauri::State<'_, AppState>,
+) -> Result<TotpStatus, String> {
+    let current_user = current_caller(&state).await?;
+    let user_id = ObjectId::parse_str(&current_user.id).map_err(|e| format!("用户ID解析失败: {}", e))?;
+
+    let mongo = state.mongo.read().await;
+    let record = find_totp(&mongo, user_id).await?.filter(|t| t.enabled);
+
+    Ok(TotpStatus {
+        enabled: record.is_some(),
+        required: state.config.auth.require_admin_totp && is_privileged(&current_user),
+        recovery_codes_remaining: record.map(|t| t.recovery_codes.len()).unwrap_or(0),
+    })
+}
+
+// 开始绑定两步验证：生成密钥和 otpauth 链接，需再提交一次验证码确认
+#[tauri::command]
+pub async fn begin_totp_enrollment(
+    state: tauri::State<'_, AppState>,
+) -> Result<TotpEnrollment, String> {
+    let current_user = current_caller(&state).await?;
+    let user_id = ObjectId::parse_str(&current_user.id).map_err(|e| format!("用户ID解析失败: {}", e))?;
+
+    let mongo = state.mongo.read().await;
+    if is_enabled(&mongo, user_id).await? {
+        return Err("已启用两步验证，如需更换请先停用".to_string());
+    }
+
+    let mut secret = vec![0u8; SECRET_BYTES];
+    rand::thread_rng().fill(&mut secret[..]);
+    let totp = build_totp(&secret, &current_user.username)?;
+
+    let record = UserTotp {
+        id: None,
+        user_id,
+        secret: Secret::new(totp.get_secret_base32()),
+        enabled: false,
+        recovery_codes: Vec::new(),
+        last_used_step: None,
+        created_at: DateTime::now(),
+        confirmed_at: None,
+    };
+
+    mongo.user_totp()
+        .replace_one(doc! {"userId": user_id}, &record)
+        .upsert(true)
+        .await
+        .map_err(|e| format!("保存两步验证配置失败: {}", e))?;
+
+    log::info!("🔐 用户 {} 开始绑定两步验证", current_user.username);
+    Ok(TotpEnrollment {
+        secret: record.secret,
+        otpauth_uri: Secret::new(totp.get_url()),
+    })
+}
+
+// 确认绑定：校验第一个验证码后启用，返回只显示一次的恢复码
+#[tauri::command]
+pub async fn confirm_totp_enrollment(
+    code: Secret<String>,
+    state: tauri::State<'_, AppState>,
+) -> Result<Vec<Secret<String>>, String> {
+    let current_user = current_caller(&state).await?;
+    let user_id = ObjectId::parse_str(&current_user.id).map_err(|e| format!("用户ID解析失败: {}", e))?;
+
+    let mongo = state.mongo.read().await;
+    let record = find_totp(&mongo, user_id)
+        .await?
+        .ok_or("请先开始绑定两步验证")?;
+    if record.enabled {
+        return Err("已启用两步验证".to_string());
+    }
+
+    if !verify_code(&mongo, &record, &current_user.username, code.expose().trim()).await? {
+        return Err("验证码错误".to_string());
+    }
+
+    let recovery_codes = generate_recovery_codes();
+    let hashes: Vec<String> = recovery_codes.iter().map(|c| hash_recovery_code(c)).collect();
+
+    mongo.user_totp()
+        .update_one(
+            doc! {"_id": record.id},
+            doc! {"$set": {
+                "enabled": true,
+                "recoveryCodes": hashes,
+                "confirmedAt": DateTime::now()
+            }},
+        )
+        .await
+        .map_err(|e| format!("启用两步验证失败: {}", e))?;
+
+    log::info!("✅ 用户 {} 已启用两步验证", current_user.username);
+    Ok(recovery_codes.into_iter().map(Secret::new).collect())
+}
+
+// 停用两步验证，需要提供有效的验证码或恢复码
+#[tauri::command]
+pub async fn disable_totp(
+    code: Secret<String>,
+    state: tauri::State<'_, AppState>,
+) -> Result<(), String> {
+    let current_user = current_caller(&state).await?;
+    if state.config.auth.require_admin_totp && is_privileged(&current_user) {
+        return Err(AuthError::TotpRequired.into());
+    }
+    let user_id = ObjectId::parse_str(&current_user.id).map_err(|e| format!("用户ID解析失败: {}", e))?;
+
+    let mongo = state.mongo.read().await;
+    let user = mongo.users()
+        .find_one(doc! {"_id": user_id})
+        .await
+        .map_err(|e| format!("查询用户失败: {}", e))?
+        .ok_or("用户不存在")?;
+
+    if !verify_second_factor(&mongo, &user, code.expose()).await? {
+        return Err("验证码错误".to_string());
+    }
+
+    mongo.user_totp()
+        .delete_one(doc! {"userId": user_id})
+        .await
+        .map_err(|e| format!("停用两步验证失败: {}", e))?;
+
+    log::info!("🔓 用户 {} 已停用两步验证", current_user.username);
+    Ok(())
+}
+
+// 重置用户的两步验证（用户丢失设备时使用）- 管理员功能
+#[tauri::command]
+pub async fn reset_user_totp(
+    user_id: String,
+    state: tauri::State<'_, AppState>,
+) -> Result<(), String> {
+    // 重置后账号只剩密码保护，因此仅限全局用户管理员操作
+    let current_user = require(&state, Permission::ManageUsers).await?;
+
+    let user_object_id = ObjectId::parse_str(&user_id)
+        .map_err(|e| format!("无效的用户ID: {}", e))?;
+    if current_user.id == user_id {
+        return Err("不能重置自己的两步验证".to_string());
+    }
+
+    let mongo = state.mongo.read().await;
+    let target = mongo.users()
+        .find_one(doc! {"_id": user_object_id})
+        .await
+        .map_err(|e| format!("查询用户失败: {}", e))?
+        .ok_or("用户不存在")?;
+
+    mongo.user_totp()
+        .delete_one(doc! {"userId": user_object_id})
+        .await
+        .map_err(|e| format!("重置两步验证失败: {}", e))?;
+
+    log::info!("🔓 用户 {} 的两步验证已被 {} 重置", target.username, current_user.username);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rbac::{self, ROLE_ADMIN, ROLE_DEPARTMENT_LEAD, ROLE_USER};
+    use crate::test_support::TestDb;
+
+    const SECRET: [u8; SECRET_BYTES] = [7; SECRET_BYTES];
+
+    fn totp() -> TOTP {
+        build_totp(&SECRET, "alice").unwrap()
+    }
+
+    fn caller(permissions: Vec<Permission>) -> UserResponse {
+        UserResponse {
+            id: ObjectId::new().to_hex(),
+            username: "alice".to_string(),
+            role: ROLE_USER.to_string(),
+            roles: vec![ROLE_USER.to_string()],
+            department: None,
+            permissions,
+            is_active: true,
+            created_at: String::new(),
+            last_login_at: None,
+            total_usage_time: 0,
+            login_count: 0,
+        }
+    }
+
+    #[test]
+    fn matching_step_allows_one_step_of_clock_skew() {
+        let totp = totp();
+        let now = 1_000 * STEP_SECS + 7;
+        for step in [999, 1_000, 1_001] {
+            assert_eq!(matching_step(&totp, &totp.generate(step * STEP_SECS), now), Some(step));
+        }
+        for step in [998, 1_002] {
+            assert_eq!(matching_step(&totp, &totp.generate(step * STEP_SECS), now), None);
+        }
+        assert_eq!(matching_step(&totp, "12345", now), None);
+    }
+
+    #[test]
+    fn recovery_codes_ignore_case_and_separators() {
+        assert_eq!(normalize_code(" ABCDE-fghij "), "abcdefghij");
+        assert_eq!(normalize_code("abcde fghij"), "abcdefghij");
+        assert_eq!(hash_recovery_code("ABCDE-FGHIJ"), hash_recovery_code("abcdefghij"));
+        assert_ne!(hash_recovery_code("abcde-fghij"), hash_recovery_code("abcde-fghik"));
+
+        let codes = generate_recovery_codes();
+        assert_eq!(codes.len(), RECOVERY_CODE_COUNT);
+        for code in &codes {
+            let (left, right) = code.split_once('-').unwrap();
+            assert_eq!((left.len(), right.len()), (RECOVERY_CODE_LEN / 2, RECOVERY_CODE_LEN / 2));
+            assert!(normalize_code(code).bytes().all(|b| RECOVERY_CODE_ALPHABET.contains(&b)), "{}", code);
+        }
+    }
+
+    #[test]
+    fn privileged_accounts_are_those_that_manage_users_roles_keys_or_test_data() {
+        let builtin = |role: &str| {
+            rbac::builtin_roles()
+                .into_iter()
+                .find(|(name, ..)| *name == role)
+                .map(|(.., permissions)| permissions)
+                .unwrap()
+        };
+        assert!(is_privileged(&caller(builtin(ROLE_ADMIN))));
+        assert!(!is_privileged(&caller(builtin(ROLE_DEPARTMENT_LEAD))));
+        assert!(!is_privileged(&caller(builtin(ROLE_USER))));
+
+        for permission in Permission::ALL {
+            let expected = matches!(
+                permission,
+                Permission::ManageUsers
+                    | Permission::ManageRoles
+                    | Permission::ManageSigningKeys
+                    | Permission::ManageTestData
+            );
+            assert_eq!(is_privileged(&caller(vec![permission])), expected, "{:?}", permission);
+        }
+    }
+
+    #[tokio::test]
+    #[ignore = "需要本机 mongod"]
+    async fn codes_and_recovery_codes_are_single_use() {
+        let db = TestDb::new("totp_replay").await;
+        let user = User {
+            id: Some(ObjectId::new()),
+            username: "alice".to_string(),
+            password: Secret::new(String::new()),
+            password_history: Vec::new(),
+            role: ROLE_USER.to_string(),
+            roles: vec![ROLE_USER.to_string()],
+            department: None,
+            is_active: true,
+            created_at: DateTime::now(),
+            last_login_at: None,
+            total_usage_time: 0,
+            login_count: 0,
+        };
+        let totp = totp();
+        db.mongo.user_totp()
+            .insert_one(UserTotp {
+                id: None,
+                user_id: user.id.unwrap(),
+                secret: Secret::new(totp.get_secret_base32()),
+                enabled: true,
+                recovery_codes: vec![Secret::new(hash_recovery_code("abcde-fghij"))],
+                last_used_step: None,
+                created_at: DateTime::now(),
+                confirmed_at: Some(DateTime::now()),
+            })
+            .await
+            .unwrap();
+
+        // 同一时间步的验证码只能用一次，之前时间步的验证码随之失效
+        let now = now_secs();
+        let code = totp.generate(now);
+        assert!(verify_second_factor(&db.mongo, &user, &code).await.unwrap());
+        assert!(!verify_second_factor(&db.mongo, &user, &code).await.unwrap());
+        let previous = totp.generate(now - STEP_SECS);
+        assert!(!verify_second_factor(&db.mongo, &user, &previous).await.unwrap());
+
+        assert!(verify_second_factor(&db.mongo, &user, "ABCDE FGHIJ").await.unwrap());
+        assert!(!verify_second_factor(&db.mongo, &user, "abcde-fghij").await.unwrap());
+        let record = find_totp(&db.mongo, user.id.unwrap()).await.unwrap().unwrap();
+        assert!(record.recovery_codes.is_empty());
+
+        db.drop().await;
+    }
+}
@@ -4,11 +4,9 @@ export interface Tool {
   id: number
   name: string
   description: string
-  category: "运营工具" | "美工工具" | "销售工具" | "人事工具" | "客服工具"
+  category: string
   url: string
   icon: LucideIcon
-  rating: number
-  downloads: string
   tags: string[]
   color: string
   featured: boolean